@@ -4,7 +4,7 @@ use criterion::{black_box, criterion_group, criterion_main, Criterion, Benchmark
 use skp_ratelimit::{
     algorithm::{Algorithm, FixedWindow, SlidingWindow, TokenBucket},
     storage::MemoryStorage,
-    LeakyBucket, Quota, SlidingLog, GCRA,
+    AtomicGCRA, LeakyBucket, Quota, SlidingLog, GCRA,
 };
 use tokio::runtime::Runtime;
 
@@ -138,5 +138,42 @@ fn bench_algorithm_comparison(c: &mut Criterion) {
     group.finish();
 }
 
-criterion_group!(benches, bench_algorithms, bench_algorithm_comparison);
+fn bench_gcra_fast_path(c: &mut Criterion) {
+    let rt = Runtime::new().unwrap();
+    let quota = Quota::per_second(10000).with_burst(100);
+    let keys: Vec<String> = (0..1000).map(|i| format!("k:{}", i)).collect();
+
+    let mut group = c.benchmark_group("gcra_fast_path");
+
+    group.bench_function("gcra_memory_storage", |b| {
+        let storage = MemoryStorage::new();
+        let algorithm = GCRA::new();
+        let mut i = 0usize;
+        b.iter(|| {
+            i += 1;
+            let key = &keys[i % keys.len()];
+            rt.block_on(async {
+                black_box(algorithm.check_and_record(&storage, key, &quota).await)
+            })
+        })
+    });
+
+    group.bench_function("atomic_gcra", |b| {
+        let limiter = AtomicGCRA::new(quota.clone());
+        let mut i = 0usize;
+        b.iter(|| {
+            i += 1;
+            black_box(limiter.check_and_record(&keys[i % keys.len()]))
+        })
+    });
+
+    group.finish();
+}
+
+criterion_group!(
+    benches,
+    bench_algorithms,
+    bench_algorithm_comparison,
+    bench_gcra_fast_path
+);
 criterion_main!(benches);
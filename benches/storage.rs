@@ -1,7 +1,7 @@
 //! Benchmarks for storage operations.
 
 use criterion::{black_box, criterion_group, criterion_main, Criterion, BenchmarkId};
-use skp_ratelimit::storage::{MemoryStorage, Storage, StorageEntry};
+use skp_ratelimit::storage::{EntryCodec, MemoryStorage, Storage, StorageEntry};
 use std::time::Duration;
 use tokio::runtime::Runtime;
 
@@ -143,5 +143,49 @@ fn bench_concurrent_access(c: &mut Criterion) {
     group.finish();
 }
 
-criterion_group!(benches, bench_storage_operations, bench_storage_scaling, bench_concurrent_access);
+fn bench_entry_codec(c: &mut Criterion) {
+    let mut group = c.benchmark_group("entry_codec");
+
+    let entries = [
+        ("gcra", StorageEntry::with_tat(1_700_000_000_100)),
+        ("token_bucket", StorageEntry::with_tokens(42.5, 1_700_000_000_000)),
+        (
+            "sliding_log_100",
+            StorageEntry::with_timestamps((0..100).map(|i| 1_700_000_000_000 + i * 13).collect()),
+        ),
+        (
+            "sliding_log_10000",
+            StorageEntry::with_timestamps((0..10_000).map(|i| 1_700_000_000_000 + i * 13).collect()),
+        ),
+    ];
+
+    for (name, entry) in entries.iter() {
+        for codec in [EntryCodec::Json, EntryCodec::Binary] {
+            let codec_name = format!("{:?}", codec).to_lowercase();
+            let encoded = codec.encode(entry).unwrap();
+
+            group.bench_with_input(
+                BenchmarkId::new(format!("encode_{}", codec_name), name),
+                entry,
+                |b, entry| b.iter(|| black_box(codec.encode(entry).unwrap())),
+            );
+
+            group.bench_with_input(
+                BenchmarkId::new(format!("decode_{}", codec_name), name),
+                &encoded,
+                |b, encoded| b.iter(|| black_box(EntryCodec::decode(encoded).unwrap())),
+            );
+        }
+    }
+
+    group.finish();
+}
+
+criterion_group!(
+    benches,
+    bench_storage_operations,
+    bench_storage_scaling,
+    bench_concurrent_access,
+    bench_entry_codec
+);
 criterion_main!(benches);
@@ -0,0 +1,304 @@
+//! Adaptive limiting driven by backend health.
+//!
+//! An `AdaptiveController` watches the responses behind a rate limiter and
+//! scales its quota with AIMD (additive increase, multiplicative decrease):
+//! when a window of responses is slow or failing, the multiplier is cut by a
+//! factor; otherwise it grows by a fixed step, within a floor and ceiling.
+//!
+//! # Example
+//!
+//! ```ignore
+//! use skp_ratelimit::adaptive::{AdaptiveConfig, AdaptiveController};
+//!
+//! let controller = AdaptiveController::new(
+//!     AdaptiveConfig::new()
+//!         .with_latency_target(Duration::from_millis(300))
+//!         .with_bounds(0.2, 1.0),
+//! );
+//!
+//! let layer = RateLimitLayer::new(storage, GCRA::new(), Quota::per_second(100), IpKey::new())
+//!     .with_adaptive(controller.clone());
+//!
+//! // Export for metrics
+//! gauge!("ratelimit_multiplier").set(controller.multiplier());
+//! ```
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use parking_lot::Mutex;
+use tokio::time::Instant;
+
+use crate::quota::Quota;
+
+/// Configuration for an [`AdaptiveController`].
+#[derive(Debug, Clone)]
+pub struct AdaptiveConfig {
+    /// Lowest multiplier (floor).
+    pub min_multiplier: f64,
+    /// Highest multiplier (ceiling), also the starting one.
+    pub max_multiplier: f64,
+    /// Added to the multiplier after a healthy window.
+    pub increase: f64,
+    /// Factor the multiplier is cut by after an unhealthy window.
+    pub decrease: f64,
+    /// Mean response latency above which a window is unhealthy.
+    pub latency_target: Duration,
+    /// Share of 5xx responses above which a window is unhealthy.
+    pub max_error_rate: f64,
+    /// Length of the window responses are averaged over.
+    pub window: Duration,
+    /// Minimum responses in a window before adjusting.
+    pub min_samples: u64,
+}
+
+impl Default for AdaptiveConfig {
+    fn default() -> Self {
+        Self {
+            min_multiplier: 0.1,
+            max_multiplier: 1.0,
+            increase: 0.05,
+            decrease: 0.5,
+            latency_target: Duration::from_millis(500),
+            max_error_rate: 0.05,
+            window: Duration::from_secs(10),
+            min_samples: 10,
+        }
+    }
+}
+
+impl AdaptiveConfig {
+    /// Create a config with the defaults.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set the multiplier floor and ceiling.
+    ///
+    /// Debug builds panic unless `0 < min <= max`.
+    pub fn with_bounds(mut self, min: f64, max: f64) -> Self {
+        self.min_multiplier = min;
+        self.max_multiplier = max;
+        self.debug_validate();
+        self
+    }
+
+    /// Set the additive increase step and multiplicative decrease factor.
+    ///
+    /// Debug builds panic unless `increase >= 0` and `0 < decrease < 1`.
+    pub fn with_aimd(mut self, increase: f64, decrease: f64) -> Self {
+        self.increase = increase;
+        self.decrease = decrease;
+        self.debug_validate();
+        self
+    }
+
+    /// Set the mean latency above which a window is unhealthy.
+    pub fn with_latency_target(mut self, target: Duration) -> Self {
+        self.latency_target = target;
+        self
+    }
+
+    /// Set the 5xx rate above which a window is unhealthy.
+    pub fn with_max_error_rate(mut self, rate: f64) -> Self {
+        self.max_error_rate = rate;
+        self
+    }
+
+    /// Set the smoothing window and the responses it needs.
+    pub fn with_window(mut self, window: Duration, min_samples: u64) -> Self {
+        self.window = window;
+        self.min_samples = min_samples;
+        self
+    }
+
+    fn debug_validate(&self) {
+        debug_assert!(
+            0.0 < self.min_multiplier && self.min_multiplier <= self.max_multiplier,
+            "adaptive bounds must satisfy 0 < min <= max, got {}..{}",
+            self.min_multiplier,
+            self.max_multiplier
+        );
+        debug_assert!(self.increase >= 0.0, "adaptive increase must be >= 0, got {}", self.increase);
+        debug_assert!(
+            0.0 < self.decrease && self.decrease < 1.0,
+            "adaptive decrease must be in (0, 1), got {}",
+            self.decrease
+        );
+    }
+}
+
+/// Responses observed in the current window.
+struct State {
+    multiplier: f64,
+    window_start: Instant,
+    samples: u64,
+    errors: u64,
+    latency: Duration,
+}
+
+/// Scales a quota with AIMD based on observed latency and 5xx rate.
+///
+/// Clones share state, so keep one to read [`multiplier`](Self::multiplier)
+/// for metrics. State is per process.
+#[derive(Clone)]
+pub struct AdaptiveController {
+    config: Arc<AdaptiveConfig>,
+    state: Arc<Mutex<State>>,
+}
+
+impl std::fmt::Debug for AdaptiveController {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("AdaptiveController")
+            .field("config", &self.config)
+            .field("multiplier", &self.multiplier())
+            .finish()
+    }
+}
+
+impl AdaptiveController {
+    /// Create a controller starting at the ceiling.
+    ///
+    /// Debug builds panic on invalid bounds or AIMD factors (see
+    /// [`AdaptiveConfig::with_bounds`] and [`AdaptiveConfig::with_aimd`]).
+    pub fn new(config: AdaptiveConfig) -> Self {
+        config.debug_validate();
+        let state = State {
+            multiplier: config.max_multiplier,
+            window_start: Instant::now(),
+            samples: 0,
+            errors: 0,
+            latency: Duration::ZERO,
+        };
+        Self {
+            config: Arc::new(config),
+            state: Arc::new(Mutex::new(state)),
+        }
+    }
+
+    /// Get the current multiplier.
+    pub fn multiplier(&self) -> f64 {
+        self.state.lock().multiplier
+    }
+
+    /// Get `base` scaled by the current multiplier.
+    pub fn quota(&self, base: &Quota) -> Quota {
+        base.scaled(self.multiplier())
+    }
+
+    /// Record a backend response.
+    ///
+    /// Once the window has elapsed with at least `min_samples` responses,
+    /// the multiplier is adjusted and a new window starts.
+    pub fn record(&self, status: u16, latency: Duration) {
+        let config = &self.config;
+        let mut state = self.state.lock();
+
+        state.samples += 1;
+        state.errors += u64::from((500..=599).contains(&status));
+        state.latency += latency;
+
+        if state.window_start.elapsed() < config.window || state.samples < config.min_samples {
+            return;
+        }
+
+        let error_rate = state.errors as f64 / state.samples as f64;
+        let mean_latency = Duration::from_secs_f64(state.latency.as_secs_f64() / state.samples as f64);
+        let healthy = error_rate <= config.max_error_rate && mean_latency <= config.latency_target;
+
+        let multiplier = if healthy {
+            state.multiplier + config.increase
+        } else {
+            state.multiplier * config.decrease
+        };
+        state.multiplier = multiplier.min(config.max_multiplier).max(config.min_multiplier);
+
+        tracing::debug!(
+            multiplier = state.multiplier,
+            error_rate,
+            mean_latency_ms = mean_latency.as_millis() as u64,
+            "adaptive limit adjusted"
+        );
+
+        state.window_start = Instant::now();
+        state.samples = 0;
+        state.errors = 0;
+        state.latency = Duration::ZERO;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn controller() -> AdaptiveController {
+        AdaptiveController::new(
+            AdaptiveConfig::new()
+                .with_bounds(0.2, 1.0)
+                .with_aimd(0.1, 0.5)
+                .with_window(Duration::from_secs(1), 2),
+        )
+    }
+
+    fn window(controller: &AdaptiveController, status: u16, latency_ms: u64) {
+        controller.record(status, Duration::from_millis(latency_ms));
+        controller.record(status, Duration::from_millis(latency_ms));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_aimd() {
+        let controller = controller();
+        let quota = Quota::per_second(100);
+
+        // Windows must elapse before adjusting
+        window(&controller, 500, 10);
+        assert_eq!(controller.multiplier(), 1.0);
+
+        tokio::time::advance(Duration::from_secs(1)).await;
+        window(&controller, 500, 10);
+        assert_eq!(controller.multiplier(), 0.5);
+        assert_eq!(controller.quota(&quota).max_requests(), 50);
+
+        // Slow responses count too, down to the floor
+        for _ in 0..3 {
+            tokio::time::advance(Duration::from_secs(1)).await;
+            window(&controller, 200, 900);
+        }
+        assert_eq!(controller.multiplier(), 0.2);
+
+        // Healthy windows recover additively, up to the ceiling
+        tokio::time::advance(Duration::from_secs(1)).await;
+        window(&controller, 200, 10);
+        assert!((controller.multiplier() - 0.3).abs() < 1e-9);
+        for _ in 0..10 {
+            tokio::time::advance(Duration::from_secs(1)).await;
+            window(&controller, 200, 10);
+        }
+        assert_eq!(controller.multiplier(), 1.0);
+    }
+
+    #[test]
+    #[cfg(debug_assertions)]
+    #[should_panic(expected = "0 < min <= max")]
+    fn test_inverted_bounds() {
+        let _ = AdaptiveConfig::new().with_bounds(1.0, 0.5);
+    }
+
+    #[test]
+    #[cfg(debug_assertions)]
+    #[should_panic(expected = "decrease must be in (0, 1)")]
+    fn test_decrease_out_of_range() {
+        let _ = AdaptiveConfig::new().with_aimd(0.1, 1.5);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_min_samples() {
+        let controller = controller();
+
+        tokio::time::advance(Duration::from_secs(5)).await;
+        controller.record(503, Duration::ZERO);
+        assert_eq!(controller.multiplier(), 1.0);
+        controller.record(503, Duration::ZERO);
+        assert_eq!(controller.multiplier(), 0.5);
+    }
+}
@@ -0,0 +1,246 @@
+//! Lock-free in-memory GCRA.
+//!
+//! A specialization of [`GCRA`](super::GCRA) for single-node services. Each
+//! key's TAT lives in an `AtomicU64` and is updated with a compare-and-swap
+//! loop, so deciding for an existing key takes a shared map read and a few
+//! atomic operations: no `StorageEntry` clone, no closure, no allocation.
+
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::Arc;
+
+use dashmap::DashMap;
+
+use crate::algorithm::{current_timestamp_ms, GCRA};
+use crate::decision::Decision;
+use crate::quota::Quota;
+use crate::storage::primitives::gcra_step;
+
+/// New keys inserted between automatic idle-key sweeps.
+const GC_EVERY_INSERTS: usize = 10_000;
+
+/// Lock-free in-memory GCRA limiter.
+///
+/// Produces the same decisions as [`GCRA`] with a `MemoryStorage`, but
+/// holds its own state and is synchronous. The quota is fixed at
+/// construction; its warm-up, if any, is ignored (keys get the full quota
+/// from the start).
+///
+/// Keys whose TAT has fallen behind the clock carry no information (they
+/// behave exactly like unseen keys) and are swept periodically as new keys
+/// are added, or on demand with [`run_gc`](Self::run_gc).
+///
+/// # Example
+///
+/// ```ignore
+/// use skp_ratelimit::{AtomicGCRA, Quota};
+///
+/// let limiter = AtomicGCRA::new(Quota::per_second(10).with_burst(15));
+///
+/// let decision = limiter.check_and_record("user:123");
+/// ```
+#[derive(Clone)]
+pub struct AtomicGCRA {
+    quota: Quota,
+    period_ms: u64,
+    tolerance_ms: u64,
+    cells: Arc<DashMap<Box<str>, AtomicU64>>,
+    inserts: Arc<AtomicUsize>,
+}
+
+impl std::fmt::Debug for AtomicGCRA {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("AtomicGCRA")
+            .field("quota", &self.quota)
+            .field("active_keys", &self.cells.len())
+            .finish()
+    }
+}
+
+impl AtomicGCRA {
+    /// Create a new limiter for `quota`.
+    ///
+    /// A warm-up on `quota` is ignored, with a warning.
+    pub fn new(quota: Quota) -> Self {
+        if quota.warmup().is_some() {
+            tracing::warn!("AtomicGCRA ignores quota warm-up; keys get the full quota immediately");
+        }
+        Self {
+            period_ms: quota.period().as_millis() as u64,
+            tolerance_ms: quota.max_tat_offset().as_millis() as u64,
+            quota,
+            cells: Arc::new(DashMap::new()),
+            inserts: Arc::new(AtomicUsize::new(0)),
+        }
+    }
+
+    /// The quota enforced by this limiter.
+    pub fn quota(&self) -> &Quota {
+        &self.quota
+    }
+
+    /// Check whether a request for `key` is allowed and record it if so.
+    pub fn check_and_record(&self, key: &str) -> Decision {
+        let now = current_timestamp_ms();
+
+        let update = match self.cells.get(key) {
+            Some(cell) => self.update(&cell, now),
+            None => {
+                let update = {
+                    let cell = self.cells.entry(key.into()).or_insert_with(|| AtomicU64::new(0));
+                    self.update(&cell, now)
+                };
+                self.maybe_run_gc();
+                update
+            }
+        };
+
+        let info = GCRA::build_info(update.tat, now, &self.quota, update.allowed);
+        if update.allowed {
+            Decision::allowed(info)
+        } else {
+            Decision::denied(info)
+        }
+    }
+
+    /// Check whether a request for `key` would be allowed, without recording it.
+    pub fn check(&self, key: &str) -> Decision {
+        let now = current_timestamp_ms();
+        let current = self.cells.get(key).map(|cell| cell.load(Ordering::Acquire));
+
+        let update = gcra_step(current, now, self.period_ms, self.tolerance_ms);
+        let info = GCRA::build_info(update.tat, now, &self.quota, update.allowed);
+        if update.allowed {
+            Decision::allowed(info)
+        } else {
+            Decision::denied(info)
+        }
+    }
+
+    /// Forget the state of `key`.
+    pub fn reset(&self, key: &str) {
+        self.cells.remove(key);
+    }
+
+    /// Number of keys currently tracked.
+    pub fn len(&self) -> usize {
+        self.cells.len()
+    }
+
+    /// Check if no keys are tracked.
+    pub fn is_empty(&self) -> bool {
+        self.cells.is_empty()
+    }
+
+    /// Drop keys whose TAT is in the past.
+    pub fn run_gc(&self) {
+        let now = current_timestamp_ms();
+        self.cells.retain(|_, tat| tat.load(Ordering::Acquire) > now);
+    }
+
+    /// Apply one GCRA step to `cell` with a CAS loop.
+    fn update(&self, cell: &AtomicU64, now: u64) -> crate::storage::TatUpdate {
+        let mut current = cell.load(Ordering::Acquire);
+        loop {
+            // A zero TAT (fresh cell) behaves like a missing key
+            let update = gcra_step(Some(current), now, self.period_ms, self.tolerance_ms);
+            if !update.allowed {
+                return update;
+            }
+
+            match cell.compare_exchange_weak(current, update.tat, Ordering::AcqRel, Ordering::Acquire) {
+                Ok(_) => return update,
+                Err(actual) => current = actual,
+            }
+        }
+    }
+
+    fn maybe_run_gc(&self) {
+        let inserts = self.inserts.fetch_add(1, Ordering::Relaxed) + 1;
+        if inserts.is_multiple_of(GC_EVERY_INSERTS) {
+            self.run_gc();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::algorithm::Algorithm;
+    use crate::storage::MemoryStorage;
+
+    #[test]
+    fn test_atomic_gcra_burst() {
+        let limiter = AtomicGCRA::new(Quota::per_second(1).with_burst(5));
+
+        for i in 1..=5 {
+            let decision = limiter.check_and_record("user:1");
+            assert!(decision.is_allowed(), "Request {} should be allowed", i);
+        }
+
+        let decision = limiter.check_and_record("user:1");
+        assert!(decision.is_denied());
+        assert!(decision.info().retry_after.is_some());
+
+        // Other keys are independent
+        assert!(limiter.check_and_record("user:2").is_allowed());
+    }
+
+    #[tokio::test]
+    async fn test_atomic_gcra_matches_gcra() {
+        let quota = Quota::per_second(10).with_burst(3);
+        let limiter = AtomicGCRA::new(quota.clone());
+        let storage = MemoryStorage::new();
+        let algorithm = GCRA::new();
+
+        for _ in 0..6 {
+            let fast = limiter.check_and_record("key");
+            let slow = algorithm.check_and_record(&storage, "key", &quota).await.unwrap();
+            assert_eq!(fast.is_allowed(), slow.is_allowed());
+            assert_eq!(fast.info().remaining, slow.info().remaining);
+        }
+    }
+
+    #[test]
+    fn test_atomic_gcra_check_does_not_record() {
+        let limiter = AtomicGCRA::new(Quota::per_second(1).with_burst(1));
+
+        assert!(limiter.check("key").is_allowed());
+        assert!(limiter.check("key").is_allowed());
+        assert!(limiter.check_and_record("key").is_allowed());
+        assert!(limiter.check("key").is_denied());
+
+        limiter.reset("key");
+        assert!(limiter.check("key").is_allowed());
+    }
+
+    #[test]
+    fn test_atomic_gcra_concurrent() {
+        let limiter = AtomicGCRA::new(Quota::per_minute(1).with_burst(100));
+
+        let allowed: usize = std::thread::scope(|scope| {
+            let handles: Vec<_> = (0..8)
+                .map(|_| {
+                    scope.spawn(|| {
+                        (0..50)
+                            .filter(|_| limiter.check_and_record("shared").is_allowed())
+                            .count()
+                    })
+                })
+                .collect();
+            handles.into_iter().map(|h| h.join().unwrap()).sum()
+        });
+
+        assert_eq!(allowed, 100);
+    }
+
+    #[test]
+    fn test_atomic_gcra_gc() {
+        let limiter = AtomicGCRA::new(Quota::per_second(1000));
+        limiter.check_and_record("key");
+        assert_eq!(limiter.len(), 1);
+
+        std::thread::sleep(std::time::Duration::from_millis(5));
+        limiter.run_gc();
+        assert!(limiter.is_empty());
+    }
+}
@@ -2,7 +2,7 @@
 
 use std::time::Duration;
 
-use crate::algorithm::{current_timestamp_ms, timestamp_to_instant, Algorithm};
+use crate::algorithm::{current_timestamp_ms, timestamp_to_instant, warmed_quota, Algorithm};
 use crate::decision::{Decision, RateLimitInfo};
 use crate::error::Result;
 use crate::quota::Quota;
@@ -39,6 +39,8 @@ impl Algorithm for FixedWindow {
         quota: &Quota,
     ) -> Result<Decision> {
         let now = current_timestamp_ms();
+        let quota = warmed_quota(storage, key, quota, now, true).await?;
+        let quota = &*quota;
         let window_ms = quota.window().as_millis() as u64;
         let window_start = self.window_start(now, window_ms);
         let ttl = Duration::from_millis(window_ms * 2);
@@ -68,6 +70,8 @@ impl Algorithm for FixedWindow {
         quota: &Quota,
     ) -> Result<Decision> {
         let now = current_timestamp_ms();
+        let quota = warmed_quota(storage, key, quota, now, false).await?;
+        let quota = &*quota;
         let window_ms = quota.window().as_millis() as u64;
         let window_start = self.window_start(now, window_ms);
 
@@ -112,4 +116,33 @@ mod tests {
         let decision = algorithm.check_and_record(&storage, "user:1", &quota).await.unwrap();
         assert!(decision.is_denied());
     }
+
+    #[tokio::test]
+    async fn test_fixed_window_warmup() {
+        use crate::quota::Warmup;
+        use crate::storage::StorageEntry;
+
+        let algorithm = FixedWindow::new();
+        let storage = MemoryStorage::new();
+        let quota = Quota::per_minute(10).with_warmup(Warmup::new(0.2, Duration::from_secs(3600)));
+
+        let decision = algorithm.check(&storage, "new", &quota).await.unwrap();
+        assert_eq!(decision.info().limit, 2);
+
+        for _ in 0..2 {
+            assert!(algorithm.check_and_record(&storage, "new", &quota).await.unwrap().is_allowed());
+        }
+        let decision = algorithm.check_and_record(&storage, "new", &quota).await.unwrap();
+        assert!(decision.is_denied());
+        assert_eq!(decision.info().limit, 2);
+
+        // First seen 30 minutes ago: 60% of the quota
+        let first_seen = current_timestamp_ms() - 1_800_000;
+        storage
+            .set(&crate::algorithm::warmup_key("half"), StorageEntry::new(0, first_seen), Duration::from_secs(60))
+            .await
+            .unwrap();
+        let decision = algorithm.check_and_record(&storage, "half", &quota).await.unwrap();
+        assert_eq!(decision.info().limit, 6);
+    }
 }
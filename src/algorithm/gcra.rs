@@ -25,11 +25,12 @@
 
 use std::time::Duration;
 
-use crate::algorithm::{current_timestamp_ms, timestamp_to_instant, Algorithm};
+use crate::algorithm::{current_timestamp_ms, timestamp_to_instant, warmed_quota, Algorithm};
 use crate::decision::{Decision, DecisionMetadata, RateLimitInfo};
 use crate::error::Result;
 use crate::quota::Quota;
-use crate::storage::{Storage, StorageEntry};
+use crate::storage::primitives::gcra_step;
+use crate::storage::Storage;
 
 /// GCRA (Generic Cell Rate Algorithm) rate limiter.
 ///
@@ -60,37 +61,8 @@ impl GCRA {
         Self
     }
 
-    /// Calculate the decision based on current TAT and quota.
-    fn calculate_decision(
-        &self,
-        current_tat: Option<u64>,
-        now: u64,
-        quota: &Quota,
-    ) -> (bool, u64) {
-        let period_ms = quota.period().as_millis() as u64;
-        let max_tat_offset_ms = quota.max_tat_offset().as_millis() as u64;
-
-        // Get effective TAT (starts from now if first request)
-        let effective_tat = current_tat.unwrap_or(now);
-
-        // New TAT would be max(now, current_tat) + period
-        let new_tat = effective_tat.max(now) + period_ms;
-
-        // Calculate how far ahead we'd be
-        let tat_offset = new_tat.saturating_sub(now);
-
-        // Check if within burst tolerance
-        if tat_offset <= max_tat_offset_ms + period_ms {
-            // Allowed: update TAT
-            (true, new_tat)
-        } else {
-            // Denied: keep current TAT
-            (false, effective_tat)
-        }
-    }
-
     /// Build rate limit info from current state.
-    fn build_info(&self, tat: u64, now: u64, quota: &Quota, allowed: bool) -> RateLimitInfo {
+    pub(crate) fn build_info(tat: u64, now: u64, quota: &Quota, allowed: bool) -> RateLimitInfo {
         let period_ms = quota.period().as_millis() as u64;
         let max_tat_offset_ms = quota.max_tat_offset().as_millis() as u64;
         let limit = quota.effective_burst();
@@ -139,32 +111,25 @@ impl Algorithm for GCRA {
         quota: &Quota,
     ) -> Result<Decision> {
         let now = current_timestamp_ms();
+        let quota = warmed_quota(storage, key, quota, now, true).await?;
+        let quota = &*quota;
         let period_ms = quota.period().as_millis() as u64;
-        
+        let max_tat_offset_ms = quota.max_tat_offset().as_millis() as u64;
+
         // TTL based on max TAT offset (how far ahead we can schedule)
-        let ttl = Duration::from_millis(
-            quota.max_tat_offset().as_millis() as u64 + period_ms * 2
-        );
+        let ttl = Duration::from_millis(max_tat_offset_ms + period_ms * 2);
 
-        let decision = storage
-            .execute_atomic(key, ttl, |entry| {
-                let current_tat = entry.and_then(|e| e.tat);
-                let (allowed, new_tat) = self.calculate_decision(current_tat, now, quota);
-                
-                let new_entry = StorageEntry::with_tat(new_tat);
-                let info = self.build_info(new_tat, now, quota, allowed);
-                
-                let decision = if allowed {
-                    Decision::allowed(info)
-                } else {
-                    Decision::denied(info)
-                };
-                
-                (new_entry, decision)
-            })
+        let update = storage
+            .update_tat(key, now, period_ms, max_tat_offset_ms, ttl)
             .await?;
 
-        Ok(decision)
+        let info = Self::build_info(update.tat, now, quota, update.allowed);
+
+        Ok(if update.allowed {
+            Decision::allowed(info)
+        } else {
+            Decision::denied(info)
+        })
     }
 
     async fn check<S: Storage>(
@@ -174,14 +139,21 @@ impl Algorithm for GCRA {
         quota: &Quota,
     ) -> Result<Decision> {
         let now = current_timestamp_ms();
+        let quota = warmed_quota(storage, key, quota, now, false).await?;
+        let quota = &*quota;
 
         let entry = storage.get(key).await?;
         let current_tat = entry.and_then(|e| e.tat);
         
-        let (allowed, effective_tat) = self.calculate_decision(current_tat, now, quota);
-        let info = self.build_info(effective_tat, now, quota, allowed);
+        let update = gcra_step(
+            current_tat,
+            now,
+            quota.period().as_millis() as u64,
+            quota.max_tat_offset().as_millis() as u64,
+        );
+        let info = Self::build_info(update.tat, now, quota, update.allowed);
 
-        Ok(if allowed {
+        Ok(if update.allowed {
             Decision::allowed(info)
         } else {
             Decision::denied(info)
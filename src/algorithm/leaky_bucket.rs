@@ -2,14 +2,18 @@
 //!
 //! The Leaky Bucket algorithm smooths out bursty traffic by processing
 //! requests at a constant rate, like water leaking from a bucket.
+//!
+//! The bucket is stored as its free capacity (`max_level - level`), which is
+//! exactly a token bucket, so it shares the `Storage::take_tokens` primitive.
 
 use std::time::Duration;
 
-use crate::algorithm::{current_timestamp_ms, timestamp_to_instant, Algorithm};
+use crate::algorithm::{current_timestamp_ms, timestamp_to_instant, warmed_quota, Algorithm};
 use crate::decision::{Decision, DecisionMetadata, RateLimitInfo};
 use crate::error::Result;
 use crate::quota::Quota;
-use crate::storage::{Storage, StorageEntry};
+use crate::storage::primitives::bucket_level;
+use crate::storage::Storage;
 
 /// Leaky Bucket rate limiting algorithm.
 ///
@@ -24,10 +28,19 @@ impl LeakyBucket {
         Self
     }
 
-    /// Calculate how much has "leaked" based on elapsed time.
-    fn calculate_leak(&self, elapsed_ms: u64, leak_rate: f64) -> f64 {
-        let elapsed_secs = elapsed_ms as f64 / 1000.0;
-        elapsed_secs * leak_rate
+    /// Build rate limit info for the given level.
+    fn build_info(&self, level: f64, max_level: f64, leak_rate: f64, now: u64) -> RateLimitInfo {
+        let remaining = (max_level - level).floor() as u64;
+        let drain_time = (level / leak_rate * 1000.0) as u64;
+        let reset_at = timestamp_to_instant(now + drain_time);
+
+        RateLimitInfo::new(max_level as u64, remaining, reset_at, timestamp_to_instant(now))
+            .with_algorithm("leaky_bucket")
+    }
+
+    /// Calculate how long until there's room for another request.
+    fn wait_time(&self, level: f64, max_level: f64, leak_rate: f64) -> Duration {
+        Duration::from_millis(((level + 1.0 - max_level) / leak_rate * 1000.0) as u64)
     }
 }
 
@@ -43,57 +56,35 @@ impl Algorithm for LeakyBucket {
         quota: &Quota,
     ) -> Result<Decision> {
         let now = current_timestamp_ms();
+        let quota = warmed_quota(storage, key, quota, now, true).await?;
         let max_level = quota.effective_burst() as f64;
         let leak_rate = quota.effective_refill_rate(); // tokens leak out per second
 
         let ttl_ms = ((max_level / leak_rate) * 1000.0 * 2.0) as u64;
         let ttl = Duration::from_millis(ttl_ms.max(1000));
 
-        let decision = storage
-            .execute_atomic(key, ttl, |entry| {
-                let (mut level, last_update) = match entry {
-                    Some(e) => (e.tokens.unwrap_or(0.0), e.last_update),
-                    None => (0.0, now),
-                };
-
-                // Leak tokens based on elapsed time
-                if now > last_update {
-                    let elapsed = now - last_update;
-                    let leaked = self.calculate_leak(elapsed, leak_rate);
-                    level = (level - leaked).max(0.0);
-                }
-
-                // Try to add a "drop" to the bucket
-                if level + 1.0 <= max_level {
-                    level += 1.0;
-                    let new_entry = StorageEntry::with_tokens(level, now);
-                    
-                    let remaining = (max_level - level).floor() as u64;
-                    let drain_time = (level / leak_rate * 1000.0) as u64;
-                    let reset_at = timestamp_to_instant(now + drain_time);
-                    
-                    let info = RateLimitInfo::new(max_level as u64, remaining, reset_at, timestamp_to_instant(now))
-                        .with_algorithm("leaky_bucket")
-                        .with_metadata(DecisionMetadata::new().with_tokens_available(max_level - level));
-                    
-                    (new_entry, Decision::allowed(info))
-                } else {
-                    let new_entry = StorageEntry::with_tokens(level, now);
-                    
-                    // Calculate when there's room for another request
-                    let wait_ms = ((level + 1.0 - max_level) / leak_rate * 1000.0) as u64;
-                    let reset_at = timestamp_to_instant(now + wait_ms);
-                    
-                    let info = RateLimitInfo::new(max_level as u64, 0, reset_at, timestamp_to_instant(now))
-                        .with_algorithm("leaky_bucket")
-                        .with_retry_after(Duration::from_millis(wait_ms));
-                    
-                    (new_entry, Decision::denied(info))
-                }
-            })
+        // Adding a "drop" to the bucket is taking one unit of free capacity
+        let update = storage
+            .take_tokens(key, now, max_level, leak_rate, 1.0, ttl)
             .await?;
+        let level = max_level - update.tokens;
 
-        Ok(decision)
+        if update.allowed {
+            let info = self
+                .build_info(level, max_level, leak_rate, now)
+                .with_metadata(DecisionMetadata::new().with_tokens_available(update.tokens));
+
+            Ok(Decision::allowed(info))
+        } else {
+            let wait = self.wait_time(level, max_level, leak_rate);
+            let reset_at = timestamp_to_instant(now + wait.as_millis() as u64);
+
+            let info = RateLimitInfo::new(max_level as u64, 0, reset_at, timestamp_to_instant(now))
+                .with_algorithm("leaky_bucket")
+                .with_retry_after(wait);
+
+            Ok(Decision::denied(info))
+        }
     }
 
     async fn check<S: Storage>(
@@ -103,34 +94,20 @@ impl Algorithm for LeakyBucket {
         quota: &Quota,
     ) -> Result<Decision> {
         let now = current_timestamp_ms();
+        let quota = warmed_quota(storage, key, quota, now, false).await?;
         let max_level = quota.effective_burst() as f64;
         let leak_rate = quota.effective_refill_rate();
 
         let entry = storage.get(key).await?;
+        let level = max_level - bucket_level(entry.as_ref(), now, max_level, leak_rate);
 
-        let (mut level, last_update) = match entry {
-            Some(e) => (e.tokens.unwrap_or(0.0), e.last_update),
-            None => (0.0, now),
-        };
-
-        if now > last_update {
-            let elapsed = now - last_update;
-            let leaked = self.calculate_leak(elapsed, leak_rate);
-            level = (level - leaked).max(0.0);
-        }
-
-        let remaining = (max_level - level).floor() as u64;
-        let drain_time = (level / leak_rate * 1000.0) as u64;
-        let reset_at = timestamp_to_instant(now + drain_time);
-
-        let info = RateLimitInfo::new(max_level as u64, remaining, reset_at, timestamp_to_instant(now))
-            .with_algorithm("leaky_bucket");
+        let info = self.build_info(level, max_level, leak_rate, now);
 
         Ok(if level + 1.0 <= max_level {
             Decision::allowed(info)
         } else {
-            let wait_ms = ((level + 1.0 - max_level) / leak_rate * 1000.0) as u64;
-            Decision::denied(info.with_retry_after(Duration::from_millis(wait_ms)))
+            let wait = self.wait_time(level, max_level, leak_rate);
+            Decision::denied(info.with_retry_after(wait))
         })
     }
 }
@@ -155,6 +132,26 @@ mod tests {
         assert!(decision.is_denied());
     }
 
+    #[tokio::test]
+    async fn test_leaky_bucket_warmup() {
+        use crate::quota::Warmup;
+
+        let algorithm = LeakyBucket::new();
+        let storage = MemoryStorage::new();
+        let quota = Quota::per_minute(10)
+            .with_burst(10)
+            .with_warmup(Warmup::new(0.5, Duration::from_secs(3600)));
+
+        for i in 1..=5 {
+            let decision = algorithm.check_and_record(&storage, "user:1", &quota).await.unwrap();
+            assert!(decision.is_allowed(), "Request {} should be allowed", i);
+            assert_eq!(decision.info().limit, 5);
+        }
+
+        let decision = algorithm.check_and_record(&storage, "user:1", &quota).await.unwrap();
+        assert!(decision.is_denied());
+    }
+
     #[tokio::test]
     async fn test_leaky_bucket_drain() {
         let algorithm = LeakyBucket::new();
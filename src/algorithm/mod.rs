@@ -6,6 +6,7 @@
 //! # Available Algorithms
 //!
 //! - **GCRA** (`gcra` feature): Generic Cell Rate Algorithm - precise, low memory
+//! - **AtomicGCRA** (`gcra` + `memory`): Lock-free in-memory GCRA for single-node services
 //! - **Token Bucket** (default): Controlled bursts with refilling tokens
 //! - **Leaky Bucket** (`leaky-bucket` feature): Smooth constant output rate
 //! - **Sliding Log** (`sliding-log` feature): High precision, stores all timestamps
@@ -13,6 +14,8 @@
 //! - **Fixed Window** (default): Simple counter per time window
 //! - **Concurrent** (`concurrent` feature): Limit simultaneous requests
 
+#[cfg(all(feature = "gcra", feature = "memory"))]
+mod atomic_gcra;
 #[cfg(feature = "gcra")]
 mod gcra;
 #[cfg(feature = "leaky-bucket")]
@@ -25,6 +28,8 @@ mod fixed_window;
 mod sliding_window;
 mod token_bucket;
 
+#[cfg(all(feature = "gcra", feature = "memory"))]
+pub use atomic_gcra::AtomicGCRA;
 #[cfg(feature = "gcra")]
 pub use gcra::GCRA;
 #[cfg(feature = "leaky-bucket")]
@@ -37,12 +42,14 @@ pub use fixed_window::FixedWindow;
 pub use sliding_window::SlidingWindow;
 pub use token_bucket::TokenBucket;
 
+use std::borrow::Cow;
 use std::future::Future;
+use std::time::Duration;
 
 use crate::decision::Decision;
 use crate::error::Result;
 use crate::quota::Quota;
-use crate::storage::Storage;
+use crate::storage::{Storage, StorageEntry};
 
 /// Rate limiting algorithm trait.
 ///
@@ -91,6 +98,49 @@ pub trait Algorithm: Send + Sync + 'static {
     }
 }
 
+/// Prefix of the keys holding warm-up first-seen times.
+///
+/// Reserved: built-in extractors never produce keys starting with `__`, so
+/// these can't collide with rate limiting keys. Custom extractors shouldn't
+/// either.
+const WARMUP_KEY_PREFIX: &str = "__warmup:";
+
+/// Get the storage key holding the warm-up first-seen time of `key`.
+pub(crate) fn warmup_key(key: &str) -> String {
+    format!("{}{}", WARMUP_KEY_PREFIX, key)
+}
+
+/// Get the effective quota of `key` at `now`, applying the quota's warm-up.
+///
+/// The key's first-seen time is kept under [`warmup_key`]. Recording
+/// creates it for new keys (without rewriting it afterwards) and extends
+/// its TTL to the warm-up's `idle_timeout`; peeking treats unseen keys as
+/// first seen `now`.
+pub(crate) async fn warmed_quota<'q, S: Storage>(
+    storage: &S,
+    key: &str,
+    quota: &'q Quota,
+    now: u64,
+    record: bool,
+) -> Result<Cow<'q, Quota>> {
+    let Some(warmup) = quota.warmup() else {
+        return Ok(Cow::Borrowed(quota));
+    };
+
+    let first_seen_key = warmup_key(key);
+    let first_seen = if record {
+        storage
+            .get_or_insert(&first_seen_key, StorageEntry::new(0, now), warmup.idle_timeout)
+            .await?
+            .window_start
+    } else {
+        storage.get(&first_seen_key).await?.map_or(now, |e| e.window_start)
+    };
+
+    let elapsed = Duration::from_millis(now.saturating_sub(first_seen));
+    Ok(Cow::Owned(quota.warmed_up(elapsed)))
+}
+
 /// Get the current timestamp in milliseconds since Unix epoch.
 pub(crate) fn current_timestamp_ms() -> u64 {
     use std::time::{SystemTime, UNIX_EPOCH};
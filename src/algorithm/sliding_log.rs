@@ -5,11 +5,11 @@
 
 use std::time::Duration;
 
-use crate::algorithm::{current_timestamp_ms, timestamp_to_instant, Algorithm};
+use crate::algorithm::{current_timestamp_ms, timestamp_to_instant, warmed_quota, Algorithm};
 use crate::decision::{Decision, RateLimitInfo};
 use crate::error::Result;
 use crate::quota::Quota;
-use crate::storage::{Storage, StorageEntry};
+use crate::storage::{LogState, Storage};
 
 /// Sliding Log rate limiting algorithm.
 ///
@@ -24,13 +24,26 @@ impl SlidingLog {
         Self
     }
 
-    /// Filter timestamps to only include those within the window.
-    fn filter_window(&self, timestamps: &[u64], window_start: u64) -> Vec<u64> {
-        timestamps
-            .iter()
-            .filter(|&&ts| ts >= window_start)
-            .copied()
-            .collect()
+    /// Build rate limit info from the current log state.
+    fn build_info(&self, state: &LogState, now: u64, window_ms: u64, limit: u64) -> RateLimitInfo {
+        let window_start = now.saturating_sub(window_ms);
+        let remaining = limit.saturating_sub(state.count);
+        let reset_at = match state.oldest {
+            Some(oldest) if !state.recorded => timestamp_to_instant(oldest + window_ms),
+            _ => timestamp_to_instant(now + window_ms),
+        };
+
+        let info = RateLimitInfo::new(limit, remaining, reset_at, timestamp_to_instant(window_start))
+            .with_algorithm("sliding_log");
+
+        if remaining == 0 && !state.recorded {
+            // Wait until the oldest request leaves the window
+            let oldest = state.oldest.unwrap_or(now);
+            let retry_ms = (oldest + window_ms).saturating_sub(now);
+            info.with_retry_after(Duration::from_millis(retry_ms))
+        } else {
+            info
+        }
     }
 }
 
@@ -46,49 +59,20 @@ impl Algorithm for SlidingLog {
         quota: &Quota,
     ) -> Result<Decision> {
         let now = current_timestamp_ms();
+        let quota = warmed_quota(storage, key, quota, now, true).await?;
+        let quota = &*quota;
         let window_ms = quota.window().as_millis() as u64;
-        let window_start = now.saturating_sub(window_ms);
         let ttl = Duration::from_millis(window_ms * 2);
         let limit = quota.max_requests();
 
-        let decision = storage
-            .execute_atomic(key, ttl, |entry| {
-                let mut timestamps = entry
-                    .and_then(|e| e.timestamps)
-                    .unwrap_or_default();
-
-                // Filter to only requests within window
-                timestamps = self.filter_window(&timestamps, window_start);
-                let current_count = timestamps.len() as u64;
-
-                if current_count < limit {
-                    timestamps.push(now);
-                    let new_entry = StorageEntry::with_timestamps(timestamps);
-                    
-                    let remaining = limit - current_count - 1;
-                    let reset_at = timestamp_to_instant(now + window_ms);
-                    let info = RateLimitInfo::new(limit, remaining, reset_at, timestamp_to_instant(window_start))
-                        .with_algorithm("sliding_log");
-                    
-                    (new_entry, Decision::allowed(info))
-                } else {
-                    let new_entry = StorageEntry::with_timestamps(timestamps.clone());
-                    
-                    // Find when oldest request will expire
-                    let oldest = timestamps.first().copied().unwrap_or(now);
-                    let retry_ms = oldest + window_ms - now;
-                    let reset_at = timestamp_to_instant(oldest + window_ms);
-                    
-                    let info = RateLimitInfo::new(limit, 0, reset_at, timestamp_to_instant(window_start))
-                        .with_algorithm("sliding_log")
-                        .with_retry_after(Duration::from_millis(retry_ms));
-                    
-                    (new_entry, Decision::denied(info))
-                }
-            })
-            .await?;
-
-        Ok(decision)
+        let state = storage.append_log(key, now, window_ms, limit, ttl).await?;
+        let info = self.build_info(&state, now, window_ms, limit);
+
+        Ok(if state.recorded {
+            Decision::allowed(info)
+        } else {
+            Decision::denied(info)
+        })
     }
 
     async fn check<S: Storage>(
@@ -98,34 +82,18 @@ impl Algorithm for SlidingLog {
         quota: &Quota,
     ) -> Result<Decision> {
         let now = current_timestamp_ms();
+        let quota = warmed_quota(storage, key, quota, now, false).await?;
+        let quota = &*quota;
         let window_ms = quota.window().as_millis() as u64;
-        let window_start = now.saturating_sub(window_ms);
         let limit = quota.max_requests();
 
-        let entry = storage.get(key).await?;
-        let timestamps = entry
-            .and_then(|e| e.timestamps)
-            .unwrap_or_default();
-
-        let filtered = self.filter_window(&timestamps, window_start);
-        let current_count = filtered.len() as u64;
-
-        let remaining = limit.saturating_sub(current_count);
-        let reset_at = if let Some(&oldest) = filtered.first() {
-            timestamp_to_instant(oldest + window_ms)
-        } else {
-            timestamp_to_instant(now + window_ms)
-        };
-
-        let info = RateLimitInfo::new(limit, remaining, reset_at, timestamp_to_instant(window_start))
-            .with_algorithm("sliding_log");
+        let state = storage.read_log(key, now, window_ms).await?;
+        let info = self.build_info(&state, now, window_ms, limit);
 
-        Ok(if current_count < limit {
+        Ok(if state.count < limit {
             Decision::allowed(info)
         } else {
-            let oldest = filtered.first().copied().unwrap_or(now);
-            let retry_ms = oldest + window_ms - now;
-            Decision::denied(info.with_retry_after(Duration::from_millis(retry_ms)))
+            Decision::denied(info)
         })
     }
 }
@@ -2,11 +2,12 @@
 
 use std::time::Duration;
 
-use crate::algorithm::{current_timestamp_ms, timestamp_to_instant, Algorithm};
+use crate::algorithm::{current_timestamp_ms, timestamp_to_instant, warmed_quota, Algorithm};
 use crate::decision::{Decision, DecisionMetadata, RateLimitInfo};
 use crate::error::Result;
 use crate::quota::Quota;
-use crate::storage::{Storage, StorageEntry};
+use crate::storage::primitives::bucket_level;
+use crate::storage::Storage;
 
 /// Token Bucket rate limiting algorithm.
 ///
@@ -21,12 +22,6 @@ impl TokenBucket {
         Self
     }
 
-    /// Calculate token refill based on elapsed time.
-    fn calculate_refill(&self, elapsed_ms: u64, refill_rate: f64) -> f64 {
-        let elapsed_secs = elapsed_ms as f64 / 1000.0;
-        elapsed_secs * refill_rate
-    }
-
     /// Build rate limit info from current state.
     fn build_info(&self, tokens: f64, quota: &Quota, now: u64) -> RateLimitInfo {
         let max_tokens = quota.effective_burst();
@@ -73,39 +68,25 @@ impl Algorithm for TokenBucket {
         quota: &Quota,
     ) -> Result<Decision> {
         let now = current_timestamp_ms();
+        let quota = warmed_quota(storage, key, quota, now, true).await?;
+        let quota = &*quota;
         let max_tokens = quota.effective_burst() as f64;
         let refill_rate = quota.effective_refill_rate();
 
         let ttl_ms = ((max_tokens / refill_rate) * 1000.0 * 2.0) as u64;
         let ttl = Duration::from_millis(ttl_ms.max(1000));
 
-        let decision = storage
-            .execute_atomic(key, ttl, |entry| {
-                let (mut tokens, last_update) = match entry {
-                    Some(e) => (e.tokens.unwrap_or(max_tokens), e.last_update),
-                    None => (max_tokens, now),
-                };
-
-                if now > last_update {
-                    let elapsed = now - last_update;
-                    let refill = self.calculate_refill(elapsed, refill_rate);
-                    tokens = (tokens + refill).min(max_tokens);
-                }
-
-                if tokens >= 1.0 {
-                    tokens -= 1.0;
-                    let new_entry = StorageEntry::with_tokens(tokens, now);
-                    let info = self.build_info(tokens, quota, now);
-                    (new_entry, Decision::allowed(info))
-                } else {
-                    let new_entry = StorageEntry::with_tokens(tokens, now);
-                    let info = self.build_info(tokens, quota, now);
-                    (new_entry, Decision::denied(info))
-                }
-            })
+        let update = storage
+            .take_tokens(key, now, max_tokens, refill_rate, 1.0, ttl)
             .await?;
 
-        Ok(decision)
+        let info = self.build_info(update.tokens, quota, now);
+
+        Ok(if update.allowed {
+            Decision::allowed(info)
+        } else {
+            Decision::denied(info)
+        })
     }
 
     async fn check<S: Storage>(
@@ -115,21 +96,13 @@ impl Algorithm for TokenBucket {
         quota: &Quota,
     ) -> Result<Decision> {
         let now = current_timestamp_ms();
+        let quota = warmed_quota(storage, key, quota, now, false).await?;
+        let quota = &*quota;
         let max_tokens = quota.effective_burst() as f64;
         let refill_rate = quota.effective_refill_rate();
 
         let entry = storage.get(key).await?;
-
-        let (mut tokens, last_update) = match entry {
-            Some(e) => (e.tokens.unwrap_or(max_tokens), e.last_update),
-            None => (max_tokens, now),
-        };
-
-        if now > last_update {
-            let elapsed = now - last_update;
-            let refill = self.calculate_refill(elapsed, refill_rate);
-            tokens = (tokens + refill).min(max_tokens);
-        }
+        let tokens = bucket_level(entry.as_ref(), now, max_tokens, refill_rate);
 
         let info = self.build_info(tokens, quota, now);
 
@@ -192,4 +165,24 @@ mod tests {
         let decision = algorithm.check_and_record(&storage, "user:1", &quota).await.unwrap();
         assert!(decision.is_allowed());
     }
+
+    #[tokio::test]
+    async fn test_token_bucket_warmup() {
+        use crate::quota::Warmup;
+
+        let algorithm = TokenBucket::new();
+        let storage = MemoryStorage::new();
+        let quota = Quota::per_minute(10)
+            .with_burst(10)
+            .with_warmup(Warmup::new(0.3, Duration::from_secs(3600)));
+
+        for i in 1..=3 {
+            let decision = algorithm.check_and_record(&storage, "user:1", &quota).await.unwrap();
+            assert!(decision.is_allowed(), "Request {} should be allowed", i);
+            assert_eq!(decision.info().limit, 3);
+        }
+
+        let decision = algorithm.check_and_record(&storage, "user:1", &quota).await.unwrap();
+        assert!(decision.is_denied());
+    }
 }
@@ -8,6 +8,8 @@ use std::time::{Duration, Instant};
 
 use serde::{Deserialize, Serialize};
 
+use crate::error::KeyError;
+
 /// The result of a rate limit check.
 #[derive(Debug, Clone)]
 pub struct Decision {
@@ -53,6 +55,36 @@ impl Decision {
     pub fn into_info(self) -> RateLimitInfo {
         self.info
     }
+
+    /// Update the metadata, starting from empty metadata if there is none.
+    pub(crate) fn map_metadata(mut self, f: impl FnOnce(DecisionMetadata) -> DecisionMetadata) -> Self {
+        self.info.metadata = Some(f(self.info.metadata.take().unwrap_or_default()));
+        self
+    }
+}
+
+/// Decision for requests that aren't limited.
+pub(crate) fn unlimited() -> Decision {
+    Decision::allowed(RateLimitInfo::new(
+        u64::MAX,
+        u64::MAX,
+        Instant::now() + Duration::from_secs(3600),
+        Instant::now(),
+    ))
+}
+
+/// Pick the tightest of several decisions, each tagged with its limit.
+///
+/// That is the denial with the longest retry, or else the decision with the
+/// fewest requests remaining; ties go to the earliest.
+pub(crate) fn tightest<T>(decisions: impl IntoIterator<Item = (T, Decision)>) -> Option<(T, Decision)> {
+    decisions.into_iter().min_by_key(|(_, d)| {
+        (
+            d.is_allowed(),
+            std::cmp::Reverse(d.info().retry_after),
+            d.info().remaining,
+        )
+    })
 }
 
 /// Information about the current rate limit state.
@@ -151,6 +183,11 @@ pub struct DecisionMetadata {
     pub tokens_available: Option<f64>,
     /// Theoretical arrival time (for GCRA).
     pub tat: Option<u64>,
+    /// Why the key could not be extracted, if the request was limited
+    /// under the manager's `MissingKeyPolicy`.
+    pub key_error: Option<KeyError>,
+    /// The level that decided, for hierarchical limits.
+    pub level: Option<String>,
 }
 
 impl DecisionMetadata {
@@ -162,6 +199,8 @@ impl DecisionMetadata {
             tokens_consumed: None,
             tokens_available: None,
             tat: None,
+            key_error: None,
+            level: None,
         }
     }
 
@@ -194,6 +233,18 @@ impl DecisionMetadata {
         self.tat = Some(tat);
         self
     }
+
+    /// Set the key extraction failure.
+    pub fn with_key_error(mut self, error: KeyError) -> Self {
+        self.key_error = Some(error);
+        self
+    }
+
+    /// Set the deciding level.
+    pub fn with_level(mut self, level: impl Into<String>) -> Self {
+        self.level = Some(level.into());
+        self
+    }
 }
 
 impl Default for DecisionMetadata {
@@ -4,6 +4,8 @@
 //! operations, including storage errors, configuration errors, and key extraction errors.
 
 use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
 use thiserror::Error;
 
 /// Result type for rate limiting operations.
@@ -22,7 +24,7 @@ pub enum RateLimitError {
 
     /// Key extraction error.
     #[error("Key extraction failed: {0}")]
-    KeyExtraction(String),
+    KeyExtraction(#[from] KeyError),
 
     /// Connection error (e.g., Redis connection failed).
     #[error("Connection error: {0}")]
@@ -44,6 +46,20 @@ pub enum RateLimitError {
     },
 }
 
+impl RateLimitError {
+    /// Check if this error is transient, so the operation can be retried.
+    ///
+    /// Retryable storage errors and connection errors other than failed
+    /// authentication qualify.
+    pub fn is_retryable(&self) -> bool {
+        match self {
+            Self::Storage(e) => e.is_retryable(),
+            Self::Connection(e) => !matches!(e, ConnectionError::AuthFailed(_)),
+            _ => false,
+        }
+    }
+}
+
 /// Storage-related errors.
 #[derive(Debug, Error)]
 pub enum StorageError {
@@ -108,11 +124,64 @@ pub enum ConfigError {
     #[error("Invalid storage configuration: {0}")]
     InvalidStorage(String),
 
+    /// Invalid key extractor configuration.
+    #[error("Invalid key configuration: {0}")]
+    InvalidKey(String),
+
     /// Missing required configuration.
     #[error("Missing required configuration: {0}")]
     MissingRequired(String),
 }
 
+/// Why a rate limiting key could not be extracted from a request.
+#[derive(Debug, Clone, PartialEq, Eq, Error, Serialize, Deserialize)]
+#[serde(tag = "kind", content = "detail", rename_all = "snake_case")]
+pub enum KeyError {
+    /// A required part of the request is absent (e.g. `"header x-api-key"`).
+    #[error("missing {0}")]
+    Missing(String),
+
+    /// A part of the request is present but unusable (e.g. an invalid token).
+    #[error("malformed {what}: {reason}")]
+    Malformed {
+        /// The malformed part of the request.
+        what: String,
+        /// What is wrong with it.
+        reason: String,
+    },
+
+    /// The client address can't be trusted (e.g. an unparseable forwarded
+    /// address).
+    #[error("untrusted client address: {0}")]
+    UntrustedAddress(String),
+
+    /// Any other failure.
+    #[error("{0}")]
+    Other(String),
+}
+
+impl KeyError {
+    /// Create a malformed error.
+    pub fn malformed(what: impl Into<String>, reason: impl ToString) -> Self {
+        Self::Malformed {
+            what: what.into(),
+            reason: reason.to_string(),
+        }
+    }
+}
+
+impl From<String> for KeyError {
+    fn from(message: String) -> Self {
+        Self::Other(message)
+    }
+}
+
+impl From<&str> for KeyError {
+    fn from(message: &str) -> Self {
+        Self::Other(message.to_string())
+    }
+}
+
 /// Connection-related errors.
 #[derive(Debug, Error)]
 pub enum ConnectionError {
@@ -152,11 +221,27 @@ mod tests {
         assert!(!err.is_retryable());
     }
 
+    #[test]
+    fn test_rate_limit_error_retryable() {
+        assert!(RateLimitError::from(StorageError::PoolExhausted).is_retryable());
+        assert!(RateLimitError::from(ConnectionError::Timeout(Duration::from_millis(5))).is_retryable());
+        assert!(!RateLimitError::from(ConnectionError::AuthFailed("denied".into())).is_retryable());
+        assert!(!RateLimitError::from(StorageError::Serialization("bad".into())).is_retryable());
+        assert!(!RateLimitError::Internal("bug".into()).is_retryable());
+    }
+
     #[test]
     fn test_error_display() {
         let err = RateLimitError::KeyExtraction("missing header".into());
         assert_eq!(err.to_string(), "Key extraction failed: missing header");
 
+        let err = RateLimitError::from(KeyError::Missing("header x-api-key".into()));
+        assert_eq!(err.to_string(), "Key extraction failed: missing header x-api-key");
+        assert_eq!(
+            KeyError::malformed("bearer token", "InvalidSignature").to_string(),
+            "malformed bearer token: InvalidSignature"
+        );
+
         let err = RateLimitError::RateLimitExceeded {
             retry_after: Some(Duration::from_secs(10)),
             remaining: 0,
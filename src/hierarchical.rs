@@ -0,0 +1,232 @@
+//! Hierarchical rate limits.
+//!
+//! A request often has to fit several budgets at once: its user's, its
+//! organization's (shared by all of the org's users) and a global one that
+//! protects the backend. `HierarchicalLimiter` checks a request against an
+//! ordered list of such levels and only records it if every level allows it
+//! (best-effort under concurrency, see
+//! [`HierarchicalLimiter::check_and_record_best_effort`]).
+//!
+//! # Example
+//!
+//! ```ignore
+//! use skp_ratelimit::{GCRA, HierarchicalLimiter, Level, MemoryStorage, Quota};
+//!
+//! let limiter = HierarchicalLimiter::new(GCRA::new(), MemoryStorage::new());
+//!
+//! let decision = limiter
+//!     .check_and_record_best_effort(&[
+//!         Level::new("user", format!("user:{}", user_id), Quota::per_second(5)),
+//!         Level::new("org", format!("org:{}", org_id), Quota::per_second(50)),
+//!         Level::new("global", "global", Quota::per_second(1000)),
+//!     ])
+//!     .await?;
+//!
+//! if decision.is_denied() {
+//!     // e.g. Some("org")
+//!     let level = decision.info().metadata.as_ref().and_then(|m| m.level.clone());
+//! }
+//! ```
+
+use std::borrow::Cow;
+use std::sync::Arc;
+
+use crate::algorithm::Algorithm;
+use crate::decision::{tightest, unlimited, Decision};
+use crate::error::Result;
+use crate::key::redact;
+use crate::quota::Quota;
+use crate::storage::Storage;
+
+/// One level of a hierarchical limit.
+#[derive(Debug, Clone)]
+pub struct Level {
+    /// Name reported in `DecisionMetadata::level` (e.g. `"org"`).
+    pub name: Cow<'static, str>,
+    /// Storage key of the level's bucket.
+    pub key: String,
+    /// Quota of the level.
+    pub quota: Quota,
+}
+
+impl Level {
+    /// Create a level.
+    pub fn new(name: impl Into<Cow<'static, str>>, key: impl Into<String>, quota: Quota) -> Self {
+        Self {
+            name: name.into(),
+            key: key.into(),
+            quota,
+        }
+    }
+}
+
+/// Limits requests against several levels of buckets at once.
+///
+/// The decision is the tightest level's (the denial with the longest retry,
+/// or else the level with the fewest requests remaining), with the level's
+/// name in `DecisionMetadata::level`.
+///
+/// Levels are checked before any is recorded, so a request denied by one
+/// level doesn't consume from the others.
+pub struct HierarchicalLimiter<A, S> {
+    algorithm: A,
+    storage: Arc<S>,
+}
+
+impl<A, S> HierarchicalLimiter<A, S>
+where
+    A: Algorithm,
+    S: Storage,
+{
+    /// Create a hierarchical limiter.
+    pub fn new(algorithm: A, storage: S) -> Self {
+        Self {
+            algorithm,
+            storage: Arc::new(storage),
+        }
+    }
+
+    /// Check a request against all `levels` and record it in each if all
+    /// allow it.
+    ///
+    /// Levels are checked in order; put the most likely to deny first.
+    ///
+    /// This is best-effort: levels are checked and then recorded one by
+    /// one, not atomically. A concurrent request can fill a level between
+    /// the two; the request is then denied, but the levels recorded before
+    /// that one keep it. No level ever admits more than its quota, but
+    /// under contention earlier levels can be charged for requests a later
+    /// level denied.
+    pub async fn check_and_record_best_effort(&self, levels: &[Level]) -> Result<Decision> {
+        let decisions = self.check_all(levels).await?;
+        if decisions.iter().any(|(_, decision)| decision.is_denied()) {
+            return Ok(Self::decide(decisions));
+        }
+
+        let mut decisions = Vec::with_capacity(levels.len());
+        for level in levels {
+            let decision = self
+                .algorithm
+                .check_and_record(&*self.storage, &level.key, &level.quota)
+                .await?;
+            let denied = decision.is_denied();
+            decisions.push((level, decision));
+
+            if denied {
+                tracing::debug!(key = %redact(&level.key), level = %level.name, "rate limit exceeded");
+                break;
+            }
+        }
+        Ok(Self::decide(decisions))
+    }
+
+    /// Check a request against all `levels` without recording it.
+    pub async fn check(&self, levels: &[Level]) -> Result<Decision> {
+        Ok(Self::decide(self.check_all(levels).await?))
+    }
+
+    /// Reset the bucket of a level.
+    pub async fn reset(&self, level: &Level) -> Result<()> {
+        self.algorithm.reset(&*self.storage, &level.key).await
+    }
+
+    async fn check_all<'l>(&self, levels: &'l [Level]) -> Result<Vec<(&'l Level, Decision)>> {
+        let mut decisions = Vec::with_capacity(levels.len());
+        for level in levels {
+            let decision = self.algorithm.check(&*self.storage, &level.key, &level.quota).await?;
+            decisions.push((level, decision));
+        }
+        Ok(decisions)
+    }
+
+    /// Report the tightest level's decision.
+    fn decide(decisions: Vec<(&Level, Decision)>) -> Decision {
+        match tightest(decisions) {
+            Some((level, decision)) => decision.map_metadata(|m| m.with_level(level.name.clone())),
+            None => unlimited(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::algorithm::FixedWindow;
+    use crate::storage::MemoryStorage;
+
+    fn levels(user: &str) -> Vec<Level> {
+        vec![
+            Level::new("user", format!("user:{}", user), Quota::per_minute(2)),
+            Level::new("org", "org:acme", Quota::per_minute(3)),
+            Level::new("global", "global", Quota::per_minute(100)),
+        ]
+    }
+
+    fn level(decision: &Decision) -> Option<&str> {
+        decision.info().metadata.as_ref()?.level.as_deref()
+    }
+
+    #[tokio::test]
+    async fn test_levels() {
+        let limiter = HierarchicalLimiter::new(FixedWindow::new(), MemoryStorage::new());
+
+        let decision = limiter.check_and_record_best_effort(&levels("alice")).await.unwrap();
+        assert!(decision.is_allowed());
+        assert_eq!(level(&decision), Some("user"));
+        assert_eq!(decision.info().remaining, 1);
+
+        assert!(limiter.check_and_record_best_effort(&levels("alice")).await.unwrap().is_allowed());
+        let decision = limiter.check_and_record_best_effort(&levels("alice")).await.unwrap();
+        assert!(decision.is_denied());
+        assert_eq!(level(&decision), Some("user"));
+
+        // The org budget is shared by its users
+        assert!(limiter.check_and_record_best_effort(&levels("bob")).await.unwrap().is_allowed());
+        let decision = limiter.check_and_record_best_effort(&levels("carol")).await.unwrap();
+        assert!(decision.is_denied());
+        assert_eq!(level(&decision), Some("org"));
+
+        // Denied requests aren't recorded in any level
+        let decision = limiter.check(&levels("carol")[..1]).await.unwrap();
+        assert_eq!(decision.info().remaining, 2);
+        let decision = limiter.check(&levels("carol")[2..]).await.unwrap();
+        assert_eq!(decision.info().remaining, 97);
+        assert_eq!(level(&decision), Some("global"));
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn test_concurrent_late_denials() {
+        let limiter = Arc::new(HierarchicalLimiter::new(FixedWindow::new(), MemoryStorage::new()));
+        let levels = Arc::new(vec![
+            Level::new("user", "user:alice", Quota::per_minute(100)),
+            Level::new("global", "global", Quota::per_minute(5)),
+        ]);
+        let barrier = Arc::new(tokio::sync::Barrier::new(20));
+
+        let tasks: Vec<_> = (0..20)
+            .map(|_| {
+                let (limiter, levels, barrier) = (limiter.clone(), levels.clone(), barrier.clone());
+                tokio::spawn(async move {
+                    barrier.wait().await;
+                    limiter.check_and_record_best_effort(&levels).await.unwrap().is_allowed()
+                })
+            })
+            .collect();
+        let mut allowed = 0;
+        for task in tasks {
+            allowed += u64::from(task.await.unwrap());
+        }
+
+        // The global level never over-admits...
+        assert_eq!(allowed, 5);
+        // ...but requests it denied late may still be charged to the user
+        let remaining = limiter.check(&levels[..1]).await.unwrap().info().remaining;
+        assert!((80..=95).contains(&remaining), "remaining {remaining}");
+    }
+
+    #[tokio::test]
+    async fn test_no_levels() {
+        let limiter = HierarchicalLimiter::new(FixedWindow::new(), MemoryStorage::new());
+        assert!(limiter.check_and_record_best_effort(&[]).await.unwrap().is_allowed());
+    }
+}
@@ -0,0 +1,353 @@
+//! Asynchronous key extraction.
+//!
+//! Some keys need I/O to compute, e.g. resolving an API key to the account
+//! that owns it. `AsyncKey` is the async counterpart of [`Key`]; the usual
+//! shape is a sync `Key` that extracts the input plus an async lookup, which
+//! [`LookupKey`] provides (optionally with an LRU cache in front).
+
+use std::collections::HashMap;
+use std::fmt;
+use std::future::Future;
+use std::sync::Arc;
+use std::time::Duration;
+
+use parking_lot::Mutex;
+use tokio::time::Instant;
+
+use crate::error::KeyError;
+use crate::key::Key;
+
+/// Trait for extracting rate limiting keys asynchronously.
+///
+/// Use it with `RateLimitManager::check_and_record_async` or, in the
+/// middlewares, wrapped in `AsyncKeyed`. Wrap sync keys in [`SyncKey`] to
+/// pass them where an `AsyncKey` is expected.
+///
+/// Returned futures must be `Send`; avoid holding `request` across an
+/// `.await` unless the request type is `Sync`.
+pub trait AsyncKey<R>: Send + Sync + 'static {
+    /// Extract a rate limiting key from the request.
+    ///
+    /// Returns `None` if the key cannot be extracted.
+    fn extract(&self, request: &R) -> impl Future<Output = Option<String>> + Send;
+
+    /// Extract a rate limiting key, reporting why extraction failed.
+    ///
+    /// Defaults to `extract`, like [`Key::try_extract`].
+    fn try_extract(&self, request: &R) -> impl Future<Output = Result<String, KeyError>> + Send {
+        let key = self.extract(request);
+        async move {
+            key.await
+                .ok_or_else(|| KeyError::Missing(self.name().to_string()))
+        }
+    }
+
+    /// Get the key name for logging/metrics.
+    fn name(&self) -> &'static str;
+}
+
+/// Use a sync [`Key`] where an [`AsyncKey`] is expected.
+#[derive(Debug, Clone)]
+pub struct SyncKey<K> {
+    inner: K,
+}
+
+impl<K> SyncKey<K> {
+    /// Wrap a sync key extractor.
+    pub fn new(inner: K) -> Self {
+        Self { inner }
+    }
+}
+
+impl<R, K> AsyncKey<R> for SyncKey<K>
+where
+    K: Key<R>,
+{
+    fn extract(&self, request: &R) -> impl Future<Output = Option<String>> + Send {
+        std::future::ready(self.inner.extract(request))
+    }
+
+    fn try_extract(&self, request: &R) -> impl Future<Output = Result<String, KeyError>> + Send {
+        std::future::ready(self.inner.try_extract(request))
+    }
+
+    fn name(&self) -> &'static str {
+        self.inner.name()
+    }
+}
+
+/// Derive the key with an async lookup on the output of a sync key.
+///
+/// The lookup receives the extracted input (e.g. `"header:x-api-key:sk_..."`)
+/// and returns the final key, or `None` if there is none (e.g. an unknown
+/// API key), reported as `KeyError::Other`. With
+/// [`with_cache`](Self::with_cache), results, including `None`, are cached
+/// per input.
+///
+/// # Example
+///
+/// ```ignore
+/// use skp_ratelimit::key::{HeaderKey, LookupKey};
+///
+/// let key = LookupKey::new(HeaderKey::api_key(), move |api_key: String| {
+///     let db = db.clone();
+///     async move { db.account_for(&api_key).await.map(|id| format!("account:{}", id)) }
+/// })
+/// .with_cache(10_000, Duration::from_secs(60));
+/// ```
+#[derive(Clone)]
+pub struct LookupKey<K, F> {
+    input: K,
+    lookup: F,
+    cache: Option<Arc<Mutex<LruCache>>>,
+}
+
+impl<K: fmt::Debug, F> fmt::Debug for LookupKey<K, F> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("LookupKey")
+            .field("input", &self.input)
+            .field("cached", &self.cache.is_some())
+            .finish_non_exhaustive()
+    }
+}
+
+impl<K, F> LookupKey<K, F> {
+    /// Look up the key from the output of `input`.
+    pub fn new(input: K, lookup: F) -> Self {
+        Self {
+            input,
+            lookup,
+            cache: None,
+        }
+    }
+
+    /// Cache up to `capacity` lookup results for `ttl`, evicting the least
+    /// recently used.
+    ///
+    /// Clones share the cache.
+    pub fn with_cache(mut self, capacity: usize, ttl: Duration) -> Self {
+        self.cache = Some(Arc::new(Mutex::new(LruCache::new(capacity, ttl))));
+        self
+    }
+}
+
+impl<R, K, F, Fut> AsyncKey<R> for LookupKey<K, F>
+where
+    K: Key<R>,
+    F: Fn(String) -> Fut + Send + Sync + 'static,
+    Fut: Future<Output = Option<String>> + Send,
+{
+    fn extract(&self, request: &R) -> impl Future<Output = Option<String>> + Send {
+        let key = self.try_extract(request);
+        async move { key.await.ok() }
+    }
+
+    fn try_extract(&self, request: &R) -> impl Future<Output = Result<String, KeyError>> + Send {
+        // Extract before the first await so the future doesn't borrow the
+        // request
+        let input = self.input.try_extract(request);
+
+        async move {
+            let input = input?;
+            let cached = self.cache.as_ref().and_then(|cache| cache.lock().get(&input));
+            let key = match cached {
+                Some(key) => key,
+                None => {
+                    let key = (self.lookup)(input.clone()).await;
+                    if let Some(cache) = &self.cache {
+                        cache.lock().insert(input, key.clone());
+                    }
+                    key
+                }
+            };
+            key.ok_or_else(|| KeyError::Other("lookup found no key".into()))
+        }
+    }
+
+    fn name(&self) -> &'static str {
+        self.input.name()
+    }
+}
+
+const NIL: usize = usize::MAX;
+
+/// A cached lookup result in the LRU list.
+struct Node {
+    input: String,
+    key: Option<String>,
+    expires_at: Instant,
+    prev: usize,
+    next: usize,
+}
+
+/// Fixed-capacity LRU map from lookup input to result, with expiry.
+///
+/// Nodes live in a `Vec` and form a doubly linked list by index, most
+/// recently used first; when full, the tail node is reused.
+struct LruCache {
+    index: HashMap<String, usize>,
+    nodes: Vec<Node>,
+    head: usize,
+    tail: usize,
+    capacity: usize,
+    ttl: Duration,
+}
+
+impl LruCache {
+    fn new(capacity: usize, ttl: Duration) -> Self {
+        let capacity = capacity.max(1);
+        Self {
+            index: HashMap::with_capacity(capacity),
+            nodes: Vec::with_capacity(capacity),
+            head: NIL,
+            tail: NIL,
+            capacity,
+            ttl,
+        }
+    }
+
+    /// Get a live result, marking it most recently used.
+    fn get(&mut self, input: &str) -> Option<Option<String>> {
+        let idx = *self.index.get(input)?;
+        if self.nodes[idx].expires_at <= Instant::now() {
+            return None;
+        }
+        self.unlink(idx);
+        self.push_front(idx);
+        Some(self.nodes[idx].key.clone())
+    }
+
+    fn insert(&mut self, input: String, key: Option<String>) {
+        let expires_at = Instant::now() + self.ttl;
+
+        if let Some(&idx) = self.index.get(&input) {
+            self.nodes[idx].key = key;
+            self.nodes[idx].expires_at = expires_at;
+            self.unlink(idx);
+            self.push_front(idx);
+            return;
+        }
+
+        let idx = if self.nodes.len() < self.capacity {
+            self.nodes.push(Node {
+                input: input.clone(),
+                key,
+                expires_at,
+                prev: NIL,
+                next: NIL,
+            });
+            self.nodes.len() - 1
+        } else {
+            let idx = self.tail;
+            self.unlink(idx);
+            let node = &mut self.nodes[idx];
+            self.index.remove(&node.input);
+            node.input = input.clone();
+            node.key = key;
+            node.expires_at = expires_at;
+            idx
+        };
+
+        self.index.insert(input, idx);
+        self.push_front(idx);
+    }
+
+    fn unlink(&mut self, idx: usize) {
+        let (prev, next) = (self.nodes[idx].prev, self.nodes[idx].next);
+        match prev {
+            NIL => self.head = next,
+            prev => self.nodes[prev].next = next,
+        }
+        match next {
+            NIL => self.tail = prev,
+            next => self.nodes[next].prev = prev,
+        }
+    }
+
+    fn push_front(&mut self, idx: usize) {
+        self.nodes[idx].prev = NIL;
+        self.nodes[idx].next = self.head;
+        match self.head {
+            NIL => self.tail = idx,
+            head => self.nodes[head].prev = idx,
+        }
+        self.head = idx;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::key::StaticKey;
+    use std::sync::atomic::{AtomicU32, Ordering};
+
+    fn counting_lookup(
+        calls: Arc<AtomicU32>,
+    ) -> impl Fn(String) -> std::future::Ready<Option<String>> + Clone {
+        move |input: String| {
+            calls.fetch_add(1, Ordering::SeqCst);
+            std::future::ready(input.strip_prefix("key-").map(|id| format!("account:{}", id)))
+        }
+    }
+
+    #[tokio::test]
+    async fn test_sync_key() {
+        let key = SyncKey::new(StaticKey::new("static"));
+        assert_eq!(key.extract(&()).await, Some("static".to_string()));
+        assert_eq!(AsyncKey::<()>::name(&key), "static");
+    }
+
+    #[tokio::test]
+    async fn test_lookup_key() {
+        let calls = Arc::new(AtomicU32::new(0));
+        let key = LookupKey::new(StaticKey::new("key-42"), counting_lookup(calls.clone()));
+
+        assert_eq!(key.extract(&()).await, Some("account:42".to_string()));
+        assert_eq!(key.extract(&()).await, Some("account:42".to_string()));
+        assert_eq!(calls.load(Ordering::SeqCst), 2);
+
+        let key = LookupKey::new(StaticKey::new("unknown"), counting_lookup(calls));
+        assert_eq!(key.extract(&()).await, None);
+        assert_eq!(
+            key.try_extract(&()).await,
+            Err(KeyError::Other("lookup found no key".into()))
+        );
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_lookup_key_cache() {
+        let calls = Arc::new(AtomicU32::new(0));
+        let key = LookupKey::new(StaticKey::new("key-42"), counting_lookup(calls.clone()))
+            .with_cache(10, Duration::from_secs(60));
+
+        assert_eq!(key.extract(&()).await, Some("account:42".to_string()));
+        assert_eq!(key.clone().extract(&()).await, Some("account:42".to_string()));
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+
+        tokio::time::advance(Duration::from_secs(61)).await;
+        assert_eq!(key.extract(&()).await, Some("account:42".to_string()));
+        assert_eq!(calls.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_lru_cache_eviction() {
+        let mut cache = LruCache::new(2, Duration::from_secs(60));
+        cache.insert("a".into(), Some("1".into()));
+        cache.insert("b".into(), None);
+
+        // Touch "a" so "b" is the least recently used
+        assert_eq!(cache.get("a"), Some(Some("1".into())));
+        cache.insert("c".into(), Some("3".into()));
+
+        assert_eq!(cache.get("b"), None);
+        assert_eq!(cache.get("a"), Some(Some("1".into())));
+        assert_eq!(cache.get("c"), Some(Some("3".into())));
+
+        cache.insert("a".into(), Some("updated".into()));
+        cache.insert("d".into(), None);
+        assert_eq!(cache.get("c"), None);
+        assert_eq!(cache.get("a"), Some(Some("updated".into())));
+        assert_eq!(cache.get("d"), Some(None));
+        assert_eq!(cache.index.len(), 2);
+    }
+}
@@ -0,0 +1,437 @@
+//! Variable-arity key pipelines.
+//!
+//! `KeyChain` combines any number of extractors and post-processes the
+//! result. Chains can also be compiled from a small string DSL, so the key
+//! can come from a config file:
+//!
+//! ```text
+//! ip+header:x-tenant|global
+//! ```
+//!
+//! `+` joins extractors into one key (all must succeed) and `|` tries
+//! alternatives in order; `+` binds tighter than `|`. The available terms
+//! are `global`, `ip`, `path`, `method`, `header:<name>`, `query:<name>`,
+//! `cookie:<name>`, `param:<name>` and `static:<value>`.
+
+use std::borrow::Cow;
+use std::fmt;
+use std::str::FromStr;
+use std::sync::Arc;
+
+use crate::error::{ConfigError, KeyError};
+use crate::key::{
+    CookieKey, GlobalKey, HasCookies, HasHeaders, HasIpAddr, HasMethod, HasPath, HasPathParams,
+    HasQuery, HeaderKey, IpKey, Key, MethodKey, PathKey, PathParamKey, QueryKey, StaticKey,
+};
+
+type Transform = Arc<dyn Fn(String) -> Option<String> + Send + Sync>;
+
+/// How a chain combines the keys of its extractors.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Mode {
+    AllOf,
+    FirstOf,
+}
+
+/// A pipeline of key extractors.
+///
+/// `K` is the element type: [`BuiltinKey`] for chains compiled from the DSL,
+/// or a boxed trait object (see [`DynKeyChain`]) to mix arbitrary
+/// extractors. The combined key is passed through each transform in order,
+/// and the default (if any) is used when no key results.
+///
+/// # Example
+///
+/// ```ignore
+/// use skp_ratelimit::key::{DynKeyChain, HeaderKey, IpKey, KeyChain, PathKey};
+///
+/// // Four parts: "ip:1.2.3.4:header:x-tenant:acme:path:/api:method:GET"
+/// let key: DynKeyChain<MyRequest> = KeyChain::all_of([
+///     Box::new(IpKey::new()) as Box<dyn Key<MyRequest>>,
+///     Box::new(HeaderKey::new("x-tenant")),
+///     Box::new(PathKey::new()),
+///     Box::new(MethodKey::new()),
+/// ]);
+///
+/// // From config
+/// let key: KeyChain = "ip+header:x-tenant|global".parse()?;
+/// ```
+#[derive(Clone)]
+pub struct KeyChain<K = BuiltinKey> {
+    keys: Vec<K>,
+    mode: Mode,
+    separator: Cow<'static, str>,
+    transforms: Vec<Transform>,
+    default: Option<String>,
+}
+
+/// A chain of boxed extractors for requests of type `R`.
+pub type DynKeyChain<R> = KeyChain<Box<dyn Key<R>>>;
+
+impl<K: fmt::Debug> fmt::Debug for KeyChain<K> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("KeyChain")
+            .field("keys", &self.keys)
+            .field("mode", &self.mode)
+            .field("separator", &self.separator)
+            .field("transforms", &self.transforms.len())
+            .field("default", &self.default)
+            .finish()
+    }
+}
+
+impl<K> KeyChain<K> {
+    fn with_mode(keys: impl IntoIterator<Item = K>, mode: Mode) -> Self {
+        Self {
+            keys: keys.into_iter().collect(),
+            mode,
+            separator: Cow::Borrowed(":"),
+            transforms: Vec::new(),
+            default: None,
+        }
+    }
+
+    /// Join the keys of all extractors; fails if any of them fails.
+    pub fn all_of(keys: impl IntoIterator<Item = K>) -> Self {
+        Self::with_mode(keys, Mode::AllOf)
+    }
+
+    /// Use the key of the first extractor that succeeds.
+    pub fn first_of(keys: impl IntoIterator<Item = K>) -> Self {
+        Self::with_mode(keys, Mode::FirstOf)
+    }
+
+    /// Set the separator used by `all_of` (default `:`).
+    pub fn with_separator(mut self, separator: impl Into<Cow<'static, str>>) -> Self {
+        self.separator = separator.into();
+        self
+    }
+
+    /// Rewrite the key; returning `None` rejects it.
+    pub fn transform<F>(mut self, transform: F) -> Self
+    where
+        F: Fn(String) -> Option<String> + Send + Sync + 'static,
+    {
+        self.transforms.push(Arc::new(transform));
+        self
+    }
+
+    /// Lowercase the key, e.g. for case-insensitive header values.
+    pub fn lowercase(self) -> Self {
+        self.transform(|key| Some(key.to_lowercase()))
+    }
+
+    /// Use `default` when no key results.
+    pub fn or_default(mut self, default: impl Into<String>) -> Self {
+        self.default = Some(default.into());
+        self
+    }
+}
+
+impl KeyChain {
+    /// Compile a chain from the key DSL (see the [module docs](self)).
+    pub fn parse(dsl: &str) -> Result<Self, ConfigError> {
+        let alternatives = dsl
+            .split('|')
+            .map(|alternative| {
+                let terms = alternative
+                    .split('+')
+                    .map(BuiltinKey::parse)
+                    .collect::<Result<Vec<_>, _>>()?;
+                Ok(KeyChain::all_of(terms))
+            })
+            .collect::<Result<Vec<_>, ConfigError>>()?;
+
+        match <[_; 1]>::try_from(alternatives) {
+            Ok([chain]) => Ok(chain),
+            Err(alternatives) => Ok(KeyChain::first_of(
+                alternatives.into_iter().map(|chain| BuiltinKey::Chain(Box::new(chain))),
+            )),
+        }
+    }
+}
+
+impl FromStr for KeyChain {
+    type Err = ConfigError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::parse(s)
+    }
+}
+
+impl<R, K> Key<R> for KeyChain<K>
+where
+    K: Key<R>,
+{
+    fn extract(&self, request: &R) -> Option<String> {
+        self.try_extract(request).ok()
+    }
+
+    /// Reports the first failure of `all_of` and the last of `first_of`.
+    fn try_extract(&self, request: &R) -> Result<String, KeyError> {
+        let key = match self.mode {
+            Mode::AllOf => self
+                .keys
+                .iter()
+                .map(|key| key.try_extract(request))
+                .collect::<Result<Vec<_>, _>>()
+                .map(|parts| parts.join(&self.separator)),
+            Mode::FirstOf => {
+                let mut result = Err(KeyError::Missing("chain".into()));
+                for key in &self.keys {
+                    result = key.try_extract(request);
+                    if result.is_ok() {
+                        break;
+                    }
+                }
+                result
+            }
+        };
+
+        key.and_then(|key| {
+            self.transforms
+                .iter()
+                .try_fold(key, |key, transform| transform(key))
+                .ok_or_else(|| KeyError::Other("key rejected by transform".into()))
+        })
+        .or_else(|e| self.default.clone().ok_or(e))
+    }
+
+    fn name(&self) -> &'static str {
+        "chain"
+    }
+}
+
+/// The extractors available in the key DSL.
+///
+/// Implements `Key` for any request providing all the request traits, which
+/// includes the axum and actix middleware adapters.
+#[derive(Debug, Clone)]
+pub enum BuiltinKey {
+    /// `global`
+    Global(GlobalKey),
+    /// `static:<value>`
+    Static(StaticKey),
+    /// `ip`
+    Ip(IpKey),
+    /// `path`
+    Path(PathKey),
+    /// `method`
+    Method(MethodKey),
+    /// `header:<name>`
+    Header(HeaderKey),
+    /// `query:<name>`
+    Query(QueryKey),
+    /// `cookie:<name>`
+    Cookie(CookieKey),
+    /// `param:<name>`
+    PathParam(PathParamKey),
+    /// A nested chain.
+    Chain(Box<KeyChain>),
+}
+
+impl BuiltinKey {
+    /// Parse a single DSL term such as `header:x-tenant`.
+    fn parse(term: &str) -> Result<Self, ConfigError> {
+        let term = term.trim();
+        let (kind, arg) = match term.split_once(':') {
+            Some((kind, arg)) => (kind, Some(arg.trim())),
+            None => (term, None),
+        };
+
+        let key = match (kind, arg) {
+            ("global", None) => Self::Global(GlobalKey::new()),
+            ("ip", None) => Self::Ip(IpKey::new()),
+            ("path", None) => Self::Path(PathKey::new()),
+            ("method", None) => Self::Method(MethodKey::new()),
+            ("static", Some(value)) if !value.is_empty() => Self::Static(StaticKey::new(value)),
+            ("header", Some(name)) if !name.is_empty() => {
+                Self::Header(HeaderKey::new(name.to_ascii_lowercase()))
+            }
+            ("query", Some(name)) if !name.is_empty() => Self::Query(QueryKey::new(name.to_owned())),
+            ("cookie", Some(name)) if !name.is_empty() => {
+                Self::Cookie(CookieKey::new(name.to_owned()))
+            }
+            ("param", Some(name)) if !name.is_empty() => {
+                Self::PathParam(PathParamKey::new(name.to_owned()))
+            }
+            _ => {
+                return Err(ConfigError::InvalidKey(format!(
+                    "invalid key term '{}'",
+                    term
+                )))
+            }
+        };
+        Ok(key)
+    }
+}
+
+impl<R> Key<R> for BuiltinKey
+where
+    R: HasIpAddr + HasPath + HasMethod + HasHeaders + HasQuery + HasCookies + HasPathParams,
+{
+    fn extract(&self, request: &R) -> Option<String> {
+        match self {
+            Self::Global(key) => Key::<R>::extract(key, request),
+            Self::Static(key) => Key::<R>::extract(key, request),
+            Self::Ip(key) => key.extract(request),
+            Self::Path(key) => key.extract(request),
+            Self::Method(key) => key.extract(request),
+            Self::Header(key) => key.extract(request),
+            Self::Query(key) => key.extract(request),
+            Self::Cookie(key) => key.extract(request),
+            Self::PathParam(key) => key.extract(request),
+            Self::Chain(chain) => chain.extract(request),
+        }
+    }
+
+    fn try_extract(&self, request: &R) -> Result<String, KeyError> {
+        match self {
+            Self::Global(key) => Key::<R>::try_extract(key, request),
+            Self::Static(key) => Key::<R>::try_extract(key, request),
+            Self::Ip(key) => key.try_extract(request),
+            Self::Path(key) => key.try_extract(request),
+            Self::Method(key) => key.try_extract(request),
+            Self::Header(key) => key.try_extract(request),
+            Self::Query(key) => key.try_extract(request),
+            Self::Cookie(key) => key.try_extract(request),
+            Self::PathParam(key) => key.try_extract(request),
+            Self::Chain(chain) => chain.try_extract(request),
+        }
+    }
+
+    fn name(&self) -> &'static str {
+        match self {
+            Self::Global(key) => Key::<R>::name(key),
+            Self::Static(key) => Key::<R>::name(key),
+            Self::Ip(key) => Key::<R>::name(key),
+            Self::Path(key) => Key::<R>::name(key),
+            Self::Method(key) => Key::<R>::name(key),
+            Self::Header(key) => Key::<R>::name(key),
+            Self::Query(key) => Key::<R>::name(key),
+            Self::Cookie(key) => Key::<R>::name(key),
+            Self::PathParam(key) => Key::<R>::name(key),
+            Self::Chain(chain) => Key::<R>::name(chain.as_ref()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+    use std::net::IpAddr;
+
+    #[derive(Default)]
+    struct MockRequest {
+        ip: Option<IpAddr>,
+        headers: HashMap<String, String>,
+    }
+
+    impl HasIpAddr for MockRequest {
+        fn client_ip(&self) -> Option<IpAddr> {
+            self.ip
+        }
+    }
+
+    impl HasPath for MockRequest {
+        fn path(&self) -> &str {
+            "/api"
+        }
+    }
+
+    impl HasMethod for MockRequest {
+        fn method(&self) -> &str {
+            "GET"
+        }
+    }
+
+    impl HasHeaders for MockRequest {
+        fn header(&self, name: &str) -> Option<&str> {
+            self.headers.get(name).map(|s| s.as_str())
+        }
+    }
+
+    impl HasQuery for MockRequest {
+        fn query_param(&self, _name: &str) -> Option<String> {
+            None
+        }
+    }
+
+    impl HasCookies for MockRequest {
+        fn cookie(&self, _name: &str) -> Option<&str> {
+            None
+        }
+    }
+
+    impl HasPathParams for MockRequest {
+        fn path_param(&self, _name: &str) -> Option<String> {
+            None
+        }
+    }
+
+    fn request(tenant: Option<&str>) -> MockRequest {
+        let mut req = MockRequest {
+            ip: Some("10.0.0.1".parse().unwrap()),
+            ..Default::default()
+        };
+        if let Some(tenant) = tenant {
+            req.headers.insert("x-tenant".into(), tenant.into());
+        }
+        req
+    }
+
+    #[test]
+    fn test_all_of_and_first_of() {
+        let key: DynKeyChain<MockRequest> = KeyChain::all_of([
+            Box::new(IpKey::new()) as Box<dyn Key<MockRequest>>,
+            Box::new(HeaderKey::new("x-tenant")),
+            Box::new(PathKey::new()),
+            Box::new(MethodKey::new()),
+        ]);
+        assert_eq!(
+            key.extract(&request(Some("acme"))),
+            Some("ip:10.0.0.1:header:x-tenant:acme:path:/api:method:GET".to_string())
+        );
+        assert_eq!(key.extract(&request(None)), None);
+        assert_eq!(
+            key.try_extract(&request(None)),
+            Err(KeyError::Missing("header x-tenant".into()))
+        );
+
+        let key: DynKeyChain<MockRequest> = KeyChain::first_of([
+            Box::new(HeaderKey::new("x-tenant")) as Box<dyn Key<MockRequest>>,
+            Box::new(IpKey::new()),
+        ]);
+        assert_eq!(key.extract(&request(None)), Some("ip:10.0.0.1".to_string()));
+    }
+
+    #[test]
+    fn test_transforms_and_default() {
+        let key = KeyChain::all_of([BuiltinKey::parse("header:x-tenant").unwrap()])
+            .with_separator("/")
+            .lowercase()
+            .transform(|key| key.strip_prefix("header:x-tenant:").map(str::to_owned))
+            .or_default("anonymous");
+
+        assert_eq!(key.extract(&request(Some("ACME"))), Some("acme".to_string()));
+        assert_eq!(key.extract(&request(None)), Some("anonymous".to_string()));
+    }
+
+    #[test]
+    fn test_parse_dsl() {
+        let key: KeyChain = "ip+header:X-Tenant|global".parse().unwrap();
+        assert_eq!(
+            key.extract(&request(Some("acme"))),
+            Some("ip:10.0.0.1:header:x-tenant:acme".to_string())
+        );
+        assert_eq!(key.extract(&request(None)), Some("global".to_string()));
+
+        let key = KeyChain::parse(" header:x-tenant | static:anon ").unwrap();
+        assert_eq!(key.extract(&request(None)), Some("anon".to_string()));
+
+        for invalid in ["", "ip+", "bogus", "header:", "ip:v6", "global|"] {
+            assert!(KeyChain::parse(invalid).is_err(), "{:?}", invalid);
+        }
+    }
+}
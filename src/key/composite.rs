@@ -1,5 +1,6 @@
 //! Composite key for combining multiple extractors.
 
+use crate::error::KeyError;
 use crate::key::Key;
 
 /// Combine two key extractors into a composite key.
@@ -48,9 +49,13 @@ where
     K2: Key<R>,
 {
     fn extract(&self, request: &R) -> Option<String> {
-        let k1 = self.first.extract(request)?;
-        let k2 = self.second.extract(request)?;
-        Some(format!("{}{}{}", k1, self.separator, k2))
+        self.try_extract(request).ok()
+    }
+
+    fn try_extract(&self, request: &R) -> Result<String, KeyError> {
+        let k1 = self.first.try_extract(request)?;
+        let k2 = self.second.try_extract(request)?;
+        Ok(format!("{}{}{}", k1, self.separator, k2))
     }
 
     fn name(&self) -> &'static str {
@@ -86,10 +91,14 @@ where
     K3: Key<R>,
 {
     fn extract(&self, request: &R) -> Option<String> {
-        let k1 = self.first.extract(request)?;
-        let k2 = self.second.extract(request)?;
-        let k3 = self.third.extract(request)?;
-        Some(format!(
+        self.try_extract(request).ok()
+    }
+
+    fn try_extract(&self, request: &R) -> Result<String, KeyError> {
+        let k1 = self.first.try_extract(request)?;
+        let k2 = self.second.try_extract(request)?;
+        let k3 = self.third.try_extract(request)?;
+        Ok(format!(
             "{}{}{}{}{}",
             k1, self.separator, k2, self.separator, k3
         ))
@@ -127,6 +136,13 @@ where
             .or_else(|| self.fallback.extract(request))
     }
 
+    /// Reports the fallback's failure when both fail.
+    fn try_extract(&self, request: &R) -> Result<String, KeyError> {
+        self.primary
+            .try_extract(request)
+            .or_else(|_| self.fallback.try_extract(request))
+    }
+
     fn name(&self) -> &'static str {
         "either"
     }
@@ -193,6 +209,13 @@ mod tests {
         assert_eq!(key.extract(&()), Some("primary".to_string()));
     }
 
+    #[test]
+    fn test_composite_key_reports_failure() {
+        let key = CompositeKey::new(StaticKey::new("ip"), crate::key::FnKey::new("user", |_: &()| None));
+        assert_eq!(key.extract(&()), None);
+        assert_eq!(key.try_extract(&()), Err(KeyError::Missing("user".into())));
+    }
+
     #[test]
     fn test_optional_key() {
         let key = OptionalKey::new(StaticKey::new("value"), "default");
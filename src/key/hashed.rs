@@ -0,0 +1,133 @@
+//! Keyed hashing of rate limiting keys.
+//!
+//! Keys often embed sensitive identifiers (API keys, bearer tokens, client
+//! IPs). Hashing them with a secret before they reach storage keeps those
+//! values out of Redis and logs, while equal inputs still map to the same
+//! bucket.
+
+use std::fmt;
+
+use crate::error::KeyError;
+use crate::key::Key;
+
+/// Context string for deriving the BLAKE3 key from the secret.
+const KEY_CONTEXT: &str = "skp-ratelimit 2026-01-01 rate limiting key hashing";
+
+/// Bytes of the keyed hash kept in the output (128 bits).
+const DIGEST_LEN: usize = 16;
+
+/// Hashes keys with keyed BLAKE3 under a secret.
+///
+/// Output is a fixed-length lowercase hex string (32 characters) regardless
+/// of the input length. Without the secret, outputs can't be reversed or
+/// brute-forced from small input spaces such as IP addresses. Every instance
+/// sharing a storage backend must use the same secret.
+#[derive(Clone)]
+pub struct KeyHasher {
+    key: [u8; 32],
+}
+
+impl fmt::Debug for KeyHasher {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("KeyHasher").finish_non_exhaustive()
+    }
+}
+
+impl KeyHasher {
+    /// Create a hasher keyed by `secret`.
+    ///
+    /// The secret can be any length; use at least 32 random bytes.
+    pub fn new(secret: impl AsRef<[u8]>) -> Self {
+        Self {
+            key: blake3::derive_key(KEY_CONTEXT, secret.as_ref()),
+        }
+    }
+
+    /// Hash `key` to a fixed-length hex string.
+    pub fn hash(&self, key: &str) -> String {
+        let hash = blake3::keyed_hash(&self.key, key.as_bytes());
+
+        let mut hex = String::with_capacity(DIGEST_LEN * 2);
+        for byte in &hash.as_bytes()[..DIGEST_LEN] {
+            hex.push(char::from_digit((byte >> 4) as u32, 16).unwrap());
+            hex.push(char::from_digit((byte & 0xf) as u32, 16).unwrap());
+        }
+        hex
+    }
+}
+
+/// Hash the key produced by another extractor.
+///
+/// # Example
+///
+/// ```ignore
+/// use skp_ratelimit::key::{HashedKey, HeaderKey, KeyHasher};
+///
+/// let hasher = KeyHasher::new(std::env::var("RATELIMIT_KEY_SECRET")?);
+/// // "header:x-api-key:sk_live_..." becomes e.g. "9f86d081884c7d659a2feaa0c55ad015"
+/// let key = HashedKey::new(HeaderKey::api_key(), hasher);
+/// ```
+#[derive(Debug, Clone)]
+pub struct HashedKey<K> {
+    inner: K,
+    hasher: KeyHasher,
+}
+
+impl<K> HashedKey<K> {
+    /// Wrap `inner`, hashing its keys with `hasher`.
+    pub fn new(inner: K, hasher: KeyHasher) -> Self {
+        Self { inner, hasher }
+    }
+}
+
+impl<R, K> Key<R> for HashedKey<K>
+where
+    K: Key<R>,
+{
+    fn extract(&self, request: &R) -> Option<String> {
+        self.inner
+            .extract(request)
+            .map(|key| self.hasher.hash(&key))
+    }
+
+    fn try_extract(&self, request: &R) -> Result<String, KeyError> {
+        self.inner
+            .try_extract(request)
+            .map(|key| self.hasher.hash(&key))
+    }
+
+    fn name(&self) -> &'static str {
+        self.inner.name()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::key::StaticKey;
+
+    #[test]
+    fn test_key_hasher() {
+        let hasher = KeyHasher::new("secret");
+
+        let hash = hasher.hash("header:x-api-key:sk_live_123");
+        assert_eq!(hash.len(), 32);
+        assert!(hash.bytes().all(|b| b.is_ascii_hexdigit() && !b.is_ascii_uppercase()));
+        assert_eq!(hash, hasher.hash("header:x-api-key:sk_live_123"));
+        assert_eq!(hasher.hash(&"x".repeat(10_000)).len(), 32);
+
+        assert_ne!(hash, hasher.hash("header:x-api-key:sk_live_124"));
+        assert_ne!(hash, KeyHasher::new("other").hash("header:x-api-key:sk_live_123"));
+        assert!(!format!("{:?}", hasher).contains("key:"));
+    }
+
+    #[test]
+    fn test_hashed_key() {
+        let key = HashedKey::new(StaticKey::new("ip:10.0.0.1"), KeyHasher::new("secret"));
+
+        let extracted = key.extract(&()).unwrap();
+        assert_eq!(extracted, KeyHasher::new("secret").hash("ip:10.0.0.1"));
+        assert!(!extracted.contains("10.0.0.1"));
+        assert_eq!(Key::<()>::name(&key), "static");
+    }
+}
@@ -22,11 +22,29 @@
 //! let composite = CompositeKey::new(IpKey::new(), PathKey::new());
 //! ```
 
+mod async_key;
+mod chain;
 mod composite;
 mod extractors;
-
+#[cfg(feature = "hashing")]
+mod hashed;
+mod proxy;
+#[cfg(feature = "path-templates")]
+mod template;
+
+pub use async_key::{AsyncKey, LookupKey, SyncKey};
+pub use chain::{BuiltinKey, DynKeyChain, KeyChain};
 pub use composite::{CompositeKey, CompositeKey3, EitherKey, OptionalKey};
 pub use extractors::*;
+#[cfg(feature = "hashing")]
+pub use hashed::{HashedKey, KeyHasher};
+pub use proxy::{ForwardedHeader, IpCidr, TrustedProxies};
+#[cfg(feature = "path-templates")]
+pub use template::{PathNormalizer, TemplatedPathKey};
+
+use std::fmt;
+
+use crate::error::KeyError;
 
 /// Trait for extracting rate limiting keys from requests.
 ///
@@ -44,10 +62,37 @@ pub trait Key<R>: Send + Sync + 'static {
     /// means the request should be allowed (fail open).
     fn extract(&self, request: &R) -> Option<String>;
 
+    /// Extract a rate limiting key, reporting why extraction failed.
+    ///
+    /// Defaults to `extract`, reporting a missing key as
+    /// `KeyError::Missing` with the key name. Extractors that can tell what
+    /// went wrong override this and implement `extract` on top of it.
+    fn try_extract(&self, request: &R) -> Result<String, KeyError> {
+        self.extract(request)
+            .ok_or_else(|| KeyError::Missing(self.name().to_string()))
+    }
+
     /// Get the key name for logging/metrics.
     fn name(&self) -> &'static str;
 }
 
+impl<R, K> Key<R> for Box<K>
+where
+    K: Key<R> + ?Sized,
+{
+    fn extract(&self, request: &R) -> Option<String> {
+        (**self).extract(request)
+    }
+
+    fn try_extract(&self, request: &R) -> Result<String, KeyError> {
+        (**self).try_extract(request)
+    }
+
+    fn name(&self) -> &'static str {
+        (**self).name()
+    }
+}
+
 /// A constant key that applies the same limit to all requests.
 #[derive(Debug, Clone, Default)]
 pub struct GlobalKey;
@@ -127,10 +172,52 @@ impl<R> Key<R> for StaticKey {
     }
 }
 
+/// Redact a key for logging.
+///
+/// Keys embed client identifiers, so they are never logged verbatim: only
+/// the extractor prefix is kept (`"ip:10.0.0.1"` is shown as
+/// `"ip:<redacted>"`). Keys hashed by a `KeyHasher` are already opaque and
+/// are shown as-is.
+pub fn redact(key: &str) -> RedactedKey<'_> {
+    RedactedKey(key)
+}
+
+/// A key that displays in redacted form; see [`redact`].
+#[derive(Debug, Clone, Copy)]
+pub struct RedactedKey<'a>(&'a str);
+
+impl fmt::Display for RedactedKey<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let key = self.0;
+        let hashed = key.len() == 32 && key.bytes().all(|b| matches!(b, b'0'..=b'9' | b'a'..=b'f'));
+        if hashed {
+            return f.write_str(key);
+        }
+
+        match key.split_once(':') {
+            Some((prefix, _)) if prefix.bytes().all(|b| b.is_ascii_alphanumeric() || b == b'_') => {
+                write!(f, "{}:<redacted>", prefix)
+            }
+            _ => f.write_str("<redacted>"),
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_redact() {
+        assert_eq!(redact("ip:10.0.0.1:/api").to_string(), "ip:<redacted>");
+        assert_eq!(redact("header:authorization:Bearer abc").to_string(), "header:<redacted>");
+        assert_eq!(redact("Bearer abc:def").to_string(), "<redacted>");
+        assert_eq!(redact("secret").to_string(), "<redacted>");
+
+        let hashed = "0123456789abcdef0123456789abcdef";
+        assert_eq!(redact(hashed).to_string(), hashed);
+    }
+
     #[test]
     fn test_global_key() {
         let key = GlobalKey::new();
@@ -146,6 +233,13 @@ mod tests {
         assert_eq!(key.extract(&request), Some("my-key".to_string()));
     }
 
+    #[test]
+    fn test_default_try_extract() {
+        let key: FnKey<fn(&i32) -> Option<String>> = FnKey::new("custom", |_: &i32| None);
+        assert_eq!(key.try_extract(&42), Err(KeyError::Missing("custom".into())));
+        assert_eq!(StaticKey::new("my-key").try_extract(&()), Ok("my-key".to_string()));
+    }
+
     #[test]
     fn test_fn_key() {
         let key: FnKey<fn(&i32) -> Option<String>> = FnKey::new("custom", |_: &i32| Some("from-fn".to_string()));
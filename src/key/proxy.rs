@@ -0,0 +1,442 @@
+//! Client IP resolution behind reverse proxies.
+//!
+//! Proxies append the address they received a request from to
+//! `X-Forwarded-For` (or the standard `Forwarded` header), so the header
+//! reads `client, proxy1, proxy2` and the connection itself comes from the
+//! last proxy. Everything left of the proxies we control was supplied by the
+//! client and can be forged, so the chain is walked from the right and the
+//! first address not belonging to a trusted proxy is the client.
+
+use std::net::{IpAddr, Ipv4Addr};
+use std::str::FromStr;
+
+use crate::error::ConfigError;
+use crate::key::HasHeaders;
+
+/// An IP network in CIDR notation, e.g. `10.0.0.0/8` or `fd00::/8`.
+///
+/// A bare address is a single-host network.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct IpCidr {
+    network: IpAddr,
+    prefix_len: u8,
+}
+
+impl IpCidr {
+    /// Create a network from an address and prefix length.
+    ///
+    /// Host bits in `addr` are ignored.
+    pub fn new(addr: IpAddr, prefix_len: u8) -> Result<Self, ConfigError> {
+        let max = if addr.is_ipv4() { 32 } else { 128 };
+        if prefix_len > max {
+            return Err(ConfigError::InvalidKey(format!(
+                "prefix length {} exceeds {} for {}",
+                prefix_len, max, addr
+            )));
+        }
+
+        Ok(Self {
+            network: mask(addr, prefix_len),
+            prefix_len,
+        })
+    }
+
+    /// The network address.
+    pub fn network(&self) -> IpAddr {
+        self.network
+    }
+
+    /// The prefix length.
+    pub fn prefix_len(&self) -> u8 {
+        self.prefix_len
+    }
+
+    /// Check whether `ip` belongs to this network.
+    ///
+    /// IPv4-mapped IPv6 addresses are treated as IPv4.
+    pub fn contains(&self, ip: IpAddr) -> bool {
+        let ip = ip.to_canonical();
+        ip.is_ipv4() == self.network.is_ipv4() && mask(ip, self.prefix_len) == self.network
+    }
+}
+
+impl FromStr for IpCidr {
+    type Err = ConfigError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || ConfigError::InvalidKey(format!("invalid CIDR '{}'", s));
+
+        match s.trim().split_once('/') {
+            Some((addr, len)) => {
+                let addr = addr.parse().map_err(|_| invalid())?;
+                let len = len.parse().map_err(|_| invalid())?;
+                Self::new(addr, len)
+            }
+            None => {
+                let addr: IpAddr = s.trim().parse().map_err(|_| invalid())?;
+                Self::new(addr, if addr.is_ipv4() { 32 } else { 128 })
+            }
+        }
+    }
+}
+
+impl std::fmt::Display for IpCidr {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}/{}", self.network, self.prefix_len)
+    }
+}
+
+/// Zero the host bits of `ip`.
+fn mask(ip: IpAddr, prefix_len: u8) -> IpAddr {
+    match ip {
+        IpAddr::V4(v4) => {
+            let bits = u32::MAX.checked_shl(32 - prefix_len as u32).unwrap_or(0);
+            IpAddr::V4((u32::from(v4) & bits).into())
+        }
+        IpAddr::V6(v6) => {
+            let bits = u128::MAX.checked_shl(128 - prefix_len as u32).unwrap_or(0);
+            IpAddr::V6((u128::from(v6) & bits).into())
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+enum Trust {
+    #[default]
+    None,
+    Networks(Vec<IpCidr>),
+    Hops(usize),
+}
+
+/// The header trusted proxies report client addresses in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ForwardedHeader {
+    /// `X-Forwarded-For: client, proxy1, proxy2` (default).
+    #[default]
+    XForwardedFor,
+    /// `Forwarded: for=client, for=proxy1` (RFC 7239).
+    Forwarded,
+    /// `X-Real-IP: client`, set (not appended) by the nearest proxy.
+    XRealIp,
+}
+
+impl ForwardedHeader {
+    fn name(self) -> &'static str {
+        match self {
+            Self::XForwardedFor => "x-forwarded-for",
+            Self::Forwarded => "forwarded",
+            Self::XRealIp => "x-real-ip",
+        }
+    }
+}
+
+/// Which proxies in front of the service are trusted to report client IPs.
+///
+/// Only the configured [`ForwardedHeader`] is read (`X-Forwarded-For` by
+/// default); set it with [`header`](Self::header) to what your proxies
+/// write. Other forwarding headers are never consulted, since proxies pass
+/// client-sent ones through unchanged. Headers are ignored entirely unless
+/// the directly connected peer is a trusted proxy.
+///
+/// # Example
+///
+/// ```ignore
+/// use skp_ratelimit::key::{ForwardedHeader, IpKey, TrustedProxies};
+///
+/// // Behind a load balancer in our private network
+/// let proxies = TrustedProxies::networks(["10.0.0.0/8", "fd00::/8"])?;
+///
+/// // Or: exactly two proxies (CDN + load balancer) in front of the service
+/// let proxies = TrustedProxies::hops(2);
+///
+/// // Proxies that write RFC 7239 `Forwarded` instead
+/// let proxies = TrustedProxies::private_networks().header(ForwardedHeader::Forwarded);
+///
+/// let key = IpKey::with_trusted_proxies(proxies);
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct TrustedProxies {
+    trust: Trust,
+    header: ForwardedHeader,
+}
+
+impl TrustedProxies {
+    /// Trust no proxies: the client is the connected peer (default).
+    pub fn none() -> Self {
+        Self::default()
+    }
+
+    /// Trust proxies whose addresses fall in any of `networks`.
+    pub fn networks<I>(networks: I) -> Result<Self, ConfigError>
+    where
+        I: IntoIterator,
+        I::Item: AsRef<str>,
+    {
+        let networks = networks
+            .into_iter()
+            .map(|cidr| cidr.as_ref().parse())
+            .collect::<Result<_, _>>()?;
+        Ok(Self {
+            trust: Trust::Networks(networks),
+            header: ForwardedHeader::default(),
+        })
+    }
+
+    /// Trust loopback, private (RFC 1918), unique local and link-local
+    /// addresses, where load balancers in the same network usually live.
+    pub fn private_networks() -> Self {
+        Self::networks([
+            "127.0.0.0/8",
+            "10.0.0.0/8",
+            "172.16.0.0/12",
+            "192.168.0.0/16",
+            "169.254.0.0/16",
+            "::1/128",
+            "fc00::/7",
+            "fe80::/10",
+        ])
+        .expect("built-in networks are valid")
+    }
+
+    /// Trust exactly `hops` proxies in front of the service, whatever their
+    /// addresses.
+    ///
+    /// Use this when proxy addresses are not known in advance, e.g. a CDN.
+    /// Requests reaching the service without passing all `hops` proxies can
+    /// forge their address.
+    pub fn hops(hops: usize) -> Self {
+        Self {
+            trust: Trust::Hops(hops),
+            header: ForwardedHeader::default(),
+        }
+    }
+
+    /// Read client addresses from `header` only.
+    pub fn header(mut self, header: ForwardedHeader) -> Self {
+        self.header = header;
+        self
+    }
+
+    /// Check whether `ip` is a trusted proxy address.
+    ///
+    /// Always `false` with hop-count trust, which doesn't look at addresses.
+    pub fn is_trusted(&self, ip: IpAddr) -> bool {
+        match &self.trust {
+            Trust::Networks(networks) => networks.iter().any(|net| net.contains(ip)),
+            Trust::None | Trust::Hops(_) => false,
+        }
+    }
+
+    /// Resolve the client address of a request received from `peer`.
+    ///
+    /// Returns `None` if the peer is unknown or the address the chain points
+    /// to can't be parsed (e.g. `for=unknown`).
+    pub fn resolve<R: HasHeaders + ?Sized>(&self, peer: Option<IpAddr>, request: &R) -> Option<IpAddr> {
+        let peer = peer?;
+
+        match &self.trust {
+            Trust::None => Some(peer),
+            Trust::Networks(_) => {
+                if !self.is_trusted(peer) {
+                    return Some(peer);
+                }
+
+                let mut client = peer;
+                for hop in forwarded_chain(request, self.header).into_iter().rev() {
+                    client = hop?;
+                    if !self.is_trusted(client) {
+                        break;
+                    }
+                }
+                Some(client)
+            }
+            Trust::Hops(0) => Some(peer),
+            Trust::Hops(hops) => {
+                let chain = forwarded_chain(request, self.header);
+                // The peer itself is the nearest proxy
+                let index = chain.len().saturating_sub(*hops);
+                chain.get(index).copied().unwrap_or(Some(peer))
+            }
+        }
+    }
+}
+
+/// Addresses from the forwarding header, client first.
+///
+/// Entries that aren't IP addresses are kept as `None` so hop positions stay
+/// correct.
+fn forwarded_chain<R: HasHeaders + ?Sized>(request: &R, header: ForwardedHeader) -> Vec<Option<IpAddr>> {
+    let Some(value) = request.header_all(header.name()) else {
+        return Vec::new();
+    };
+
+    match header {
+        ForwardedHeader::Forwarded => value
+            .split(',')
+            .map(|element| {
+                element.split(';').find_map(|pair| {
+                    let (name, value) = pair.split_once('=')?;
+                    name.trim().eq_ignore_ascii_case("for").then_some(value)
+                })
+                .and_then(parse_node)
+            })
+            .collect(),
+        ForwardedHeader::XForwardedFor => value.split(',').map(parse_node).collect(),
+        ForwardedHeader::XRealIp => vec![parse_node(&value)],
+    }
+}
+
+/// Parse a forwarded node: an address, optionally quoted, bracketed (IPv6)
+/// and followed by a port.
+fn parse_node(node: &str) -> Option<IpAddr> {
+    let node = node.trim().trim_matches('"');
+
+    if let Some(rest) = node.strip_prefix('[') {
+        let (addr, _port) = rest.split_once(']')?;
+        return addr.parse().ok();
+    }
+    if let Ok(ip) = node.parse() {
+        return Some(ip);
+    }
+
+    let (addr, port) = node.rsplit_once(':')?;
+    port.parse::<u16>().ok()?;
+    addr.parse::<Ipv4Addr>().ok().map(IpAddr::V4)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    struct Headers(HashMap<&'static str, &'static str>);
+
+    impl HasHeaders for Headers {
+        fn header(&self, name: &str) -> Option<&str> {
+            self.0.get(name).copied()
+        }
+    }
+
+    fn headers(pairs: &[(&'static str, &'static str)]) -> Headers {
+        Headers(pairs.iter().copied().collect())
+    }
+
+    fn ip(s: &str) -> IpAddr {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn test_cidr() {
+        let net: IpCidr = "10.1.2.3/8".parse().unwrap();
+        assert_eq!(net.to_string(), "10.0.0.0/8");
+        assert!(net.contains(ip("10.255.0.1")));
+        assert!(!net.contains(ip("11.0.0.1")));
+        assert!(!net.contains(ip("::a01:203")));
+        assert!(net.contains(ip("::ffff:10.1.2.3")));
+
+        let net: IpCidr = "2001:db8::/32".parse().unwrap();
+        assert!(net.contains(ip("2001:db8:cafe::17")));
+        assert!(!net.contains(ip("2001:db9::1")));
+
+        let host: IpCidr = "192.168.1.1".parse().unwrap();
+        assert_eq!(host.prefix_len(), 32);
+        assert!("0.0.0.0/0".parse::<IpCidr>().unwrap().contains(ip("8.8.8.8")));
+
+        assert!("10.0.0.0/33".parse::<IpCidr>().is_err());
+        assert!("nonsense/8".parse::<IpCidr>().is_err());
+    }
+
+    #[test]
+    fn test_parse_node() {
+        assert_eq!(parse_node(" 192.0.2.60 "), Some(ip("192.0.2.60")));
+        assert_eq!(parse_node("192.0.2.60:8080"), Some(ip("192.0.2.60")));
+        assert_eq!(parse_node("\"[2001:db8:cafe::17]:4711\""), Some(ip("2001:db8:cafe::17")));
+        assert_eq!(parse_node("[2001:db8::1]"), Some(ip("2001:db8::1")));
+        assert_eq!(parse_node("2001:db8::1"), Some(ip("2001:db8::1")));
+        assert_eq!(parse_node("unknown"), None);
+        assert_eq!(parse_node("_hidden"), None);
+    }
+
+    #[test]
+    fn test_no_trust_ignores_headers() {
+        let request = headers(&[("x-forwarded-for", "1.1.1.1")]);
+        let proxies = TrustedProxies::none();
+
+        assert_eq!(proxies.resolve(Some(ip("10.0.0.1")), &request), Some(ip("10.0.0.1")));
+        assert_eq!(proxies.resolve(None, &request), None);
+    }
+
+    #[test]
+    fn test_networks_walk_from_right() {
+        let proxies = TrustedProxies::networks(["10.0.0.0/8"]).unwrap();
+        // The client forged the first entry; 2.2.2.2 connected to our proxy
+        let request = headers(&[("x-forwarded-for", "6.6.6.6, 2.2.2.2, 10.0.0.2")]);
+
+        assert_eq!(proxies.resolve(Some(ip("10.0.0.1")), &request), Some(ip("2.2.2.2")));
+        // Untrusted peers can't set their own address
+        assert_eq!(proxies.resolve(Some(ip("3.3.3.3")), &request), Some(ip("3.3.3.3")));
+
+        // Every hop trusted: the left-most address is the client
+        let request = headers(&[("x-forwarded-for", "10.0.0.9")]);
+        assert_eq!(proxies.resolve(Some(ip("10.0.0.1")), &request), Some(ip("10.0.0.9")));
+
+        // No header: the peer is the client
+        assert_eq!(proxies.resolve(Some(ip("10.0.0.1")), &headers(&[])), Some(ip("10.0.0.1")));
+
+        let request = headers(&[("x-forwarded-for", "2.2.2.2, garbage")]);
+        assert_eq!(proxies.resolve(Some(ip("10.0.0.1")), &request), None);
+    }
+
+    #[test]
+    fn test_hops() {
+        let request = headers(&[("x-forwarded-for", "6.6.6.6, 2.2.2.2, 5.5.5.5")]);
+
+        assert_eq!(TrustedProxies::hops(0).resolve(Some(ip("9.9.9.9")), &request), Some(ip("9.9.9.9")));
+        assert_eq!(TrustedProxies::hops(1).resolve(Some(ip("9.9.9.9")), &request), Some(ip("5.5.5.5")));
+        assert_eq!(TrustedProxies::hops(2).resolve(Some(ip("9.9.9.9")), &request), Some(ip("2.2.2.2")));
+        assert_eq!(TrustedProxies::hops(9).resolve(Some(ip("9.9.9.9")), &request), Some(ip("6.6.6.6")));
+    }
+
+    #[test]
+    fn test_forwarded_header() {
+        let proxies = TrustedProxies::private_networks().header(ForwardedHeader::Forwarded);
+        let request = headers(&[
+            ("forwarded", "for=6.6.6.6, for=\"[2001:db8:cafe::17]:4711\";proto=https, For=192.168.0.2;by=10.0.0.1"),
+            ("x-forwarded-for", "7.7.7.7"),
+        ]);
+
+        assert_eq!(proxies.resolve(Some(ip("10.0.0.1")), &request), Some(ip("2001:db8:cafe::17")));
+
+        let request = headers(&[("forwarded", "for=unknown")]);
+        assert_eq!(proxies.resolve(Some(ip("10.0.0.1")), &request), None);
+    }
+
+    #[test]
+    fn test_spoofed_forwarded_header_is_ignored() {
+        // The proxy appends to X-Forwarded-For and passes the client's
+        // Forwarded header through
+        let request = headers(&[
+            ("forwarded", "for=6.6.6.6"),
+            ("x-forwarded-for", "2.2.2.2"),
+            ("x-real-ip", "7.7.7.7"),
+        ]);
+
+        for proxies in [TrustedProxies::networks(["10.0.0.0/8"]).unwrap(), TrustedProxies::hops(1)] {
+            assert_eq!(proxies.resolve(Some(ip("10.0.0.1")), &request), Some(ip("2.2.2.2")));
+        }
+
+        // Without the configured header the peer is the client
+        let request = headers(&[("forwarded", "for=6.6.6.6")]);
+        let proxies = TrustedProxies::private_networks();
+        assert_eq!(proxies.resolve(Some(ip("10.0.0.1")), &request), Some(ip("10.0.0.1")));
+    }
+
+    #[test]
+    fn test_real_ip() {
+        let proxies = TrustedProxies::private_networks().header(ForwardedHeader::XRealIp);
+        let request = headers(&[("x-real-ip", "203.0.113.7")]);
+
+        assert_eq!(proxies.resolve(Some(ip("127.0.0.1")), &request), Some(ip("203.0.113.7")));
+        assert_eq!(proxies.resolve(Some(ip("8.8.8.8")), &request), Some(ip("8.8.8.8")));
+    }
+}
@@ -0,0 +1,228 @@
+//! Route-template normalization of request paths.
+//!
+//! Keying on raw paths gives every id its own bucket (`/users/1`,
+//! `/users/2`, ...). A `PathNormalizer` maps paths back to their route
+//! template, e.g. `/users/{id}`, so the number of keys stays bounded.
+
+use regex::Regex;
+
+use crate::error::ConfigError;
+use crate::key::{HasPath, Key};
+
+/// A segment rule: segments matching `pattern` become `placeholder`.
+#[derive(Debug, Clone)]
+struct Rule {
+    pattern: Regex,
+    placeholder: String,
+}
+
+/// Normalizes request paths to route templates.
+///
+/// A path matching a registered template becomes that template. Otherwise
+/// each segment is replaced by the placeholder of the first rule whose
+/// regex matches the whole segment. The default rules are:
+///
+/// | Segment | Placeholder |
+/// |---------|-------------|
+/// | UUID | `{uuid}` |
+/// | Decimal number | `{id}` |
+/// | 16+ hex digits | `{hash}` |
+///
+/// # Example
+///
+/// ```ignore
+/// use skp_ratelimit::key::PathNormalizer;
+///
+/// let normalizer = PathNormalizer::new()
+///     .with_template("/orgs/{org}/repos/{repo}")
+///     .with_rule(r"[a-z0-9]{6}", "{code}")?;
+///
+/// assert_eq!(normalizer.normalize("/users/42/posts"), "/users/{id}/posts");
+/// assert_eq!(normalizer.normalize("/orgs/acme/repos/api"), "/orgs/{org}/repos/{repo}");
+/// ```
+#[derive(Debug, Clone)]
+pub struct PathNormalizer {
+    templates: Vec<String>,
+    rules: Vec<Rule>,
+}
+
+impl Default for PathNormalizer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl PathNormalizer {
+    /// Create a normalizer with the default rules.
+    pub fn new() -> Self {
+        Self::empty()
+            .with_rule(
+                "[0-9a-fA-F]{8}-[0-9a-fA-F]{4}-[0-9a-fA-F]{4}-[0-9a-fA-F]{4}-[0-9a-fA-F]{12}",
+                "{uuid}",
+            )
+            .and_then(|n| n.with_rule("[0-9]+", "{id}"))
+            .and_then(|n| n.with_rule("[0-9a-fA-F]{16,}", "{hash}"))
+            .expect("default rules are valid")
+    }
+
+    /// Create a normalizer without any rules or templates.
+    pub fn empty() -> Self {
+        Self {
+            templates: Vec::new(),
+            rules: Vec::new(),
+        }
+    }
+
+    /// Add a segment rule, checked after the existing ones.
+    ///
+    /// `pattern` must match the whole segment.
+    pub fn with_rule(
+        mut self,
+        pattern: &str,
+        placeholder: impl Into<String>,
+    ) -> Result<Self, ConfigError> {
+        let pattern = Regex::new(&format!("^(?:{})$", pattern))
+            .map_err(|e| ConfigError::InvalidKey(format!("invalid path rule '{}': {}", pattern, e)))?;
+        self.rules.push(Rule {
+            pattern,
+            placeholder: placeholder.into(),
+        });
+        Ok(self)
+    }
+
+    /// Register a route template such as `/users/{id}` or `/files/{*path}`.
+    ///
+    /// Templates are checked before rules, in registration order.
+    pub fn with_template(mut self, template: impl Into<String>) -> Self {
+        self.templates.push(template.into());
+        self
+    }
+
+    /// Normalize `path` to its route template.
+    pub fn normalize(&self, path: &str) -> String {
+        if let Some(template) = self.templates.iter().find(|t| template_matches(t, path)) {
+            return template.clone();
+        }
+
+        path.split('/')
+            .map(|segment| {
+                self.rules
+                    .iter()
+                    .find(|rule| !segment.is_empty() && rule.pattern.is_match(segment))
+                    .map_or(segment, |rule| rule.placeholder.as_str())
+            })
+            .collect::<Vec<_>>()
+            .join("/")
+    }
+}
+
+/// Check if a route template matches a path.
+///
+/// `{name}` matches one non-empty segment and a trailing `{*name}` matches
+/// the rest of the path.
+fn template_matches(template: &str, path: &str) -> bool {
+    let mut segments = path.split('/');
+
+    for part in template.split('/') {
+        if part.starts_with("{*") && part.ends_with('}') {
+            return true;
+        }
+
+        match segments.next() {
+            Some(segment) if part.starts_with('{') && part.ends_with('}') => {
+                if segment.is_empty() {
+                    return false;
+                }
+            }
+            Some(segment) if segment == part => {}
+            _ => return false,
+        }
+    }
+
+    segments.next().is_none()
+}
+
+/// Extract key from the request path, normalized to its route template.
+///
+/// Unlike `PathKey`, `/users/1` and `/users/2` share the key
+/// `"path:/users/{id}"`.
+#[derive(Debug, Clone, Default)]
+pub struct TemplatedPathKey {
+    normalizer: PathNormalizer,
+}
+
+impl TemplatedPathKey {
+    /// Create a templated path key with the default rules.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Create a templated path key with a custom normalizer.
+    pub fn with_normalizer(normalizer: PathNormalizer) -> Self {
+        Self { normalizer }
+    }
+}
+
+impl<R: HasPath> Key<R> for TemplatedPathKey {
+    fn extract(&self, request: &R) -> Option<String> {
+        Some(format!("path:{}", self.normalizer.normalize(request.path())))
+    }
+
+    fn name(&self) -> &'static str {
+        "path"
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct MockRequest(&'static str);
+
+    impl HasPath for MockRequest {
+        fn path(&self) -> &str {
+            self.0
+        }
+    }
+
+    #[test]
+    fn test_default_rules() {
+        let normalizer = PathNormalizer::new();
+
+        assert_eq!(normalizer.normalize("/users/42/posts/"), "/users/{id}/posts/");
+        assert_eq!(
+            normalizer.normalize("/orders/3f2504e0-4f89-11d3-9a0c-0305e82c3301"),
+            "/orders/{uuid}"
+        );
+        assert_eq!(
+            normalizer.normalize("/blobs/9f86d081884c7d659a2feaa0c55ad015"),
+            "/blobs/{hash}"
+        );
+        assert_eq!(normalizer.normalize("/users/me"), "/users/me");
+        assert_eq!(PathNormalizer::empty().normalize("/users/42"), "/users/42");
+    }
+
+    #[test]
+    fn test_custom_rules_and_templates() {
+        let normalizer = PathNormalizer::empty()
+            .with_rule("v[0-9]+", "{version}")
+            .unwrap()
+            .with_template("/orgs/{org}/repos/{repo}")
+            .with_template("/files/{*path}");
+
+        assert_eq!(normalizer.normalize("/v2/users"), "/{version}/users");
+        assert_eq!(normalizer.normalize("/orgs/acme/repos/api"), "/orgs/{org}/repos/{repo}");
+        assert_eq!(normalizer.normalize("/orgs/acme/repos/api/issues"), "/orgs/acme/repos/api/issues");
+        assert_eq!(normalizer.normalize("/files/a/b/c"), "/files/{*path}");
+
+        assert!(PathNormalizer::empty().with_rule("(", "{x}").is_err());
+    }
+
+    #[test]
+    fn test_templated_path_key() {
+        let key = TemplatedPathKey::new();
+
+        assert_eq!(key.extract(&MockRequest("/users/1")), Some("path:/users/{id}".to_string()));
+        assert_eq!(key.extract(&MockRequest("/users/2")), key.extract(&MockRequest("/users/1")));
+    }
+}
@@ -54,11 +54,13 @@
 //! - `sliding-log`: Sliding Log algorithm
 //! - `concurrent`: Concurrent request limiter
 
+pub mod adaptive;
 pub mod algorithm;
 pub mod decision;
 pub mod error;
 pub mod extensions;
 pub mod headers;
+pub mod hierarchical;
 pub mod key;
 pub mod manager;
 pub mod policy;
@@ -69,12 +71,14 @@ pub mod storage;
 pub mod middleware;
 
 // Re-export main types
+pub use adaptive::{AdaptiveConfig, AdaptiveController};
 pub use algorithm::Algorithm;
 pub use decision::{Decision, DecisionMetadata, RateLimitInfo};
-pub use error::{ConfigError, ConnectionError, RateLimitError, Result, StorageError};
+pub use error::{ConfigError, ConnectionError, KeyError, RateLimitError, Result, StorageError};
+pub use hierarchical::{HierarchicalLimiter, Level};
 pub use key::{CompositeKey, FnKey, GlobalKey, Key, StaticKey};
-pub use manager::{RateLimitManager, RateLimitManagerBuilder, RouteConfig};
-pub use quota::{Quota, QuotaBuilder};
+pub use manager::{Inheritance, MissingKeyPolicy, RateLimitManager, RateLimitManagerBuilder, RouteConfig};
+pub use quota::{Quota, QuotaBuilder, Warmup};
 pub use storage::{Storage, StorageEntry};
 
 // Re-export policy types
@@ -90,6 +94,9 @@ pub use algorithm::{FixedWindow, SlidingWindow, TokenBucket};
 #[cfg(feature = "gcra")]
 pub use algorithm::GCRA;
 
+#[cfg(all(feature = "gcra", feature = "memory"))]
+pub use algorithm::AtomicGCRA;
+
 #[cfg(feature = "leaky-bucket")]
 pub use algorithm::LeakyBucket;
 
@@ -101,7 +108,7 @@ pub use algorithm::ConcurrentLimiter;
 
 // Re-export storage types
 #[cfg(feature = "memory")]
-pub use storage::{GcConfig, GcInterval, MemoryStorage};
+pub use storage::{EvictionPolicy, GcConfig, GcInterval, MemoryStorage};
 
 /// Prelude module for convenient imports.
 pub mod prelude {
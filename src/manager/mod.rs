@@ -0,0 +1,1039 @@
+//! Rate limit manager for per-route configuration.
+//!
+//! The `RateLimitManager` allows you to configure different rate limits
+//! for different routes or patterns, with optional default fallback.
+//! Routes inherit the limits of exact routes at their path prefixes, so
+//! `/api` can define a budget shared by everything below it.
+//!
+//! # Example
+//!
+//! ```ignore
+//! use oc_ratelimit_advanced::{RateLimitManager, Quota, GCRA, MemoryStorage};
+//!
+//! let storage = MemoryStorage::new();
+//! let manager = RateLimitManager::builder()
+//!     .default_quota(Quota::per_second(10))
+//!     .route("/api/search", Quota::per_minute(30))
+//!     .route("/api/auth/login", Quota::per_minute(5))
+//!     .route_pattern("/api/users/{id}", Quota::per_second(20))
+//!     .method_route("POST", "/api/orders", Quota::per_minute(10))
+//!     .build(GCRA::new(), storage);
+//! ```
+
+mod router;
+
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use crate::algorithm::Algorithm;
+use crate::decision::{unlimited, Decision};
+use crate::error::{KeyError, Result};
+use crate::key::{redact, AsyncKey, HasMethod, Key};
+#[cfg(feature = "hashing")]
+use crate::key::KeyHasher;
+#[cfg(feature = "path-templates")]
+use crate::key::PathNormalizer;
+use crate::quota::Quota;
+use crate::storage::Storage;
+
+use router::RouteTree;
+
+/// A rate limit configuration for a specific route.
+#[derive(Debug, Clone)]
+pub struct RouteConfig {
+    /// The quota for this route.
+    pub quota: Quota,
+    /// Optional custom key suffix.
+    pub key_suffix: Option<String>,
+    /// Whether the key includes the request method, giving each method its
+    /// own bucket.
+    pub method_in_key: bool,
+    /// How the route relates to the limits of its parent routes.
+    pub inheritance: Inheritance,
+}
+
+impl RouteConfig {
+    /// Create a new route config with the given quota.
+    pub fn new(quota: Quota) -> Self {
+        Self {
+            quota,
+            key_suffix: None,
+            method_in_key: false,
+            inheritance: Inheritance::Inherit,
+        }
+    }
+
+    /// Add a custom key suffix.
+    pub fn with_key_suffix(mut self, suffix: impl Into<String>) -> Self {
+        self.key_suffix = Some(suffix.into());
+        self
+    }
+
+    /// Include the request method in the key.
+    ///
+    /// Set automatically for routes registered for specific methods.
+    pub fn with_method_in_key(mut self) -> Self {
+        self.method_in_key = true;
+        self
+    }
+
+    /// Set how the route relates to the limits of its parent routes.
+    pub fn with_inheritance(mut self, inheritance: Inheritance) -> Self {
+        self.inheritance = inheritance;
+        self
+    }
+}
+
+/// How a route relates to the limits of its parent routes.
+///
+/// The parents of a request path are the exact routes (see
+/// [`RateLimitManagerBuilder::route`]) at its path prefixes: `/api/search`
+/// has `/api` and `/` as parents. Parent limits are counted in the parent's
+/// own bucket, shared with requests to the parent path itself.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Inheritance {
+    /// Also satisfy the parents' limits, consuming from their buckets
+    /// (default).
+    #[default]
+    Inherit,
+    /// The route's own limit replaces its parents': requests still count
+    /// toward the parents' buckets while those have room, but are never
+    /// denied by them.
+    Override,
+    /// Ignore the parents.
+    OptOut,
+}
+
+/// A limit that applies to a request.
+struct Limit<'a> {
+    /// The route path the limit's key is built from.
+    path: Cow<'a, str>,
+    config: Option<&'a RouteConfig>,
+    quota: &'a Quota,
+    /// Whether the limit can deny the request, or only counts it.
+    enforce: bool,
+}
+
+/// The limits that apply to a request: its route's own and its parents'.
+struct Limits<'a> {
+    own: Option<Limit<'a>>,
+    parents: Vec<Limit<'a>>,
+}
+
+impl Limits<'_> {
+    fn is_empty(&self) -> bool {
+        self.own.is_none() && self.parents.is_empty()
+    }
+}
+
+/// The request methods a route applies to.
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Methods {
+    Any,
+    Only(Vec<String>),
+}
+
+impl Methods {
+    /// Parse `*` or `|`-separated methods such as `GET|HEAD`.
+    fn parse(methods: &str) -> Self {
+        let methods: Vec<String> = methods
+            .split('|')
+            .map(|m| m.trim().to_ascii_uppercase())
+            .filter(|m| !m.is_empty())
+            .collect();
+
+        if methods.is_empty() || methods.iter().any(|m| m == "*") {
+            Self::Any
+        } else {
+            Self::Only(methods)
+        }
+    }
+
+    /// Check if a request with `method` (if known) matches.
+    fn matches(&self, method: Option<&str>) -> bool {
+        match self {
+            Self::Any => true,
+            Self::Only(methods) => {
+                method.is_some_and(|method| methods.iter().any(|m| m.eq_ignore_ascii_case(method)))
+            }
+        }
+    }
+}
+
+impl From<Quota> for RouteConfig {
+    fn from(quota: Quota) -> Self {
+        Self::new(quota)
+    }
+}
+
+/// What a [`RateLimitManager`] does with requests whose key can't be
+/// extracted.
+///
+/// Except with `Deny`, the failure is reported in the decision's
+/// `DecisionMetadata::key_error`.
+#[derive(Debug, Clone, Default)]
+pub enum MissingKeyPolicy {
+    /// Fail with `RateLimitError::KeyExtraction`.
+    Deny,
+    /// Allow the request without limiting it.
+    Allow,
+    /// Limit all such requests of a route together, under the route's
+    /// quota (default).
+    #[default]
+    SharedBucket,
+    /// Limit all such requests of a route together, under this quota.
+    Quota(Quota),
+}
+
+/// Manager for per-route rate limiting.
+///
+/// This provides a centralized way to configure different rate limits
+/// for different routes or patterns.
+pub struct RateLimitManager<A, S, K> {
+    algorithm: A,
+    storage: Arc<S>,
+    key_extractor: K,
+    default_quota: Option<Quota>,
+    routes: HashMap<String, Vec<(Methods, RouteConfig)>>,
+    patterns: RouteTree<(Methods, RouteConfig)>,
+    missing_key_policy: MissingKeyPolicy,
+    #[cfg(feature = "hashing")]
+    key_hasher: Option<KeyHasher>,
+    #[cfg(feature = "path-templates")]
+    path_normalizer: Option<PathNormalizer>,
+}
+
+impl<A, S, K> RateLimitManager<A, S, K>
+where
+    A: Algorithm,
+    S: Storage,
+{
+    /// Create a new rate limit manager builder.
+    pub fn builder() -> RateLimitManagerBuilder<K> {
+        RateLimitManagerBuilder::new()
+    }
+
+    /// Check and record a request.
+    pub async fn check_and_record<R>(&self, path: &str, request: &R) -> Result<Decision>
+    where
+        K: Key<R>,
+    {
+        let path = self.normalize_path(path);
+        let limits = self.resolve(None, &path);
+        if limits.is_empty() {
+            return Ok(unlimited());
+        }
+
+        let base_key = self.key_extractor.try_extract(request);
+        self.limit(&path, None, limits, base_key, true).await
+    }
+
+    /// Check without recording.
+    pub async fn check<R>(&self, path: &str, request: &R) -> Result<Decision>
+    where
+        K: Key<R>,
+    {
+        let path = self.normalize_path(path);
+        let limits = self.resolve(None, &path);
+        if limits.is_empty() {
+            return Ok(unlimited());
+        }
+
+        let base_key = self.key_extractor.try_extract(request);
+        self.limit(&path, None, limits, base_key, false).await
+    }
+
+    /// Check and record a request, matching routes by its method too.
+    ///
+    /// The plain `check*` methods don't know the method, so they only match
+    /// routes registered for any method.
+    pub async fn check_and_record_with_method<R>(&self, path: &str, request: &R) -> Result<Decision>
+    where
+        K: Key<R>,
+        R: HasMethod,
+    {
+        let path = self.normalize_path(path);
+        let method = Some(request.method());
+        let limits = self.resolve(method, &path);
+        if limits.is_empty() {
+            return Ok(unlimited());
+        }
+
+        let base_key = self.key_extractor.try_extract(request);
+        self.limit(&path, method, limits, base_key, true).await
+    }
+
+    /// Check without recording, matching routes by the request method too.
+    pub async fn check_with_method<R>(&self, path: &str, request: &R) -> Result<Decision>
+    where
+        K: Key<R>,
+        R: HasMethod,
+    {
+        let path = self.normalize_path(path);
+        let method = Some(request.method());
+        let limits = self.resolve(method, &path);
+        if limits.is_empty() {
+            return Ok(unlimited());
+        }
+
+        let base_key = self.key_extractor.try_extract(request);
+        self.limit(&path, method, limits, base_key, false).await
+    }
+
+    /// Check and record a request, extracting its key with an [`AsyncKey`].
+    ///
+    /// The key is only extracted for routes with a quota.
+    pub async fn check_and_record_async<R>(&self, path: &str, request: &R) -> Result<Decision>
+    where
+        K: AsyncKey<R>,
+    {
+        let path = self.normalize_path(path);
+        let limits = self.resolve(None, &path);
+        if limits.is_empty() {
+            return Ok(unlimited());
+        }
+
+        let base_key = AsyncKey::try_extract(&self.key_extractor, request).await;
+        self.limit(&path, None, limits, base_key, true).await
+    }
+
+    /// Check without recording, extracting the key with an [`AsyncKey`].
+    pub async fn check_async<R>(&self, path: &str, request: &R) -> Result<Decision>
+    where
+        K: AsyncKey<R>,
+    {
+        let path = self.normalize_path(path);
+        let limits = self.resolve(None, &path);
+        if limits.is_empty() {
+            return Ok(unlimited());
+        }
+
+        let base_key = AsyncKey::try_extract(&self.key_extractor, request).await;
+        self.limit(&path, None, limits, base_key, false).await
+    }
+
+    /// Check and record a request, matching routes by its method too and
+    /// extracting its key with an [`AsyncKey`].
+    pub async fn check_and_record_with_method_async<R>(
+        &self,
+        path: &str,
+        request: &R,
+    ) -> Result<Decision>
+    where
+        K: AsyncKey<R>,
+        R: HasMethod,
+    {
+        let path = self.normalize_path(path);
+        let method = Some(request.method());
+        let limits = self.resolve(method, &path);
+        if limits.is_empty() {
+            return Ok(unlimited());
+        }
+
+        let base_key = AsyncKey::try_extract(&self.key_extractor, request).await;
+        self.limit(&path, method, limits, base_key, true).await
+    }
+
+    /// Check without recording, matching routes by the request method too
+    /// and extracting the key with an [`AsyncKey`].
+    pub async fn check_with_method_async<R>(&self, path: &str, request: &R) -> Result<Decision>
+    where
+        K: AsyncKey<R>,
+        R: HasMethod,
+    {
+        let path = self.normalize_path(path);
+        let method = Some(request.method());
+        let limits = self.resolve(method, &path);
+        if limits.is_empty() {
+            return Ok(unlimited());
+        }
+
+        let base_key = AsyncKey::try_extract(&self.key_extractor, request).await;
+        self.limit(&path, method, limits, base_key, false).await
+    }
+
+    /// Find the limits for a request method (if known) and (normalized)
+    /// path.
+    fn resolve<'a>(&'a self, method: Option<&str>, path: &'a str) -> Limits<'a> {
+        let config = self.get_config(method, path);
+        let own = config
+            .map(|c| &c.quota)
+            .or(self.default_quota.as_ref())
+            .map(|quota| Limit {
+                path: Cow::Borrowed(path),
+                config,
+                quota,
+                enforce: true,
+            });
+
+        // Walk up the path prefixes, nearest parent first
+        let mut inheritance = config.map_or(Inheritance::Inherit, |c| c.inheritance);
+        let mut parents = Vec::new();
+        let segments: Vec<&str> = path.split('/').filter(|s| !s.is_empty()).collect();
+        for len in (0..segments.len()).rev() {
+            if inheritance == Inheritance::OptOut {
+                break;
+            }
+
+            let prefix = format!("/{}", segments[..len].join("/"));
+            let Some((_, parent)) = self.routes.get(&prefix).and_then(|routes| select(routes, method))
+            else {
+                continue;
+            };
+            parents.push(Limit {
+                path: Cow::Owned(prefix),
+                config: Some(parent),
+                quota: &parent.quota,
+                enforce: inheritance == Inheritance::Inherit,
+            });
+            if parent.inheritance != Inheritance::Inherit {
+                inheritance = parent.inheritance;
+            }
+        }
+
+        Limits { own, parents }
+    }
+
+    /// Check (and record, if `record`) a request, applying the missing key
+    /// policy if extraction failed.
+    async fn limit(
+        &self,
+        path: &str,
+        method: Option<&str>,
+        mut limits: Limits<'_>,
+        base_key: std::result::Result<String, KeyError>,
+        record: bool,
+    ) -> Result<Decision> {
+        let (base_key, key_error) = match base_key {
+            Ok(base_key) => (base_key, None),
+            Err(error) => {
+                tracing::debug!(%error, path, "key extraction failed");
+                match &self.missing_key_policy {
+                    MissingKeyPolicy::Deny => return Err(error.into()),
+                    MissingKeyPolicy::Allow => return Ok(with_key_error(unlimited(), error)),
+                    MissingKeyPolicy::SharedBucket => ("unknown".to_string(), Some(error)),
+                    MissingKeyPolicy::Quota(missing) => {
+                        let config = limits.own.take().and_then(|own| own.config);
+                        limits.own = Some(Limit {
+                            path: Cow::Borrowed(path),
+                            config,
+                            quota: missing,
+                            enforce: true,
+                        });
+                        ("unknown".to_string(), Some(error))
+                    }
+                }
+            }
+        };
+
+        let limits: Vec<(Limit<'_>, String)> = limits
+            .own
+            .into_iter()
+            .chain(limits.parents)
+            .filter(|limit| record || limit.enforce)
+            .map(|limit| {
+                let key = self.build_key(&limit.path, method, limit.config, base_key.clone());
+                (limit, key)
+            })
+            .collect();
+
+        // With parents, peek first so a request denied by one limit doesn't
+        // consume from the others
+        if record && limits.len() > 1 {
+            let mut denied = Vec::new();
+            for (limit, key) in limits.iter().filter(|(limit, _)| limit.enforce) {
+                let decision = self.algorithm.check(&*self.storage, key, limit.quota).await?;
+                if decision.is_denied() {
+                    denied.push((limit.path.as_ref(), decision));
+                }
+            }
+            if !denied.is_empty() {
+                return Ok(finish(tightest(denied, true), key_error));
+            }
+        }
+
+        let mut decisions = Vec::with_capacity(limits.len());
+        for (limit, key) in &limits {
+            let decision = if record {
+                self.record(path, key, limit.quota).await?
+            } else {
+                self.algorithm.check(&*self.storage, key, limit.quota).await?
+            };
+            if limit.enforce {
+                decisions.push((limit.path.as_ref(), decision));
+            }
+        }
+
+        Ok(finish(tightest(decisions, limits.len() > 1), key_error))
+    }
+
+    /// Record a request under `key`.
+    async fn record(&self, path: &str, key: &str, quota: &Quota) -> Result<Decision> {
+        let decision = self
+            .algorithm
+            .check_and_record(&*self.storage, key, quota)
+            .await?;
+        if decision.is_denied() {
+            tracing::debug!(key = %redact(key), path, "rate limit exceeded");
+        }
+        Ok(decision)
+    }
+
+    /// Build the storage key from the extracted base key.
+    fn build_key(
+        &self,
+        path: &str,
+        method: Option<&str>,
+        config: Option<&RouteConfig>,
+        base_key: String,
+    ) -> String {
+        #[cfg(feature = "hashing")]
+        let base_key = match &self.key_hasher {
+            Some(hasher) => hasher.hash(&base_key),
+            None => base_key,
+        };
+
+        let suffix = config.and_then(|c| c.key_suffix.as_deref()).unwrap_or(path);
+        match method.filter(|_| config.is_some_and(|c| c.method_in_key)) {
+            Some(method) => format!("{}:{}:{}", base_key, method.to_ascii_uppercase(), suffix),
+            None => format!("{}:{}", base_key, suffix),
+        }
+    }
+
+    /// Normalize a request path for route lookup and keys.
+    fn normalize_path<'p>(&self, path: &'p str) -> Cow<'p, str> {
+        #[cfg(feature = "path-templates")]
+        if let Some(normalizer) = &self.path_normalizer {
+            return Cow::Owned(normalizer.normalize(path));
+        }
+        Cow::Borrowed(path)
+    }
+
+    /// Get the configuration for a request method (if known) and path.
+    fn get_config(&self, method: Option<&str>, path: &str) -> Option<&RouteConfig> {
+        // Exact match first, then the most specific pattern
+        self.routes
+            .get(path)
+            .and_then(|routes| select(routes, method))
+            .or_else(|| self.patterns.find(path, |routes| select(routes, method)))
+            .map(|(_, config)| config)
+    }
+
+    /// Reset rate limit for a specific key.
+    ///
+    /// `key` is the storage key (`"{extracted key}:{route}"`); with
+    /// [`hash_keys`](RateLimitManagerBuilder::hash_keys) the extracted part
+    /// is hashed.
+    pub async fn reset(&self, key: &str) -> Result<()> {
+        self.algorithm.reset(&*self.storage, key).await
+    }
+}
+
+/// Parse a route's methods, keying routes for specific methods by method.
+fn method_config(method: &str, mut config: RouteConfig) -> (Methods, RouteConfig) {
+    let methods = Methods::parse(method);
+    if methods != Methods::Any {
+        config.method_in_key = true;
+    }
+    (methods, config)
+}
+
+/// Select the route for `method` among routes for the same path or pattern,
+/// preferring routes for specific methods.
+fn select<'a>(routes: &'a [(Methods, RouteConfig)], method: Option<&str>) -> Option<&'a (Methods, RouteConfig)> {
+    routes
+        .iter()
+        .find(|(methods, _)| *methods != Methods::Any && methods.matches(method))
+        .or_else(|| routes.iter().find(|(methods, _)| *methods == Methods::Any))
+}
+
+/// Combine the decisions of a request's limits into the tightest one.
+///
+/// With `name_route`, the metadata names the route of the limit.
+fn tightest(decisions: Vec<(&str, Decision)>, name_route: bool) -> Decision {
+    let Some((route, decision)) = crate::decision::tightest(decisions) else {
+        return unlimited();
+    };
+
+    if name_route {
+        decision.map_metadata(|metadata| metadata.with_route(route))
+    } else {
+        decision
+    }
+}
+
+/// Attach a key extraction failure, if any, to a decision.
+fn finish(decision: Decision, key_error: Option<KeyError>) -> Decision {
+    match key_error {
+        Some(error) => with_key_error(decision, error),
+        None => decision,
+    }
+}
+
+/// Attach a key extraction failure to a decision's metadata.
+fn with_key_error(decision: Decision, error: KeyError) -> Decision {
+    decision.map_metadata(|metadata| metadata.with_key_error(error))
+}
+
+/// Builder for RateLimitManager.
+pub struct RateLimitManagerBuilder<K> {
+    default_quota: Option<Quota>,
+    routes: HashMap<String, Vec<(Methods, RouteConfig)>>,
+    patterns: RouteTree<(Methods, RouteConfig)>,
+    key_extractor: Option<K>,
+    missing_key_policy: MissingKeyPolicy,
+    #[cfg(feature = "hashing")]
+    key_hasher: Option<KeyHasher>,
+    #[cfg(feature = "path-templates")]
+    path_normalizer: Option<PathNormalizer>,
+}
+
+impl<K> Default for RateLimitManagerBuilder<K> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<K> RateLimitManagerBuilder<K> {
+    /// Create a new builder.
+    pub fn new() -> Self {
+        Self {
+            default_quota: None,
+            routes: HashMap::new(),
+            patterns: RouteTree::new(),
+            key_extractor: None,
+            missing_key_policy: MissingKeyPolicy::default(),
+            #[cfg(feature = "hashing")]
+            key_hasher: None,
+            #[cfg(feature = "path-templates")]
+            path_normalizer: None,
+        }
+    }
+
+    /// Set the default quota for routes without specific configuration.
+    pub fn default_quota(mut self, quota: Quota) -> Self {
+        self.default_quota = Some(quota);
+        self
+    }
+
+    /// Add a rate limit for a specific route.
+    ///
+    /// Requests below the route's path must satisfy its limit too, unless
+    /// their route opts out (see [`Inheritance`]).
+    pub fn route(self, path: impl Into<String>, config: impl Into<RouteConfig>) -> Self {
+        self.method_route("*", path, config)
+    }
+
+    /// Add a rate limit for a route pattern.
+    ///
+    /// Patterns support `*`, `{param}` or `:param` for a single segment,
+    /// `{*rest}` or a trailing `**` for the rest of the path, and `**`
+    /// mid-path for any number of segments. Exact routes take precedence,
+    /// then the most specific pattern: segment by segment, literals beat
+    /// parameters, which beat `**`, which beats a catch-all.
+    pub fn route_pattern(
+        self,
+        pattern: impl Into<String>,
+        config: impl Into<RouteConfig>,
+    ) -> Self {
+        self.method_route_pattern("*", pattern, config)
+    }
+
+    /// Add a rate limit for a route and request method.
+    ///
+    /// `method` is a method such as `POST`, several separated by `|`
+    /// (`GET|HEAD`), or `*` for any. For an exact path, routes for specific
+    /// methods take precedence over `*`. Routes for specific methods key by
+    /// method (see [`RouteConfig::with_method_in_key`]) and only match
+    /// through the `check*_with_method` methods.
+    pub fn method_route(
+        mut self,
+        method: &str,
+        path: impl Into<String>,
+        config: impl Into<RouteConfig>,
+    ) -> Self {
+        let (methods, config) = method_config(method, config.into());
+        let routes = self.routes.entry(path.into()).or_default();
+        routes.retain(|(m, _)| *m != methods);
+        routes.push((methods, config));
+        self
+    }
+
+    /// Add a rate limit for a route pattern and request method.
+    ///
+    /// See [`method_route`](Self::method_route) and
+    /// [`route_pattern`](Self::route_pattern).
+    pub fn method_route_pattern(
+        mut self,
+        method: &str,
+        pattern: impl Into<String>,
+        config: impl Into<RouteConfig>,
+    ) -> Self {
+        let (methods, config) = method_config(method, config.into());
+        self.patterns.insert(&pattern.into(), (methods, config));
+        self
+    }
+
+    /// Set the key extractor.
+    pub fn key_extractor(mut self, extractor: K) -> Self {
+        self.key_extractor = Some(extractor);
+        self
+    }
+
+    /// Set what to do with requests whose key can't be extracted.
+    pub fn missing_key_policy(mut self, policy: MissingKeyPolicy) -> Self {
+        self.missing_key_policy = policy;
+        self
+    }
+
+    /// Hash every extracted key with `hasher` before it reaches storage.
+    ///
+    /// Use this when keys embed secrets such as API keys or bearer tokens;
+    /// see [`HashedKey`](crate::key::HashedKey) to hash a single extractor.
+    #[cfg(feature = "hashing")]
+    pub fn hash_keys(mut self, hasher: KeyHasher) -> Self {
+        self.key_hasher = Some(hasher);
+        self
+    }
+
+    /// Normalize request paths to route templates before route lookup and
+    /// key building.
+    ///
+    /// Routes are then registered by template (`.route("/users/{id}", ..)`)
+    /// and all ids of a route share one key.
+    #[cfg(feature = "path-templates")]
+    pub fn normalize_paths(mut self, normalizer: PathNormalizer) -> Self {
+        self.path_normalizer = Some(normalizer);
+        self
+    }
+
+    /// Build the manager with the given algorithm and storage.
+    pub fn build<A, S>(self, algorithm: A, storage: S) -> RateLimitManager<A, S, K>
+    where
+        K: Default,
+    {
+        RateLimitManager {
+            algorithm,
+            storage: Arc::new(storage),
+            key_extractor: self.key_extractor.unwrap_or_default(),
+            default_quota: self.default_quota,
+            routes: self.routes,
+            patterns: self.patterns,
+            missing_key_policy: self.missing_key_policy,
+            #[cfg(feature = "hashing")]
+            key_hasher: self.key_hasher,
+            #[cfg(feature = "path-templates")]
+            path_normalizer: self.path_normalizer,
+        }
+    }
+
+    /// Build the manager with a specific key extractor.
+    pub fn build_with_key<A, S>(
+        self,
+        algorithm: A,
+        storage: S,
+        key_extractor: K,
+    ) -> RateLimitManager<A, S, K> {
+        RateLimitManager {
+            algorithm,
+            storage: Arc::new(storage),
+            key_extractor,
+            default_quota: self.default_quota,
+            routes: self.routes,
+            patterns: self.patterns,
+            missing_key_policy: self.missing_key_policy,
+            #[cfg(feature = "hashing")]
+            key_hasher: self.key_hasher,
+            #[cfg(feature = "path-templates")]
+            path_normalizer: self.path_normalizer,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pattern_matches(pattern: &str, path: &str) -> bool {
+        let mut tree = RouteTree::new();
+        tree.insert(pattern, ());
+        tree.find(path, |values| values.first()).is_some()
+    }
+
+    #[test]
+    fn test_pattern_matches_exact() {
+        assert!(pattern_matches("/api/users", "/api/users"));
+        assert!(!pattern_matches("/api/users", "/api/posts"));
+    }
+
+    #[test]
+    fn test_pattern_matches_single_wildcard() {
+        assert!(pattern_matches("/api/*/posts", "/api/users/posts"));
+        assert!(pattern_matches("/api/*/posts", "/api/admins/posts"));
+        assert!(!pattern_matches("/api/*/posts", "/api/users/comments"));
+    }
+
+    #[test]
+    fn test_pattern_matches_double_wildcard() {
+        assert!(pattern_matches("/api/**", "/api/users"));
+        assert!(pattern_matches("/api/**", "/api/users/123/posts"));
+        assert!(!pattern_matches("/api/**", "/v2/api/users"));
+    }
+
+    #[test]
+    fn test_pattern_matches_params_and_mid_path_glob() {
+        assert!(pattern_matches("/users/{id}", "/users/42"));
+        assert!(pattern_matches("/users/:id/posts", "/users/42/posts"));
+        assert!(pattern_matches("/files/{*path}", "/files/a/b"));
+        assert!(pattern_matches("/api/**/admin", "/api/v1/admin"));
+        assert!(!pattern_matches("/api/**/admin", "/api/v1/users"));
+    }
+
+    #[tokio::test]
+    async fn test_most_specific_pattern_wins() {
+        use crate::algorithm::FixedWindow;
+        use crate::key::StaticKey;
+        use crate::storage::MemoryStorage;
+
+        let manager = RateLimitManagerBuilder::new()
+            .route_pattern("/api/**", Quota::per_minute(100))
+            .route_pattern("/api/users/{id}", Quota::per_minute(20))
+            .route_pattern("/api/users/{id}/admin", Quota::per_minute(5))
+            .route("/api/users/me", Quota::per_minute(50))
+            .build_with_key(FixedWindow::new(), MemoryStorage::new(), StaticKey::new("client"));
+
+        let limit = |path: &'static str| {
+            let manager = &manager;
+            async move { manager.check(path, &()).await.unwrap().info().limit }
+        };
+        assert_eq!(limit("/api/users/me").await, 50);
+        assert_eq!(limit("/api/users/42").await, 20);
+        assert_eq!(limit("/api/users/42/admin").await, 5);
+        assert_eq!(limit("/api/orders").await, 100);
+        assert_eq!(limit("/health").await, u64::MAX);
+    }
+
+    #[cfg(feature = "hashing")]
+    #[tokio::test]
+    async fn test_hash_keys() {
+        use crate::algorithm::FixedWindow;
+        use crate::key::StaticKey;
+        use crate::storage::MemoryStorage;
+
+        let hasher = KeyHasher::new("secret");
+        let manager = RateLimitManagerBuilder::new()
+            .default_quota(Quota::per_minute(10))
+            .hash_keys(hasher.clone())
+            .build_with_key(FixedWindow::new(), MemoryStorage::new(), StaticKey::new("ip:10.0.0.1"));
+
+        manager.check_and_record("/api", &()).await.unwrap();
+
+        let key = format!("{}:/api", hasher.hash("ip:10.0.0.1"));
+        assert!(manager.storage.get(&key).await.unwrap().is_some());
+        assert!(manager.storage.get("ip:10.0.0.1:/api").await.unwrap().is_none());
+    }
+
+    #[cfg(feature = "path-templates")]
+    #[tokio::test]
+    async fn test_normalize_paths() {
+        use crate::algorithm::FixedWindow;
+        use crate::key::StaticKey;
+        use crate::storage::MemoryStorage;
+
+        let manager = RateLimitManagerBuilder::new()
+            .route("/users/{id}", Quota::per_minute(1))
+            .normalize_paths(PathNormalizer::new())
+            .build_with_key(FixedWindow::new(), MemoryStorage::new(), StaticKey::new("client"));
+
+        assert!(manager.check_and_record("/users/1", &()).await.unwrap().is_allowed());
+        assert!(manager.check_and_record("/users/2", &()).await.unwrap().is_denied());
+        assert!(manager.storage.get("client:/users/{id}").await.unwrap().is_some());
+
+        // Unmatched routes have no quota
+        assert!(manager.check_and_record("/posts/1", &()).await.unwrap().is_allowed());
+    }
+
+    #[tokio::test]
+    async fn test_async_key() {
+        use crate::algorithm::FixedWindow;
+        use crate::key::{LookupKey, StaticKey};
+        use crate::storage::MemoryStorage;
+
+        let key = LookupKey::new(StaticKey::new("sk_live_123"), |api_key: String| async move {
+            (api_key == "sk_live_123").then(|| "account:7".to_string())
+        });
+        let manager = RateLimitManagerBuilder::new()
+            .route("/api", Quota::per_minute(1))
+            .build_with_key(FixedWindow::new(), MemoryStorage::new(), key);
+
+        assert!(manager.check_async("/api", &()).await.unwrap().is_allowed());
+        assert!(manager.check_and_record_async("/api", &()).await.unwrap().is_allowed());
+        assert!(manager.check_and_record_async("/api", &()).await.unwrap().is_denied());
+        assert!(manager.storage.get("account:7:/api").await.unwrap().is_some());
+
+        // Routes without a quota skip the lookup
+        assert!(manager.check_and_record_async("/other", &()).await.unwrap().is_allowed());
+    }
+
+    #[tokio::test]
+    async fn test_missing_key_policy() {
+        use crate::algorithm::FixedWindow;
+        use crate::error::RateLimitError;
+        use crate::key::FnKey;
+        use crate::storage::MemoryStorage;
+
+        let manager = |policy| {
+            RateLimitManagerBuilder::new()
+                .route("/api", Quota::per_minute(1))
+                .missing_key_policy(policy)
+                .build_with_key(
+                    FixedWindow::new(),
+                    MemoryStorage::new(),
+                    FnKey::new("user", |_: &()| None),
+                )
+        };
+        let key_error = |decision: &Decision| {
+            decision.info().metadata.as_ref().and_then(|m| m.key_error.clone())
+        };
+        let missing = Some(KeyError::Missing("user".into()));
+
+        let denying = manager(MissingKeyPolicy::Deny);
+        let err = denying.check_and_record("/api", &()).await.unwrap_err();
+        assert!(matches!(err, RateLimitError::KeyExtraction(KeyError::Missing(_))));
+
+        let allowing = manager(MissingKeyPolicy::Allow);
+        for _ in 0..3 {
+            let decision = allowing.check_and_record("/api", &()).await.unwrap();
+            assert!(decision.is_allowed());
+            assert_eq!(key_error(&decision), missing);
+        }
+
+        let shared = manager(MissingKeyPolicy::SharedBucket);
+        assert!(shared.check_and_record("/api", &()).await.unwrap().is_allowed());
+        let decision = shared.check_and_record("/api", &()).await.unwrap();
+        assert!(decision.is_denied());
+        assert_eq!(key_error(&decision), missing);
+        assert!(shared.storage.get("unknown:/api").await.unwrap().is_some());
+
+        let separate = manager(MissingKeyPolicy::Quota(Quota::per_minute(2)));
+        assert!(separate.check_and_record("/api", &()).await.unwrap().is_allowed());
+        assert!(separate.check_and_record("/api", &()).await.unwrap().is_allowed());
+        assert!(separate.check_and_record("/api", &()).await.unwrap().is_denied());
+    }
+
+    #[tokio::test]
+    async fn test_quota_inheritance() {
+        use crate::algorithm::FixedWindow;
+        use crate::key::StaticKey;
+        use crate::storage::MemoryStorage;
+
+        let manager = RateLimitManagerBuilder::new()
+            .route("/api", Quota::per_minute(3))
+            .route("/api/search", Quota::per_minute(10))
+            .route(
+                "/api/bulk",
+                RouteConfig::new(Quota::per_minute(10)).with_inheritance(Inheritance::Override),
+            )
+            .route(
+                "/api/health",
+                RouteConfig::new(Quota::per_minute(10)).with_inheritance(Inheritance::OptOut),
+            )
+            .build_with_key(FixedWindow::new(), MemoryStorage::new(), StaticKey::new("client"));
+
+        // Both limits apply; the parent's is the tightest
+        let decision = manager.check_and_record("/api/search", &()).await.unwrap();
+        assert!(decision.is_allowed());
+        assert_eq!(decision.info().limit, 3);
+        assert_eq!(decision.info().metadata.as_ref().unwrap().route.as_deref(), Some("/api"));
+
+        // Overriding routes count toward the parent's budget
+        assert!(manager.check_and_record("/api/bulk", &()).await.unwrap().is_allowed());
+
+        // Routes without their own quota are limited by the parent
+        assert!(manager.check_and_record("/api/other", &()).await.unwrap().is_allowed());
+        let decision = manager.check_and_record("/api/search", &()).await.unwrap();
+        assert!(decision.is_denied());
+        assert_eq!(decision.info().metadata.as_ref().unwrap().route.as_deref(), Some("/api"));
+        assert!(manager.check_and_record("/api", &()).await.unwrap().is_denied());
+
+        // The denied request didn't consume from the child's bucket
+        let decision = manager
+            .algorithm
+            .check(&*manager.storage, "client:/api/search", &Quota::per_minute(10))
+            .await
+            .unwrap();
+        assert_eq!(decision.info().remaining, 9);
+
+        assert!(manager.check_and_record("/api/bulk", &()).await.unwrap().is_allowed());
+        assert!(manager.check_and_record("/api/health", &()).await.unwrap().is_allowed());
+    }
+
+    #[test]
+    fn test_route_config_from_quota() {
+        let config: RouteConfig = Quota::per_minute(60).into();
+        assert_eq!(config.quota.max_requests(), 60);
+        assert!(config.key_suffix.is_none());
+        assert!(!config.method_in_key);
+        assert_eq!(config.inheritance, Inheritance::Inherit);
+    }
+
+    #[test]
+    fn test_methods() {
+        assert_eq!(Methods::parse("*"), Methods::Any);
+        assert_eq!(Methods::parse(""), Methods::Any);
+        assert_eq!(Methods::parse("get | head"), Methods::Only(vec!["GET".into(), "HEAD".into()]));
+
+        assert!(Methods::parse("GET|HEAD").matches(Some("head")));
+        assert!(!Methods::parse("GET").matches(Some("POST")));
+        assert!(!Methods::parse("GET").matches(None));
+        assert!(Methods::Any.matches(None));
+    }
+
+    #[tokio::test]
+    async fn test_method_routes() {
+        use crate::algorithm::FixedWindow;
+        use crate::key::StaticKey;
+        use crate::storage::MemoryStorage;
+
+        struct Method(&'static str);
+
+        impl HasMethod for Method {
+            fn method(&self) -> &str {
+                self.0
+            }
+        }
+
+        let manager = RateLimitManagerBuilder::new()
+            .route("/orders", Quota::per_minute(3))
+            .method_route("POST", "/orders", Quota::per_minute(1))
+            .method_route_pattern("GET|HEAD", "/orders/*", Quota::per_minute(1))
+            .route_pattern(
+                "/orders/*",
+                RouteConfig::new(Quota::per_minute(5))
+                    .with_method_in_key()
+                    .with_inheritance(Inheritance::OptOut),
+            )
+            .build_with_key(FixedWindow::new(), MemoryStorage::new(), StaticKey::new("client"));
+
+        let post = Method("POST");
+        assert!(manager.check_and_record_with_method("/orders", &post).await.unwrap().is_allowed());
+        assert!(manager.check_and_record_with_method("/orders", &post).await.unwrap().is_denied());
+        assert!(manager.storage.get("client:POST:/orders").await.unwrap().is_some());
+
+        // GET falls back to the route for any method, with its own bucket
+        let get = Method("get");
+        let decision = manager.check_and_record_with_method("/orders", &get).await.unwrap();
+        assert_eq!(decision.info().limit, 3);
+        assert!(manager.storage.get("client:/orders").await.unwrap().is_some());
+
+        // Without a method only routes for any method match
+        assert_eq!(manager.check("/orders", &()).await.unwrap().info().limit, 3);
+
+        assert_eq!(manager.check_with_method("/orders/1", &get).await.unwrap().info().limit, 1);
+        let decision = manager.check_and_record_with_method("/orders/1", &Method("DELETE")).await.unwrap();
+        assert_eq!(decision.info().limit, 5);
+        assert!(manager.storage.get("client:DELETE:/orders/1").await.unwrap().is_some());
+        assert_eq!(manager.check("/orders/1", &()).await.unwrap().info().limit, 5);
+    }
+}
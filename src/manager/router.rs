@@ -0,0 +1,254 @@
+//! Compiled route pattern matching.
+//!
+//! Patterns are compiled into a tree of path segments, so a lookup walks the
+//! path instead of trying every pattern in turn.
+
+use std::collections::{HashMap, HashSet};
+
+/// A parsed pattern segment.
+enum Segment<'a> {
+    /// A literal segment.
+    Static(&'a str),
+    /// `*`, `{name}` or `:name`: exactly one segment.
+    Param,
+    /// `**` or `{*name}`: any number of segments.
+    Rest,
+}
+
+impl<'a> Segment<'a> {
+    fn parse(segment: &'a str) -> Self {
+        match segment {
+            "*" => Self::Param,
+            "**" => Self::Rest,
+            _ if segment.starts_with(':') && segment.len() > 1 => Self::Param,
+            _ => match segment.strip_prefix('{').and_then(|s| s.strip_suffix('}')) {
+                Some(name) if name.starts_with('*') => Self::Rest,
+                Some(name) if !name.is_empty() => Self::Param,
+                _ => Self::Static(segment),
+            },
+        }
+    }
+}
+
+/// A node of the route tree, reached by the segments of a pattern prefix.
+struct Node<T> {
+    /// Values of patterns ending here.
+    values: Vec<T>,
+    /// Children by literal segment.
+    statics: HashMap<String, Node<T>>,
+    /// Child for a one-segment parameter.
+    param: Option<Box<Node<T>>>,
+    /// Child for a mid-path `**`, after zero or more segments.
+    glob: Option<Box<Node<T>>>,
+    /// Values of patterns ending in a catch-all here.
+    rest: Vec<T>,
+}
+
+impl<T> Default for Node<T> {
+    fn default() -> Self {
+        Self {
+            values: Vec::new(),
+            statics: HashMap::new(),
+            param: None,
+            glob: None,
+            rest: Vec::new(),
+        }
+    }
+}
+
+/// Nodes that matched nothing for a number of remaining segments.
+///
+/// Globs can reach the same node with the same remaining segments along
+/// many paths; remembering failures keeps a lookup polynomial in the path
+/// length instead of exponential in the number of globs.
+type Failures<T> = HashSet<(*const Node<T>, usize)>;
+
+impl<T> Node<T> {
+    fn find<'a, F>(&'a self, segments: &[&str], pick: &mut F, failures: &mut Failures<T>) -> Option<&'a T>
+    where
+        F: FnMut(&'a [T]) -> Option<&'a T>,
+    {
+        let id = (self as *const Self, segments.len());
+        if failures.contains(&id) {
+            return None;
+        }
+        let found = self.find_uncached(segments, pick, failures);
+        if found.is_none() {
+            failures.insert(id);
+        }
+        found
+    }
+
+    fn find_uncached<'a, F>(&'a self, segments: &[&str], pick: &mut F, failures: &mut Failures<T>) -> Option<&'a T>
+    where
+        F: FnMut(&'a [T]) -> Option<&'a T>,
+    {
+        match segments.split_first() {
+            None => {
+                if let Some(value) = pick(&self.values) {
+                    return Some(value);
+                }
+            }
+            Some((segment, rest)) => {
+                if let Some(value) = self.statics.get(*segment).and_then(|n| n.find(rest, pick, failures)) {
+                    return Some(value);
+                }
+                if let Some(value) = self.param.as_ref().and_then(|n| n.find(rest, pick, failures)) {
+                    return Some(value);
+                }
+            }
+        }
+
+        // Globs skipping fewer segments are more specific
+        if let Some(glob) = &self.glob {
+            for skip in 0..=segments.len() {
+                if let Some(value) = glob.find(&segments[skip..], pick, failures) {
+                    return Some(value);
+                }
+            }
+        }
+
+        if segments.is_empty() {
+            None
+        } else {
+            pick(&self.rest)
+        }
+    }
+}
+
+/// Route patterns compiled into a segment tree.
+///
+/// Pattern syntax, matched per `/`-separated segment (empty segments are
+/// ignored):
+///
+/// | Segment | Matches |
+/// |---------|---------|
+/// | `users` | That literal segment |
+/// | `*`, `{id}`, `:id` | Any one segment |
+/// | `**`, `{*rest}` at the end | The rest of the path, at least one segment |
+/// | `**` elsewhere | Zero or more segments |
+///
+/// When several patterns match, the most specific wins: comparing segment
+/// by segment, a literal beats a parameter, which beats `**`, which beats a
+/// catch-all. Registration order only breaks ties between identical
+/// patterns.
+pub(crate) struct RouteTree<T> {
+    root: Node<T>,
+}
+
+impl<T> Default for RouteTree<T> {
+    fn default() -> Self {
+        Self {
+            root: Node::default(),
+        }
+    }
+}
+
+impl<T> RouteTree<T> {
+    /// Create an empty tree.
+    pub(crate) fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a pattern.
+    pub(crate) fn insert(&mut self, pattern: &str, value: T) {
+        let segments: Vec<&str> = pattern.split('/').filter(|s| !s.is_empty()).collect();
+        let mut node = &mut self.root;
+
+        for (i, segment) in segments.iter().enumerate() {
+            node = match Segment::parse(segment) {
+                Segment::Static(literal) => node.statics.entry(literal.to_string()).or_default(),
+                Segment::Param => node.param.get_or_insert_with(Box::default),
+                Segment::Rest if i + 1 == segments.len() => {
+                    node.rest.push(value);
+                    return;
+                }
+                Segment::Rest => node.glob.get_or_insert_with(Box::default),
+            };
+        }
+        node.values.push(value);
+    }
+
+    /// Find the most specific pattern matching `path`.
+    ///
+    /// `pick` chooses among the values of equally specific patterns, or
+    /// returns `None` to fall back to less specific ones. It must give the
+    /// same answer for the same values, since failed subtrees are not
+    /// searched again.
+    pub(crate) fn find<'a, F>(&'a self, path: &str, mut pick: F) -> Option<&'a T>
+    where
+        F: FnMut(&'a [T]) -> Option<&'a T>,
+    {
+        let segments: Vec<&str> = path.split('/').filter(|s| !s.is_empty()).collect();
+        self.root.find(&segments, &mut pick, &mut HashSet::new())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tree(patterns: &[&'static str]) -> RouteTree<&'static str> {
+        let mut tree = RouteTree::new();
+        for pattern in patterns {
+            tree.insert(pattern, *pattern);
+        }
+        tree
+    }
+
+    fn first<'a>(values: &'a [&'static str]) -> Option<&'a &'static str> {
+        values.first()
+    }
+
+    #[test]
+    fn test_param_syntax() {
+        let tree = tree(&["/users/{id}/posts", "/orgs/:org", "/files/{*path}"]);
+
+        assert_eq!(tree.find("/users/42/posts", first), Some(&"/users/{id}/posts"));
+        assert_eq!(tree.find("/orgs/acme/", first), Some(&"/orgs/:org"));
+        assert_eq!(tree.find("/files/a/b/c", first), Some(&"/files/{*path}"));
+        assert_eq!(tree.find("/files", first), None);
+        assert_eq!(tree.find("/users/42", first), None);
+    }
+
+    #[test]
+    fn test_mid_path_glob() {
+        let tree = tree(&["/api/**/admin"]);
+
+        assert_eq!(tree.find("/api/admin", first), Some(&"/api/**/admin"));
+        assert_eq!(tree.find("/api/v1/tenants/7/admin", first), Some(&"/api/**/admin"));
+        assert_eq!(tree.find("/api/users", first), None);
+        assert_eq!(tree.find("/api/admin/users", first), None);
+    }
+
+    #[test]
+    fn test_many_globs_stay_fast() {
+        let tree = tree(&["/**/a/**/a/**/a/**/a/**/a/**/a/**/a/**/z"]);
+        let path = "/a".repeat(200);
+
+        // Exponential without remembering failed subtrees
+        assert_eq!(tree.find(&path, first), None);
+        assert!(tree.find(&format!("{path}/z"), first).is_some());
+    }
+
+    #[test]
+    fn test_most_specific_wins() {
+        // Registered from least to most specific
+        let tree = tree(&["/api/**", "/api/**/admin", "/api/*/admin", "/api/users/*", "/api/users/me"]);
+
+        assert_eq!(tree.find("/api/users/me", first), Some(&"/api/users/me"));
+        assert_eq!(tree.find("/api/users/42", first), Some(&"/api/users/*"));
+        assert_eq!(tree.find("/api/users/admin", first), Some(&"/api/users/*"));
+        assert_eq!(tree.find("/api/teams/admin", first), Some(&"/api/*/admin"));
+        assert_eq!(tree.find("/api/a/b/admin", first), Some(&"/api/**/admin"));
+        assert_eq!(tree.find("/api/a/b", first), Some(&"/api/**"));
+    }
+
+    #[test]
+    fn test_pick_falls_back() {
+        let tree = tree(&["/api/**", "/api/users"]);
+
+        let found = tree.find("/api/users", |values| values.iter().find(|v| v.ends_with("**")));
+        assert_eq!(found, Some(&"/api/**"));
+    }
+}
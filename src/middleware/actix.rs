@@ -26,29 +26,54 @@
 //! }
 //! ```
 
+use std::borrow::Cow;
 use std::future::{ready, Future, Ready};
+use std::net::{IpAddr, SocketAddr};
 use std::pin::Pin;
+use std::rc::Rc;
 use std::sync::Arc;
 use std::task::{Context, Poll};
 
 use actix_service::{Service, Transform};
 use actix_web::{
     body::EitherBody,
-    dev::{ServiceRequest, ServiceResponse},
-    http::StatusCode,
+    dev::{Path, ServiceRequest, ServiceResponse, Url},
+    http::{header::HeaderMap, Method, StatusCode, Uri},
     Error, HttpResponse,
 };
 
+use crate::adaptive::AdaptiveController;
 use crate::algorithm::Algorithm;
 use crate::decision::Decision;
+use crate::error::KeyError;
+use crate::key::{
+    join_header_values, parse_cookie, parse_query, redact, AsyncKey, HasCookies, HasHeaders, HasIpAddr,
+    HasMethod, HasPath, HasPathParams, HasQuery, IpKey, Key, TrustedProxies,
+};
+#[cfg(feature = "hashing")]
+use crate::key::KeyHasher;
+use crate::manager::MissingKeyPolicy;
 use crate::quota::Quota;
 use crate::storage::Storage;
 
+use super::{AsyncKeyed, MiddlewareKey, MissingKeyLog};
+
 /// Rate limiter middleware for Actix-web.
-pub struct RateLimiter<S, A> {
+///
+/// Requests are keyed by client IP (`IpKey`) unless another extractor is set
+/// with [`with_key`](Self::with_key). Requests without a key are handled by
+/// the [`MissingKeyPolicy`]; the first one is logged as a warning.
+pub struct RateLimiter<S, A, K = IpKey> {
     storage: Arc<S>,
     algorithm: A,
     quota: Quota,
+    key_extractor: Arc<K>,
+    proxies: Arc<TrustedProxies>,
+    adaptive: Option<AdaptiveController>,
+    missing_key_policy: Arc<MissingKeyPolicy>,
+    missing_key_log: Arc<MissingKeyLog>,
+    #[cfg(feature = "hashing")]
+    key_hasher: Option<KeyHasher>,
 }
 
 impl<S, A> RateLimiter<S, A>
@@ -62,11 +87,68 @@ where
             storage: Arc::new(storage),
             algorithm,
             quota,
+            key_extractor: Arc::new(IpKey::new()),
+            proxies: Arc::new(TrustedProxies::none()),
+            adaptive: None,
+            missing_key_policy: Arc::default(),
+            missing_key_log: Arc::default(),
+            #[cfg(feature = "hashing")]
+            key_hasher: None,
         }
     }
 }
 
-impl<S, A> Clone for RateLimiter<S, A>
+impl<S, A, K> RateLimiter<S, A, K> {
+    /// Use `key_extractor` to build rate limiting keys.
+    pub fn with_key<K2>(self, key_extractor: K2) -> RateLimiter<S, A, K2> {
+        RateLimiter {
+            storage: self.storage,
+            algorithm: self.algorithm,
+            quota: self.quota,
+            key_extractor: Arc::new(key_extractor),
+            proxies: self.proxies,
+            adaptive: self.adaptive,
+            missing_key_policy: self.missing_key_policy,
+            missing_key_log: self.missing_key_log,
+            #[cfg(feature = "hashing")]
+            key_hasher: self.key_hasher,
+        }
+    }
+
+    /// Resolve client addresses behind `proxies`.
+    ///
+    /// By default forwarding headers are ignored and the client is the
+    /// connected peer.
+    pub fn with_trusted_proxies(mut self, proxies: TrustedProxies) -> Self {
+        self.proxies = Arc::new(proxies);
+        self
+    }
+
+    /// Scale the quota with `controller`, which observes the latency and
+    /// status of every response from the wrapped service.
+    pub fn with_adaptive(mut self, controller: AdaptiveController) -> Self {
+        self.adaptive = Some(controller);
+        self
+    }
+
+    /// Set what happens to requests whose key can't be extracted.
+    ///
+    /// Defaults to [`MissingKeyPolicy::SharedBucket`]: such requests share
+    /// one `"unknown"` bucket. `Deny` rejects them with 400 Bad Request.
+    pub fn with_missing_key_policy(mut self, policy: MissingKeyPolicy) -> Self {
+        self.missing_key_policy = Arc::new(policy);
+        self
+    }
+
+    /// Hash every extracted key with `hasher` before it reaches storage.
+    #[cfg(feature = "hashing")]
+    pub fn with_key_hasher(mut self, hasher: KeyHasher) -> Self {
+        self.key_hasher = Some(hasher);
+        self
+    }
+}
+
+impl<S, A, K> Clone for RateLimiter<S, A, K>
 where
     A: Clone,
 {
@@ -75,46 +157,180 @@ where
             storage: self.storage.clone(),
             algorithm: self.algorithm.clone(),
             quota: self.quota.clone(),
+            key_extractor: self.key_extractor.clone(),
+            proxies: self.proxies.clone(),
+            adaptive: self.adaptive.clone(),
+            missing_key_policy: self.missing_key_policy.clone(),
+            missing_key_log: self.missing_key_log.clone(),
+            #[cfg(feature = "hashing")]
+            key_hasher: self.key_hasher.clone(),
         }
     }
 }
 
-impl<S, A, Svc, B> Transform<Svc, ServiceRequest> for RateLimiter<S, A>
+impl<S, A, K, Svc, B> Transform<Svc, ServiceRequest> for RateLimiter<S, A, K>
 where
     S: Storage + Send + Sync + 'static,
     A: Algorithm + Clone + Send + Sync + 'static,
+    K: for<'a> MiddlewareKey<ActixRequest<'a>>,
     Svc: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
     Svc::Future: 'static,
     B: 'static,
 {
     type Response = ServiceResponse<EitherBody<B>>;
     type Error = Error;
-    type Transform = RateLimiterMiddleware<S, A, Svc>;
+    type Transform = RateLimiterMiddleware<S, A, Svc, K>;
     type InitError = ();
     type Future = Ready<Result<Self::Transform, Self::InitError>>;
 
     fn new_transform(&self, service: Svc) -> Self::Future {
         ready(Ok(RateLimiterMiddleware {
-            service,
+            service: Rc::new(service),
             storage: self.storage.clone(),
             algorithm: self.algorithm.clone(),
             quota: self.quota.clone(),
+            key_extractor: self.key_extractor.clone(),
+            proxies: self.proxies.clone(),
+            adaptive: self.adaptive.clone(),
+            missing_key_policy: self.missing_key_policy.clone(),
+            missing_key_log: self.missing_key_log.clone(),
+            #[cfg(feature = "hashing")]
+            key_hasher: self.key_hasher.clone(),
         }))
     }
 }
 
 /// The actual middleware service.
-pub struct RateLimiterMiddleware<S, A, Svc> {
-    service: Svc,
+pub struct RateLimiterMiddleware<S, A, Svc, K = IpKey> {
+    service: Rc<Svc>,
     storage: Arc<S>,
     algorithm: A,
     quota: Quota,
+    key_extractor: Arc<K>,
+    proxies: Arc<TrustedProxies>,
+    adaptive: Option<AdaptiveController>,
+    missing_key_policy: Arc<MissingKeyPolicy>,
+    missing_key_log: Arc<MissingKeyLog>,
+    #[cfg(feature = "hashing")]
+    key_hasher: Option<KeyHasher>,
+}
+
+/// Wrapper around an Actix request for key extraction.
+///
+/// Only borrows the parts of the request used for keys, so it is `Sync`
+/// and async key extractors can hold it across `.await`s.
+pub struct ActixRequest<'a> {
+    method: &'a Method,
+    uri: &'a Uri,
+    headers: &'a HeaderMap,
+    match_info: &'a Path<Url>,
+    peer_addr: Option<SocketAddr>,
+    proxies: &'a TrustedProxies,
+}
+
+impl<'a> ActixRequest<'a> {
+    /// Wrap `request`, resolving its client address behind `proxies`.
+    pub fn new(request: &'a ServiceRequest, proxies: &'a TrustedProxies) -> Self {
+        Self {
+            method: request.method(),
+            uri: request.uri(),
+            headers: request.headers(),
+            match_info: request.match_info(),
+            peer_addr: request.peer_addr(),
+            proxies,
+        }
+    }
+}
+
+impl HasPath for ActixRequest<'_> {
+    fn path(&self) -> &str {
+        self.uri.path()
+    }
+}
+
+impl HasMethod for ActixRequest<'_> {
+    fn method(&self) -> &str {
+        self.method.as_str()
+    }
+}
+
+impl HasHeaders for ActixRequest<'_> {
+    fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .get(name)
+            .and_then(|v| v.to_str().ok())
+    }
+
+    fn header_all(&self, name: &str) -> Option<Cow<'_, str>> {
+        join_header_values(self.headers.get_all(name).map(|v| v.as_bytes()))
+    }
+}
+
+impl HasQuery for ActixRequest<'_> {
+    fn query_param(&self, name: &str) -> Option<String> {
+        parse_query(self.uri.query()?, name)
+    }
+}
+
+impl HasCookies for ActixRequest<'_> {
+    fn cookie(&self, name: &str) -> Option<&str> {
+        // HTTP/2 clients may split cookies across several headers
+        self.headers
+            .get_all(actix_web::http::header::COOKIE)
+            .filter_map(|v| v.to_str().ok())
+            .find_map(|v| parse_cookie(v, name))
+    }
+}
+
+impl HasPathParams for ActixRequest<'_> {
+    /// Only populated when the middleware wraps a scope or resource, since
+    /// app-level middleware runs before routing.
+    fn path_param(&self, name: &str) -> Option<String> {
+        self.match_info.get(name).map(str::to_owned)
+    }
+}
+
+impl HasIpAddr for ActixRequest<'_> {
+    fn client_ip(&self) -> Option<IpAddr> {
+        self.proxies.resolve(self.peer_ip(), self)
+    }
+
+    fn peer_ip(&self) -> Option<IpAddr> {
+        self.peer_addr.map(|addr| addr.ip())
+    }
 }
 
-impl<S, A, Svc, B> Service<ServiceRequest> for RateLimiterMiddleware<S, A, Svc>
+impl<'a, K> MiddlewareKey<ActixRequest<'a>> for K
+where
+    K: Key<ActixRequest<'a>>,
+{
+    fn extract_key(&self, request: &ActixRequest<'a>) -> impl Future<Output = Option<String>> + Send {
+        ready(self.extract(request))
+    }
+
+    fn try_extract_key(&self, request: &ActixRequest<'a>) -> impl Future<Output = Result<String, KeyError>> + Send {
+        ready(self.try_extract(request))
+    }
+}
+
+impl<'a, K> MiddlewareKey<ActixRequest<'a>> for AsyncKeyed<K>
+where
+    K: AsyncKey<ActixRequest<'a>>,
+{
+    fn extract_key(&self, request: &ActixRequest<'a>) -> impl Future<Output = Option<String>> + Send {
+        self.inner.extract(request)
+    }
+
+    fn try_extract_key(&self, request: &ActixRequest<'a>) -> impl Future<Output = Result<String, KeyError>> + Send {
+        self.inner.try_extract(request)
+    }
+}
+
+impl<S, A, K, Svc, B> Service<ServiceRequest> for RateLimiterMiddleware<S, A, Svc, K>
 where
     S: Storage + Send + Sync + 'static,
     A: Algorithm + Clone + Send + Sync + 'static,
+    K: for<'a> MiddlewareKey<ActixRequest<'a>>,
     Svc: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
     Svc::Future: 'static,
     B: 'static,
@@ -128,23 +344,67 @@ where
     }
 
     fn call(&self, req: ServiceRequest) -> Self::Future {
+        let service = self.service.clone();
         let storage = self.storage.clone();
         let algorithm = self.algorithm.clone();
-        let quota = self.quota.clone();
+        let quota = match &self.adaptive {
+            Some(controller) => controller.quota(&self.quota),
+            None => self.quota.clone(),
+        };
+        let key_extractor = self.key_extractor.clone();
+        let proxies = self.proxies.clone();
+        let mut adaptive = self.adaptive.clone();
+        let missing_key_policy = self.missing_key_policy.clone();
+        let missing_key_log = self.missing_key_log.clone();
+        #[cfg(feature = "hashing")]
+        let key_hasher = self.key_hasher.clone();
 
-        // Extract key from request
-        let key = extract_key(&req);
+        Box::pin(async move {
+            // Extract key from request
+            let key = key_extractor
+                .try_extract_key(&ActixRequest::new(&req, &proxies))
+                .await;
+            #[cfg(feature = "hashing")]
+            let key = match &key_hasher {
+                Some(hasher) => key.map(|key| hasher.hash(&key)),
+                None => key,
+            };
 
-        // We need to capture the service call
-        let fut = self.service.call(req);
+            let (key, quota) = match key {
+                Ok(key) => (key, quota),
+                Err(error) => {
+                    missing_key_log.report(&error, &missing_key_policy);
+                    // Only keyed requests are reported to the controller
+                    let controller = adaptive.take();
+                    match &*missing_key_policy {
+                        MissingKeyPolicy::Deny => {
+                            let response = HttpResponse::build(StatusCode::BAD_REQUEST)
+                                .insert_header(("Content-Type", "application/json"))
+                                .body(r#"{"error":"Bad Request","reason":"missing rate limit key"}"#);
+                            return Err(actix_web::error::InternalError::from_response("Missing rate limit key", response).into());
+                        }
+                        MissingKeyPolicy::Allow => {
+                            return Ok(service.call(req).await?.map_into_left_body());
+                        }
+                        MissingKeyPolicy::SharedBucket => ("unknown".to_string(), quota),
+                        MissingKeyPolicy::Quota(missing) => {
+                            let quota = match &controller {
+                                Some(controller) => controller.quota(missing),
+                                None => missing.clone(),
+                            };
+                            ("unknown".to_string(), quota)
+                        }
+                    }
+                }
+            };
 
-        Box::pin(async move {
             // Check rate limit
             let decision = algorithm
                 .check_and_record(&*storage, &key, &quota)
                 .await
-                .unwrap_or_else(|_| {
+                .unwrap_or_else(|e| {
                     // Fail open on errors
+                    tracing::warn!(key = %redact(&key), error = %e, "rate limit check failed, allowing request");
                     Decision::allowed(crate::decision::RateLimitInfo::new(
                         quota.max_requests(),
                         quota.max_requests(),
@@ -183,37 +443,31 @@ where
             }
 
             // Proceed with the request and add headers
-            let res = fut.await?;
+            let res = call_observed(&*service, req, adaptive.as_ref()).await?;
             Ok(res.map_into_left_body())
         })
     }
 }
 
-/// Extract a rate limiting key from the request.
-#[allow(clippy::collapsible_if)]
-fn extract_key(req: &ServiceRequest) -> String {
-    // Try to get client IP from various headers
-    if let Some(forwarded) = req.headers().get("x-forwarded-for") {
-        if let Ok(value) = forwarded.to_str() {
-            if let Some(ip) = value.split(',').next() {
-                return format!("ip:{}", ip.trim());
-            }
-        }
-    }
-
-    if let Some(real_ip) = req.headers().get("x-real-ip") {
-        if let Ok(value) = real_ip.to_str() {
-            return format!("ip:{}", value);
-        }
-    }
-
-    // Fall back to connection info
-    if let Some(peer) = req.connection_info().peer_addr() {
-        return format!("ip:{}", peer);
+/// Call the wrapped service, reporting the response to `adaptive`.
+async fn call_observed<Svc, B>(
+    service: &Svc,
+    req: ServiceRequest,
+    adaptive: Option<&AdaptiveController>,
+) -> Result<ServiceResponse<B>, Error>
+where
+    Svc: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error>,
+{
+    let start = tokio::time::Instant::now();
+    let result = service.call(req).await;
+    if let Some(controller) = adaptive {
+        let status = match &result {
+            Ok(res) => res.status(),
+            Err(e) => e.as_response_error().status_code(),
+        };
+        controller.record(status.as_u16(), start.elapsed());
     }
-
-    // Ultimate fallback
-    format!("path:{}", req.path())
+    result
 }
 
 #[cfg(test)]
@@ -230,4 +484,151 @@ mod tests {
 
         assert_eq!(limiter.quota.max_requests(), 10);
     }
+
+    #[actix_web::test]
+    async fn test_limits_by_resolved_client_ip() {
+        use crate::algorithm::FixedWindow;
+        use crate::storage::MemoryStorage;
+        use actix_web::{test, web, App};
+
+        let limiter = RateLimiter::new(MemoryStorage::new(), FixedWindow::new(), Quota::per_minute(1))
+            .with_trusted_proxies(TrustedProxies::networks(["10.0.0.0/8"]).unwrap());
+        let app = test::init_service(
+            App::new()
+                .wrap(limiter)
+                .route("/", web::get().to(|| async { "ok" })),
+        )
+        .await;
+
+        let request = |forwarded_for: &str| {
+            test::TestRequest::get()
+                .peer_addr("10.0.0.1:4000".parse().unwrap())
+                .insert_header(("x-forwarded-for", forwarded_for))
+                .to_request()
+        };
+
+        let response = test::try_call_service(&app, request("1.1.1.1")).await.unwrap();
+        assert!(response.status().is_success());
+
+        // Forging the left-most address doesn't get a fresh bucket
+        let error = test::try_call_service(&app, request("6.6.6.6, 1.1.1.1")).await.unwrap_err();
+        assert_eq!(error.as_response_error().status_code(), StatusCode::TOO_MANY_REQUESTS);
+
+        let response = test::try_call_service(&app, request("2.2.2.2")).await.unwrap();
+        assert!(response.status().is_success());
+
+        // A proxy adding its own line after the client's is read in full
+        let request = test::TestRequest::get()
+            .peer_addr("10.0.0.1:4000".parse().unwrap())
+            .append_header(("x-forwarded-for", "6.6.6.6"))
+            .append_header(("x-forwarded-for", "2.2.2.2"))
+            .to_request();
+        let error = test::try_call_service(&app, request).await.unwrap_err();
+        assert_eq!(error.as_response_error().status_code(), StatusCode::TOO_MANY_REQUESTS);
+    }
+
+    #[actix_web::test]
+    async fn test_reads_query_cookies_and_path_params() {
+        use crate::algorithm::FixedWindow;
+        use crate::key::{CookieKey, PathParamKey, QueryKey};
+        use crate::storage::MemoryStorage;
+        use actix_web::{test, web, App};
+
+        let request = test::TestRequest::get()
+            .uri("/?api_key=k%2B1")
+            .insert_header(("cookie", "a=1; session=s1"))
+            .to_srv_request();
+        let proxies = TrustedProxies::none();
+        let adapter = ActixRequest::new(&request, &proxies);
+        assert_eq!(QueryKey::api_key().extract(&adapter), Some("query:api_key:k+1".to_string()));
+        assert_eq!(CookieKey::new("session").extract(&adapter), Some("cookie:session:s1".to_string()));
+
+        let limiter = RateLimiter::new(MemoryStorage::new(), FixedWindow::new(), Quota::per_minute(1))
+            .with_key(PathParamKey::new("tenant_id"));
+        let app = test::init_service(
+            App::new().service(
+                web::scope("/tenants/{tenant_id}")
+                    .wrap(limiter)
+                    .route("/files", web::get().to(|| async { "ok" })),
+            ),
+        )
+        .await;
+
+        let request = |uri: &str| test::TestRequest::get().uri(uri).to_request();
+
+        let response = test::try_call_service(&app, request("/tenants/acme/files")).await.unwrap();
+        assert!(response.status().is_success());
+
+        let error = test::try_call_service(&app, request("/tenants/acme/files")).await.unwrap_err();
+        assert_eq!(error.as_response_error().status_code(), StatusCode::TOO_MANY_REQUESTS);
+
+        let response = test::try_call_service(&app, request("/tenants/other/files")).await.unwrap();
+        assert!(response.status().is_success());
+    }
+
+    #[actix_web::test]
+    async fn test_missing_key_policy() {
+        use crate::algorithm::FixedWindow;
+        use crate::key::HeaderKey;
+        use crate::storage::MemoryStorage;
+        use actix_web::{test, web, App};
+
+        let limiter = RateLimiter::new(MemoryStorage::new(), FixedWindow::new(), Quota::per_minute(1))
+            .with_key(HeaderKey::api_key());
+        let app = test::init_service(
+            App::new()
+                .wrap(limiter.clone())
+                .route("/", web::get().to(|| async { "ok" })),
+        )
+        .await;
+
+        // Requests without a key share one bucket by default
+        let response = test::try_call_service(&app, test::TestRequest::get().to_request()).await.unwrap();
+        assert!(response.status().is_success());
+        let error = test::try_call_service(&app, test::TestRequest::get().to_request()).await.unwrap_err();
+        assert_eq!(error.as_response_error().status_code(), StatusCode::TOO_MANY_REQUESTS);
+
+        let app = test::init_service(
+            App::new()
+                .wrap(limiter.with_missing_key_policy(MissingKeyPolicy::Deny))
+                .route("/", web::get().to(|| async { "ok" })),
+        )
+        .await;
+        let error = test::try_call_service(&app, test::TestRequest::get().to_request()).await.unwrap_err();
+        assert_eq!(error.as_response_error().status_code(), StatusCode::BAD_REQUEST);
+    }
+
+    #[actix_web::test]
+    async fn test_limits_by_async_key() {
+        use crate::algorithm::FixedWindow;
+        use crate::key::{HeaderKey, LookupKey};
+        use crate::storage::MemoryStorage;
+        use actix_web::{test, web, App};
+
+        // Both API keys belong to the same account
+        let lookup = LookupKey::new(HeaderKey::api_key(), |_: String| async {
+            tokio::task::yield_now().await;
+            Some("account:7".to_string())
+        });
+        let limiter = RateLimiter::new(MemoryStorage::new(), FixedWindow::new(), Quota::per_minute(1))
+            .with_key(AsyncKeyed::new(lookup));
+        let app = test::init_service(
+            App::new()
+                .wrap(limiter)
+                .route("/", web::get().to(|| async { "ok" })),
+        )
+        .await;
+
+        let request = |api_key: &str| {
+            test::TestRequest::get()
+                .insert_header(("x-api-key", api_key))
+                .to_request()
+        };
+
+        let response = test::try_call_service(&app, request("key-a")).await.unwrap();
+        assert!(response.status().is_success());
+
+        let error = test::try_call_service(&app, request("key-b")).await.unwrap_err();
+        assert_eq!(error.as_response_error().status_code(), StatusCode::TOO_MANY_REQUESTS);
+    }
 }
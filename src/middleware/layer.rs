@@ -1,30 +1,48 @@
 //! Tower layer for rate limiting in Axum.
 
+use std::borrow::Cow;
 use std::future::Future;
+use std::net::{IpAddr, SocketAddr};
 use std::pin::Pin;
 use std::sync::Arc;
 use std::task::{Context, Poll};
 
 use axum::{
     body::Body,
-    http::{Request, Response, StatusCode},
+    extract::{ConnectInfo, MatchedPath},
+    http::{request::Parts, Extensions, HeaderMap, Method, Request, Response, StatusCode, Uri},
 };
 use tower::{Layer, Service};
 
+use crate::adaptive::AdaptiveController;
 use crate::algorithm::Algorithm;
 use crate::decision::Decision;
-use crate::key::{HasHeaders, HasIpAddr, HasMethod, HasPath, Key};
+use crate::error::KeyError;
+use crate::key::{
+    join_header_values, parse_cookie, parse_query, percent_decode, redact, AsyncKey, HasCookies,
+    HasHeaders, HasIpAddr, HasMethod, HasPath, HasPathParams, HasQuery, Key, TrustedProxies,
+};
+#[cfg(feature = "hashing")]
+use crate::key::KeyHasher;
+use crate::manager::MissingKeyPolicy;
 use crate::quota::Quota;
 use crate::storage::Storage;
 
+use super::{AsyncKeyed, MiddlewareKey, MissingKeyLog};
+
 /// Tower layer for rate limiting.
 // derive(Clone) removed to allow S to be ?Clone
-
 pub struct RateLimitLayer<S, A, K> {
     storage: Arc<S>,
     algorithm: A,
     quota: Quota,
     key_extractor: K,
+    proxies: Arc<TrustedProxies>,
+    adaptive: Option<AdaptiveController>,
+    missing_key_policy: Arc<MissingKeyPolicy>,
+    missing_key_log: Arc<MissingKeyLog>,
+    #[cfg(feature = "hashing")]
+    key_hasher: Option<KeyHasher>,
 }
 
 impl<S, A, K> RateLimitLayer<S, A, K> {
@@ -35,8 +53,47 @@ impl<S, A, K> RateLimitLayer<S, A, K> {
             algorithm,
             quota,
             key_extractor,
+            proxies: Arc::new(TrustedProxies::none()),
+            adaptive: None,
+            missing_key_policy: Arc::default(),
+            missing_key_log: Arc::default(),
+            #[cfg(feature = "hashing")]
+            key_hasher: None,
         }
     }
+
+    /// Resolve client addresses behind `proxies`.
+    ///
+    /// By default forwarding headers are ignored and the client is the
+    /// connected peer, taken from axum's `ConnectInfo<SocketAddr>` (see
+    /// `Router::into_make_service_with_connect_info`).
+    pub fn with_trusted_proxies(mut self, proxies: TrustedProxies) -> Self {
+        self.proxies = Arc::new(proxies);
+        self
+    }
+
+    /// Scale the quota with `controller`, which observes the latency and
+    /// status of every response from the inner service.
+    pub fn with_adaptive(mut self, controller: AdaptiveController) -> Self {
+        self.adaptive = Some(controller);
+        self
+    }
+
+    /// Set what happens to requests whose key can't be extracted.
+    ///
+    /// Defaults to [`MissingKeyPolicy::SharedBucket`]: such requests share
+    /// one `"unknown"` bucket. `Deny` rejects them with 400 Bad Request.
+    pub fn with_missing_key_policy(mut self, policy: MissingKeyPolicy) -> Self {
+        self.missing_key_policy = Arc::new(policy);
+        self
+    }
+
+    /// Hash every extracted key with `hasher` before it reaches storage.
+    #[cfg(feature = "hashing")]
+    pub fn with_key_hasher(mut self, hasher: KeyHasher) -> Self {
+        self.key_hasher = Some(hasher);
+        self
+    }
 }
 
 impl<S, A, K> Clone for RateLimitLayer<S, A, K>
@@ -50,6 +107,12 @@ where
             algorithm: self.algorithm.clone(),
             quota: self.quota.clone(),
             key_extractor: self.key_extractor.clone(),
+            proxies: self.proxies.clone(),
+            adaptive: self.adaptive.clone(),
+            missing_key_policy: self.missing_key_policy.clone(),
+            missing_key_log: self.missing_key_log.clone(),
+            #[cfg(feature = "hashing")]
+            key_hasher: self.key_hasher.clone(),
         }
     }
 }
@@ -67,26 +130,36 @@ where
             storage: self.storage.clone(),
             algorithm: self.algorithm.clone(),
             quota: self.quota.clone(),
-            key_extractor: self.key_extractor.clone(),
+            key_extractor: Arc::new(self.key_extractor.clone()),
+            proxies: self.proxies.clone(),
+            adaptive: self.adaptive.clone(),
+            missing_key_policy: self.missing_key_policy.clone(),
+            missing_key_log: self.missing_key_log.clone(),
+            #[cfg(feature = "hashing")]
+            key_hasher: self.key_hasher.clone(),
         }
     }
 }
 
 /// The rate limiting service.
 // derive(Clone) removed to allow S to be ?Clone
-
 pub struct RateLimitService<S, A, K, Inner> {
     inner: Inner,
     storage: Arc<S>,
     algorithm: A,
     quota: Quota,
-    key_extractor: K,
+    key_extractor: Arc<K>,
+    proxies: Arc<TrustedProxies>,
+    adaptive: Option<AdaptiveController>,
+    missing_key_policy: Arc<MissingKeyPolicy>,
+    missing_key_log: Arc<MissingKeyLog>,
+    #[cfg(feature = "hashing")]
+    key_hasher: Option<KeyHasher>,
 }
 
 impl<S, A, K, Inner> Clone for RateLimitService<S, A, K, Inner>
 where
     A: Clone,
-    K: Clone,
     Inner: Clone,
 {
     fn clone(&self) -> Self {
@@ -96,67 +169,160 @@ where
             algorithm: self.algorithm.clone(),
             quota: self.quota.clone(),
             key_extractor: self.key_extractor.clone(),
+            proxies: self.proxies.clone(),
+            adaptive: self.adaptive.clone(),
+            missing_key_policy: self.missing_key_policy.clone(),
+            missing_key_log: self.missing_key_log.clone(),
+            #[cfg(feature = "hashing")]
+            key_hasher: self.key_hasher.clone(),
         }
     }
 }
 
 /// Wrapper around Axum request for key extraction.
+///
+/// Only borrows the request head, so it is `Sync` and async key extractors
+/// can hold it across `.await`s.
 pub struct AxumRequest<'a> {
-    request: &'a Request<Body>,
+    method: &'a Method,
+    uri: &'a Uri,
+    headers: &'a HeaderMap,
+    extensions: &'a Extensions,
+    proxies: &'a TrustedProxies,
 }
 
 impl<'a> AxumRequest<'a> {
-    #[allow(dead_code)]
-    fn new(request: &'a Request<Body>) -> Self {
-        Self { request }
+    /// Wrap `request`, resolving its client address behind `proxies`.
+    pub fn new(request: &'a Request<Body>, proxies: &'a TrustedProxies) -> Self {
+        Self {
+            method: request.method(),
+            uri: request.uri(),
+            headers: request.headers(),
+            extensions: request.extensions(),
+            proxies,
+        }
+    }
+
+    /// Wrap the head of a request split with `Request::into_parts`.
+    pub fn from_parts(parts: &'a Parts, proxies: &'a TrustedProxies) -> Self {
+        Self {
+            method: &parts.method,
+            uri: &parts.uri,
+            headers: &parts.headers,
+            extensions: &parts.extensions,
+            proxies,
+        }
     }
 }
 
 impl HasPath for AxumRequest<'_> {
     fn path(&self) -> &str {
-        self.request.uri().path()
+        self.uri.path()
     }
 }
 
 impl HasMethod for AxumRequest<'_> {
     fn method(&self) -> &str {
-        self.request.method().as_str()
+        self.method.as_str()
     }
 }
 
 impl HasHeaders for AxumRequest<'_> {
     fn header(&self, name: &str) -> Option<&str> {
-        self.request
-            .headers()
+        self.headers
             .get(name)
             .and_then(|v| v.to_str().ok())
     }
+
+    fn header_all(&self, name: &str) -> Option<Cow<'_, str>> {
+        join_header_values(self.headers.get_all(name).iter().map(|v| v.as_bytes()))
+    }
 }
 
-impl HasIpAddr for AxumRequest<'_> {
-    #[allow(clippy::collapsible_if)]
-    fn client_ip(&self) -> Option<std::net::IpAddr> {
-        // Try to get from extensions (set by outer middleware)
-        // For now, try parsing from X-Forwarded-For or X-Real-IP
-        if let Some(forwarded) = self.header("x-forwarded-for") {
-            if let Ok(ip) = forwarded.split(',').next()?.trim().parse() {
-                return Some(ip);
+impl HasQuery for AxumRequest<'_> {
+    fn query_param(&self, name: &str) -> Option<String> {
+        parse_query(self.uri.query()?, name)
+    }
+}
+
+impl HasCookies for AxumRequest<'_> {
+    fn cookie(&self, name: &str) -> Option<&str> {
+        // HTTP/2 clients may split cookies across several headers
+        self.headers
+            .get_all(axum::http::header::COOKIE)
+            .iter()
+            .filter_map(|v| v.to_str().ok())
+            .find_map(|v| parse_cookie(v, name))
+    }
+}
+
+impl HasPathParams for AxumRequest<'_> {
+    /// Matches the path against the route's [`MatchedPath`], which is only
+    /// set when the layer runs after routing.
+    fn path_param(&self, name: &str) -> Option<String> {
+        let pattern = self.extensions.get::<MatchedPath>()?.as_str();
+        let mut segments = self.uri.path().split('/');
+
+        for part in pattern.split('/') {
+            if let Some(param) = part.strip_prefix("{*").and_then(|p| p.strip_suffix('}')) {
+                if param == name {
+                    return percent_decode(&segments.collect::<Vec<_>>().join("/"), false);
+                }
+                return None;
             }
-        }
-        if let Some(real_ip) = self.header("x-real-ip") {
-            if let Ok(ip) = real_ip.parse() {
-                return Some(ip);
+
+            let segment = segments.next()?;
+            if part.strip_prefix('{').and_then(|p| p.strip_suffix('}')) == Some(name) {
+                return percent_decode(segment, false);
             }
         }
         None
     }
 }
 
+impl HasIpAddr for AxumRequest<'_> {
+    fn client_ip(&self) -> Option<IpAddr> {
+        self.proxies.resolve(self.peer_ip(), self)
+    }
+
+    fn peer_ip(&self) -> Option<IpAddr> {
+        self.extensions
+            .get::<ConnectInfo<SocketAddr>>()
+            .map(|info| info.0.ip())
+    }
+}
+
+impl<'a, K> MiddlewareKey<AxumRequest<'a>> for K
+where
+    K: Key<AxumRequest<'a>>,
+{
+    fn extract_key(&self, request: &AxumRequest<'a>) -> impl Future<Output = Option<String>> + Send {
+        std::future::ready(self.extract(request))
+    }
+
+    fn try_extract_key(&self, request: &AxumRequest<'a>) -> impl Future<Output = Result<String, KeyError>> + Send {
+        std::future::ready(self.try_extract(request))
+    }
+}
+
+impl<'a, K> MiddlewareKey<AxumRequest<'a>> for AsyncKeyed<K>
+where
+    K: AsyncKey<AxumRequest<'a>>,
+{
+    fn extract_key(&self, request: &AxumRequest<'a>) -> impl Future<Output = Option<String>> + Send {
+        self.inner.extract(request)
+    }
+
+    fn try_extract_key(&self, request: &AxumRequest<'a>) -> impl Future<Output = Result<String, KeyError>> + Send {
+        self.inner.try_extract(request)
+    }
+}
+
 impl<S, A, K, Inner> Service<Request<Body>> for RateLimitService<S, A, K, Inner>
 where
     S: Storage + Send + Sync + 'static,
     A: Algorithm + Clone + Send + Sync + 'static,
-    K: Key<AxumRequest<'static>> + Clone + Send + Sync + 'static,
+    K: for<'a> MiddlewareKey<AxumRequest<'a>>,
     Inner: Service<Request<Body>, Response = Response<Body>> + Clone + Send + 'static,
     Inner::Future: Send,
 {
@@ -171,26 +337,61 @@ where
     fn call(&mut self, request: Request<Body>) -> Self::Future {
         let storage = self.storage.clone();
         let algorithm = self.algorithm.clone();
-        let quota = self.quota.clone();
-        let _key_extractor = self.key_extractor.clone();
+        let quota = match &self.adaptive {
+            Some(controller) => controller.quota(&self.quota),
+            None => self.quota.clone(),
+        };
+        let key_extractor = self.key_extractor.clone();
+        let proxies = self.proxies.clone();
+        let mut adaptive = self.adaptive.clone();
+        let missing_key_policy = self.missing_key_policy.clone();
+        let missing_key_log = self.missing_key_log.clone();
+        #[cfg(feature = "hashing")]
+        let key_hasher = self.key_hasher.clone();
         let mut inner = self.inner.clone();
 
         Box::pin(async move {
-            // Extract key - we need to be careful with lifetimes here
-            // For safety, we use a static key extraction approach
-            let key = {
-                // This is a workaround for lifetime issues
-                // In production, you'd want a better approach
-                let path = request.uri().path().to_string();
-                format!("axum:{}", path)
+            // Only the head is borrowed while the key is extracted, so the
+            // future stays `Send`
+            let (parts, body) = request.into_parts();
+            let key = key_extractor
+                .try_extract_key(&AxumRequest::from_parts(&parts, &proxies))
+                .await;
+            let request = Request::from_parts(parts, body);
+            #[cfg(feature = "hashing")]
+            let key = match &key_hasher {
+                Some(hasher) => key.map(|key| hasher.hash(&key)),
+                None => key,
+            };
+
+            let (key, quota) = match key {
+                Ok(key) => (key, quota),
+                Err(error) => {
+                    missing_key_log.report(&error, &missing_key_policy);
+                    // Only keyed requests are reported to the controller
+                    let controller = adaptive.take();
+                    match &*missing_key_policy {
+                        MissingKeyPolicy::Deny => return Ok(missing_key_response()),
+                        MissingKeyPolicy::Allow => return inner.call(request).await,
+                        MissingKeyPolicy::SharedBucket => ("unknown".to_string(), quota),
+                        MissingKeyPolicy::Quota(missing) => {
+                            let quota = match &controller {
+                                Some(controller) => controller.quota(missing),
+                                None => missing.clone(),
+                            };
+                            ("unknown".to_string(), quota)
+                        }
+                    }
+                }
             };
 
             // Check rate limit
             let decision = algorithm
                 .check_and_record(&*storage, &key, &quota)
                 .await
-                .unwrap_or_else(|_| {
+                .unwrap_or_else(|e| {
                     // On error, allow the request (fail open)
+                    tracing::warn!(key = %redact(&key), error = %e, "rate limit check failed, allowing request");
                     Decision::allowed(crate::decision::RateLimitInfo::new(
                         quota.max_requests(),
                         quota.max_requests(),
@@ -201,7 +402,7 @@ where
 
             if decision.is_allowed() {
                 // Add rate limit headers and proceed
-                let response = inner.call(request).await?;
+                let response = call_observed(&mut inner, request, adaptive.as_ref()).await?;
                 Ok(add_rate_limit_headers(response, &decision))
             } else {
                 // Return 429 Too Many Requests
@@ -211,6 +412,25 @@ where
     }
 }
 
+/// Call the inner service, reporting the response to `adaptive`.
+async fn call_observed<Inner>(
+    inner: &mut Inner,
+    request: Request<Body>,
+    adaptive: Option<&AdaptiveController>,
+) -> Result<Response<Body>, Inner::Error>
+where
+    Inner: Service<Request<Body>, Response = Response<Body>>,
+{
+    let start = tokio::time::Instant::now();
+    let result = inner.call(request).await;
+    if let Some(controller) = adaptive {
+        // An inner error has no response, so count it as a 500
+        let status = result.as_ref().map_or(500, |response| response.status().as_u16());
+        controller.record(status, start.elapsed());
+    }
+    result
+}
+
 /// Add rate limit headers to a response.
 fn add_rate_limit_headers(mut response: Response<Body>, decision: &Decision) -> Response<Body> {
     let headers = response.headers_mut();
@@ -222,6 +442,16 @@ fn add_rate_limit_headers(mut response: Response<Body>, decision: &Decision) ->
     response
 }
 
+/// Create a 400 Bad Request response for a request without a key.
+fn missing_key_response() -> Response<Body> {
+    let mut response = Response::new(Body::from(r#"{"error":"Bad Request","reason":"missing rate limit key"}"#));
+    *response.status_mut() = StatusCode::BAD_REQUEST;
+    response
+        .headers_mut()
+        .insert("content-type", "application/json".parse().unwrap());
+    response
+}
+
 /// Create a 429 Too Many Requests response.
 fn rate_limited_response(decision: &Decision) -> Response<Body> {
     let info = decision.info();
@@ -271,4 +501,331 @@ mod tests {
         // Just verify it compiles
         assert_eq!(layer.quota.max_requests(), 10);
     }
+
+    #[tokio::test]
+    async fn test_layer_limits_by_resolved_client_ip() {
+        use crate::algorithm::FixedWindow;
+        use crate::key::IpKey;
+        use crate::storage::MemoryStorage;
+        use tower::ServiceExt;
+
+        let layer = RateLimitLayer::new(
+            MemoryStorage::new(),
+            FixedWindow::new(),
+            Quota::per_minute(1),
+            IpKey::new(),
+        )
+        .with_trusted_proxies(TrustedProxies::networks(["10.0.0.0/8"]).unwrap());
+        let service = layer.layer(tower::service_fn(|_: Request<Body>| async {
+            Ok::<_, std::convert::Infallible>(Response::new(Body::empty()))
+        }));
+
+        let request = |forwarded_for: &str| {
+            let mut request = Request::builder()
+                .header("x-forwarded-for", forwarded_for)
+                .body(Body::empty())
+                .unwrap();
+            request
+                .extensions_mut()
+                .insert(ConnectInfo(SocketAddr::from(([10, 0, 0, 1], 4000))));
+            request
+        };
+
+        let response = service.clone().oneshot(request("1.1.1.1")).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+
+        // Forging the left-most address doesn't get a fresh bucket
+        let response = service.clone().oneshot(request("6.6.6.6, 1.1.1.1")).await.unwrap();
+        assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+
+        let response = service.clone().oneshot(request("2.2.2.2")).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+
+        // A proxy adding its own line after the client's is read in full
+        let mut request = request("6.6.6.6");
+        request.headers_mut().append("x-forwarded-for", "2.2.2.2".parse().unwrap());
+        let response = service.clone().oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+    }
+
+    #[tokio::test]
+    async fn test_layer_reads_query_cookies_and_path_params() {
+        use crate::algorithm::FixedWindow;
+        use crate::key::{CookieKey, PathParamKey, QueryKey};
+        use crate::storage::MemoryStorage;
+        use axum::{routing::get, Router};
+        use tower::ServiceExt;
+
+        let get_request = |uri: &str| {
+            Request::get(uri)
+                .header("cookie", "a=1; session=s1")
+                .body(Body::empty())
+                .unwrap()
+        };
+        let proxies = TrustedProxies::none();
+        let request = get_request("/tenants/acme/files/a?api_key=k%2B1&x=2");
+        let adapter = AxumRequest::new(&request, &proxies);
+        assert_eq!(adapter.query_param("api_key").as_deref(), Some("k+1"));
+        assert_eq!(adapter.cookie("session"), Some("s1"));
+        assert_eq!(QueryKey::api_key().extract(&adapter), Some("query:api_key:k+1".to_string()));
+        assert_eq!(CookieKey::new("session").extract(&adapter), Some("cookie:session:s1".to_string()));
+        // No route matched yet
+        assert_eq!(adapter.path_param("tenant_id"), None);
+
+        let layer = RateLimitLayer::new(
+            MemoryStorage::new(),
+            FixedWindow::new(),
+            Quota::per_minute(1),
+            PathParamKey::new("tenant_id"),
+        );
+        let app = Router::new()
+            .route("/tenants/{tenant_id}/files/{*path}", get(|| async { "ok" }))
+            .layer(layer);
+
+        let response = app.clone().oneshot(get_request("/tenants/acme/files/a")).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let response = app.clone().oneshot(get_request("/tenants/acme/files/b")).await.unwrap();
+        assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+        let response = app.clone().oneshot(get_request("/tenants/acme%20co/files/a")).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+
+        // Catch-all parameters span the rest of the path
+        let layer = RateLimitLayer::new(
+            MemoryStorage::new(),
+            FixedWindow::new(),
+            Quota::per_minute(1),
+            PathParamKey::new("path"),
+        );
+        let app = Router::new()
+            .route("/tenants/{tenant_id}/files/{*path}", get(|| async { "ok" }))
+            .layer(layer);
+
+        let response = app.clone().oneshot(get_request("/tenants/acme/files/a/b")).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let response = app.clone().oneshot(get_request("/tenants/other/files/a/b")).await.unwrap();
+        assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+        let response = app.clone().oneshot(get_request("/tenants/acme/files/a/c")).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn test_layer_with_key_chain() {
+        use crate::algorithm::FixedWindow;
+        use crate::key::KeyChain;
+        use crate::storage::MemoryStorage;
+        use tower::ServiceExt;
+
+        let layer = RateLimitLayer::new(
+            MemoryStorage::new(),
+            FixedWindow::new(),
+            Quota::per_minute(1),
+            KeyChain::parse("header:x-tenant|global").unwrap(),
+        );
+        let service = layer.layer(tower::service_fn(|_: Request<Body>| async {
+            Ok::<_, std::convert::Infallible>(Response::new(Body::empty()))
+        }));
+
+        let request = |tenant: Option<&str>| {
+            let mut request = Request::builder();
+            if let Some(tenant) = tenant {
+                request = request.header("x-tenant", tenant);
+            }
+            request.body(Body::empty()).unwrap()
+        };
+
+        let response = service.clone().oneshot(request(Some("acme"))).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let response = service.clone().oneshot(request(None)).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let response = service.clone().oneshot(request(None)).await.unwrap();
+        assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+    }
+
+    #[tokio::test]
+    async fn test_layer_missing_key_policy() {
+        use crate::algorithm::FixedWindow;
+        use crate::key::HeaderKey;
+        use crate::storage::MemoryStorage;
+        use tower::ServiceExt;
+
+        async fn statuses(policy: Option<MissingKeyPolicy>) -> Vec<StatusCode> {
+            let mut layer = RateLimitLayer::new(
+                MemoryStorage::new(),
+                FixedWindow::new(),
+                Quota::per_minute(1),
+                HeaderKey::api_key(),
+            );
+            if let Some(policy) = policy {
+                layer = layer.with_missing_key_policy(policy);
+            }
+            let service = layer.layer(tower::service_fn(|_: Request<Body>| async {
+                Ok::<_, std::convert::Infallible>(Response::new(Body::empty()))
+            }));
+
+            let mut statuses = Vec::new();
+            for _ in 0..2 {
+                let response = service.clone().oneshot(Request::new(Body::empty())).await.unwrap();
+                statuses.push(response.status());
+            }
+            statuses
+        }
+
+        // Requests without a key share one bucket by default
+        assert_eq!(statuses(None).await, [StatusCode::OK, StatusCode::TOO_MANY_REQUESTS]);
+        assert_eq!(
+            statuses(Some(MissingKeyPolicy::Allow)).await,
+            [StatusCode::OK, StatusCode::OK]
+        );
+        assert_eq!(
+            statuses(Some(MissingKeyPolicy::Deny)).await,
+            [StatusCode::BAD_REQUEST, StatusCode::BAD_REQUEST]
+        );
+        assert_eq!(
+            statuses(Some(MissingKeyPolicy::Quota(Quota::per_minute(2)))).await,
+            [StatusCode::OK, StatusCode::OK]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_layer_adapts_to_backend_errors() {
+        use crate::adaptive::AdaptiveConfig;
+        use crate::algorithm::FixedWindow;
+        use crate::key::GlobalKey;
+        use crate::storage::MemoryStorage;
+        use tower::ServiceExt;
+
+        let controller = AdaptiveController::new(
+            AdaptiveConfig::new().with_window(std::time::Duration::ZERO, 2),
+        );
+        let service = RateLimitLayer::new(
+            MemoryStorage::new(),
+            FixedWindow::new(),
+            Quota::per_minute(4),
+            GlobalKey::new(),
+        )
+        .with_adaptive(controller.clone())
+        .layer(tower::service_fn(|_: Request<Body>| async {
+            let mut response = Response::new(Body::empty());
+            *response.status_mut() = StatusCode::SERVICE_UNAVAILABLE;
+            Ok::<_, std::convert::Infallible>(response)
+        }));
+
+        for _ in 0..2 {
+            let response = service.clone().oneshot(Request::new(Body::empty())).await.unwrap();
+            assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+        }
+
+        // Two failures halve the quota to 2 requests per minute
+        assert_eq!(controller.multiplier(), 0.5);
+        let response = service.clone().oneshot(Request::new(Body::empty())).await.unwrap();
+        assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+    }
+
+    #[tokio::test]
+    async fn test_layer_adaptive_counts_errors_and_skips_unkeyed() {
+        use crate::adaptive::AdaptiveConfig;
+        use crate::algorithm::FixedWindow;
+        use crate::key::HeaderKey;
+        use crate::storage::MemoryStorage;
+        use tower::ServiceExt;
+
+        let controller = AdaptiveController::new(
+            AdaptiveConfig::new().with_window(std::time::Duration::ZERO, 2),
+        );
+        let service = RateLimitLayer::new(
+            MemoryStorage::new(),
+            FixedWindow::new(),
+            Quota::per_minute(100),
+            HeaderKey::api_key(),
+        )
+        .with_adaptive(controller.clone())
+        .layer(tower::service_fn(|_: Request<Body>| async {
+            Err::<Response<Body>, _>(std::io::Error::other("backend down"))
+        }));
+        let request = |api_key: Option<&str>| {
+            let mut request = Request::builder();
+            if let Some(api_key) = api_key {
+                request = request.header("x-api-key", api_key);
+            }
+            request.body(Body::empty()).unwrap()
+        };
+
+        // Unkeyed requests don't reach the controller
+        for _ in 0..2 {
+            assert!(service.clone().oneshot(request(None)).await.is_err());
+        }
+        assert_eq!(controller.multiplier(), 1.0);
+
+        // Inner errors count as failures
+        for _ in 0..2 {
+            assert!(service.clone().oneshot(request(Some("key-a"))).await.is_err());
+        }
+        assert_eq!(controller.multiplier(), 0.5);
+    }
+
+    /// Reads a header after suspending, holding the request across the await.
+    #[derive(Clone)]
+    struct YieldingKey;
+
+    impl<R: HasHeaders + Sync> AsyncKey<R> for YieldingKey {
+        async fn extract(&self, request: &R) -> Option<String> {
+            tokio::task::yield_now().await;
+            request.header("x-tenant").map(|tenant| format!("tenant:{}", tenant))
+        }
+
+        fn name(&self) -> &'static str {
+            "tenant"
+        }
+    }
+
+    #[tokio::test]
+    async fn test_layer_with_async_key() {
+        use crate::algorithm::FixedWindow;
+        use crate::key::{HeaderKey, LookupKey};
+        use crate::storage::MemoryStorage;
+        use tower::ServiceExt;
+
+        let request = |api_key: &str| {
+            Request::builder()
+                .header("x-api-key", api_key)
+                .header("x-tenant", "acme")
+                .body(Body::empty())
+                .unwrap()
+        };
+        let ok = || {
+            tower::service_fn(|_: Request<Body>| async {
+                Ok::<_, std::convert::Infallible>(Response::new(Body::empty()))
+            })
+        };
+
+        // Both API keys belong to the same account
+        let lookup = LookupKey::new(HeaderKey::api_key(), |_: String| async {
+            Some("account:7".to_string())
+        });
+        let service = RateLimitLayer::new(
+            MemoryStorage::new(),
+            FixedWindow::new(),
+            Quota::per_minute(1),
+            AsyncKeyed::new(lookup),
+        )
+        .layer(ok());
+
+        let response = service.clone().oneshot(request("key-a")).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let response = service.clone().oneshot(request("key-b")).await.unwrap();
+        assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+
+        let service = RateLimitLayer::new(
+            MemoryStorage::new(),
+            FixedWindow::new(),
+            Quota::per_minute(1),
+            AsyncKeyed::new(YieldingKey),
+        )
+        .layer(ok());
+
+        let response = service.clone().oneshot(request("key-a")).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let response = service.clone().oneshot(request("key-b")).await.unwrap();
+        assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+    }
 }
@@ -29,4 +29,65 @@ mod layer;
 #[cfg(feature = "actix")]
 pub mod actix;
 
-pub use layer::RateLimitLayer;
+pub use layer::{AxumRequest, RateLimitLayer};
+
+use std::future::Future;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use crate::error::KeyError;
+use crate::manager::MissingKeyPolicy;
+
+/// Key extractors accepted by the middlewares.
+///
+/// Implemented for every sync [`Key`](crate::key::Key) and for
+/// [`AsyncKeyed`] wrappers of [`AsyncKey`](crate::key::AsyncKey)s.
+pub trait MiddlewareKey<R>: Send + Sync + 'static {
+    /// Extract the rate limiting key from the request.
+    fn extract_key(&self, request: &R) -> impl Future<Output = Option<String>> + Send;
+
+    /// Extract the rate limiting key, reporting why extraction failed.
+    fn try_extract_key(&self, request: &R) -> impl Future<Output = Result<String, KeyError>> + Send {
+        let key = self.extract_key(request);
+        async move { key.await.ok_or_else(|| KeyError::Missing("rate limit key".to_string())) }
+    }
+}
+
+/// Logs requests a middleware couldn't key: a warning the first time, then
+/// at debug level.
+#[derive(Debug, Default)]
+pub(crate) struct MissingKeyLog {
+    warned: AtomicBool,
+}
+
+impl MissingKeyLog {
+    pub(crate) fn report(&self, error: &KeyError, policy: &MissingKeyPolicy) {
+        if self.warned.swap(true, Ordering::Relaxed) {
+            tracing::debug!(%error, ?policy, "rate limit key extraction failed");
+        } else {
+            tracing::warn!(
+                %error,
+                ?policy,
+                "rate limit key extraction failed, applying the missing key policy (further failures are logged at debug level)"
+            );
+        }
+    }
+}
+
+/// Use an [`AsyncKey`](crate::key::AsyncKey) in a middleware.
+///
+/// # Example
+///
+/// ```ignore
+/// let layer = RateLimitLayer::new(storage, GCRA::new(), quota, AsyncKeyed::new(lookup_key));
+/// ```
+#[derive(Debug, Clone)]
+pub struct AsyncKeyed<K> {
+    inner: K,
+}
+
+impl<K> AsyncKeyed<K> {
+    /// Wrap an async key extractor.
+    pub fn new(inner: K) -> Self {
+        Self { inner }
+    }
+}
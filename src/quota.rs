@@ -20,6 +20,9 @@
 //!
 //! // Custom: 50 requests per 30 seconds
 //! let quota = Quota::new(50, Duration::from_secs(30));
+//!
+//! // New keys start at 10% and reach the full quota after a day
+//! let quota = Quota::per_minute(100).with_warmup(Warmup::new(0.1, Duration::from_secs(86400)));
 //! ```
 
 use std::time::Duration;
@@ -46,6 +49,58 @@ pub struct Quota {
     /// Refill rate for token-based algorithms (tokens per second).
     /// If not set, calculated from max_requests / window.
     refill_rate: Option<f64>,
+
+    /// Slow start for new keys.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    warmup: Option<Warmup>,
+}
+
+/// Slow start for new keys.
+///
+/// A key's effective quota ramps linearly from `initial_fraction` of the
+/// quota to the full quota over `duration` since the key was first seen.
+/// Supported by `TokenBucket`, `LeakyBucket`, `GCRA`, `FixedWindow`,
+/// `SlidingWindow` and `SlidingLog`, which track the first-seen time in
+/// storage under `__warmup:{key}`. Each recorded request costs one extra
+/// `get_or_insert` round trip on that key (peeks, a `get`). `AtomicGCRA`
+/// keeps no such state and ignores the warm-up.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Warmup {
+    /// Fraction of the quota available to a new key, in `(0, 1]`.
+    pub initial_fraction: f64,
+    /// Time from first seen to the full quota.
+    pub duration: Duration,
+    /// How long the first-seen time is kept after a key's last request.
+    ///
+    /// A key idle for longer warms up again.
+    pub idle_timeout: Duration,
+}
+
+impl Warmup {
+    /// Ramp from `initial_fraction` of the quota to the full quota over
+    /// `duration`, forgetting keys idle for 7 days.
+    pub fn new(initial_fraction: f64, duration: Duration) -> Self {
+        Self {
+            initial_fraction: initial_fraction.clamp(f64::MIN_POSITIVE, 1.0),
+            duration,
+            idle_timeout: Duration::from_secs(7 * 86400),
+        }
+    }
+
+    /// Set how long the first-seen time is kept after a key's last request.
+    pub fn with_idle_timeout(mut self, idle_timeout: Duration) -> Self {
+        self.idle_timeout = idle_timeout;
+        self
+    }
+
+    /// Get the fraction of the quota available `elapsed` after first seen.
+    pub fn fraction(&self, elapsed: Duration) -> f64 {
+        if elapsed >= self.duration {
+            return 1.0;
+        }
+        let progress = elapsed.as_secs_f64() / self.duration.as_secs_f64();
+        self.initial_fraction + (1.0 - self.initial_fraction) * progress
+    }
 }
 
 impl Quota {
@@ -68,6 +123,7 @@ impl Quota {
             window,
             burst: None,
             refill_rate: None,
+            warmup: None,
         }
     }
 
@@ -128,6 +184,7 @@ impl Quota {
             window,
             burst: None,
             refill_rate: None,
+            warmup: None,
         })
     }
 
@@ -149,6 +206,48 @@ impl Quota {
         self
     }
 
+    /// Ramp the quota up for new keys.
+    ///
+    /// Applied by every storage-backed algorithm, which keep each key's
+    /// first-seen time under a reserved `__warmup:` key. `AtomicGCRA` holds
+    /// no such state and ignores the warm-up.
+    pub fn with_warmup(mut self, warmup: Warmup) -> Self {
+        self.warmup = Some(warmup);
+        self
+    }
+
+    /// Get the warm-up configuration, if any.
+    pub fn warmup(&self) -> Option<&Warmup> {
+        self.warmup.as_ref()
+    }
+
+    /// Get the effective quota of a key first seen `elapsed` ago.
+    ///
+    /// Requests, burst and refill rate are scaled by the warm-up fraction,
+    /// keeping at least one request. The result has no warm-up.
+    pub fn warmed_up(&self, elapsed: Duration) -> Quota {
+        let fraction = self.warmup.map_or(1.0, |w| w.fraction(elapsed));
+        Quota {
+            warmup: None,
+            ..self.scaled(fraction)
+        }
+    }
+
+    /// Scale requests, burst and refill rate by `factor`, keeping at least
+    /// one request.
+    pub fn scaled(&self, factor: f64) -> Quota {
+        let factor = factor.max(0.0);
+        let scale = |n: u64| ((n as f64 * factor).floor() as u64).max(1);
+
+        Quota {
+            max_requests: scale(self.max_requests),
+            window: self.window,
+            burst: self.burst.map(scale),
+            refill_rate: self.refill_rate.map(|rate| rate * factor),
+            warmup: self.warmup,
+        }
+    }
+
     /// Get the maximum requests allowed per window.
     pub fn max_requests(&self) -> u64 {
         self.max_requests
@@ -346,6 +445,29 @@ mod tests {
         assert!(result.is_err());
     }
 
+    #[test]
+    fn test_quota_warmup() {
+        let quota = Quota::per_minute(100)
+            .with_burst(20)
+            .with_warmup(Warmup::new(0.1, Duration::from_secs(100)));
+
+        let start = quota.warmed_up(Duration::ZERO);
+        assert_eq!(start.max_requests(), 10);
+        assert_eq!(start.effective_burst(), 2);
+        assert!(start.warmup().is_none());
+
+        let half = quota.warmed_up(Duration::from_secs(50));
+        assert_eq!(half.max_requests(), 55);
+        assert_eq!(half.effective_burst(), 11);
+
+        let full = quota.warmed_up(Duration::from_secs(200));
+        assert_eq!(full.max_requests(), 100);
+        assert_eq!(full.effective_burst(), 20);
+
+        let tiny = Quota::per_minute(3).with_warmup(Warmup::new(0.01, Duration::from_secs(100)));
+        assert_eq!(tiny.warmed_up(Duration::ZERO).max_requests(), 1);
+    }
+
     #[test]
     #[should_panic]
     fn test_quota_zero_requests_panics() {
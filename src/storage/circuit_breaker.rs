@@ -0,0 +1,612 @@
+//! Circuit breaker between a primary storage and a fallback.
+//!
+//! ```text
+//!            failure_threshold consecutive failures
+//!   Closed ─────────────────────────────────────────▶ Open
+//!     ▲                                                │ reset_timeout
+//!     │ success_threshold successful probes            ▼
+//!     └──────────────────────────────────────────── HalfOpen ──▶ Open
+//!                                                     probe failed
+//! ```
+//!
+//! While the circuit is open every call goes straight to the fallback, so an
+//! unhealthy backend costs nothing per request. In half-open state a single
+//! call at a time probes the primary while the rest keep using the fallback.
+
+use std::future::Future;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use parking_lot::Mutex;
+use tokio::time::Instant;
+
+use crate::error::{RateLimitError, Result, StorageError};
+use crate::storage::{BucketUpdate, LogState, Storage, StorageEntry, TatUpdate, WindowUpdate};
+
+/// Circuit breaker configuration.
+#[derive(Debug, Clone)]
+pub struct CircuitBreakerConfig {
+    /// Consecutive failures that trip the circuit (default: 5).
+    pub failure_threshold: u32,
+    /// How long the circuit stays open before probing (default: 10s).
+    pub reset_timeout: Duration,
+    /// Consecutive successful probes that close the circuit (default: 1).
+    pub success_threshold: u32,
+}
+
+impl Default for CircuitBreakerConfig {
+    fn default() -> Self {
+        Self {
+            failure_threshold: 5,
+            reset_timeout: Duration::from_secs(10),
+            success_threshold: 1,
+        }
+    }
+}
+
+impl CircuitBreakerConfig {
+    /// Create a config with default settings.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set the consecutive failures that trip the circuit (at least 1).
+    pub fn with_failure_threshold(mut self, threshold: u32) -> Self {
+        self.failure_threshold = threshold.max(1);
+        self
+    }
+
+    /// Set how long the circuit stays open before probing.
+    pub fn with_reset_timeout(mut self, timeout: Duration) -> Self {
+        self.reset_timeout = timeout;
+        self
+    }
+
+    /// Set the consecutive successful probes that close the circuit (at least 1).
+    pub fn with_success_threshold(mut self, threshold: u32) -> Self {
+        self.success_threshold = threshold.max(1);
+        self
+    }
+}
+
+/// State of a circuit breaker.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CircuitState {
+    /// The primary is healthy and serves every call.
+    Closed,
+    /// The primary is failing; calls go to the fallback.
+    Open,
+    /// Probing whether the primary has recovered.
+    HalfOpen,
+}
+
+/// Snapshot of a circuit breaker's state and counters.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CircuitBreakerStats {
+    /// Current state.
+    pub state: CircuitState,
+    /// Consecutive primary failures observed while closed.
+    pub consecutive_failures: u32,
+    /// Times the circuit has tripped open.
+    pub trips: u64,
+    /// Calls served by the fallback.
+    pub fallback_calls: u64,
+}
+
+/// Where a call is routed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Route {
+    Primary,
+    Probe,
+    Fallback,
+}
+
+#[derive(Debug)]
+struct BreakerState {
+    state: CircuitState,
+    consecutive_failures: u32,
+    consecutive_successes: u32,
+    opened_at: Instant,
+    /// Start of the probe in flight, if any.
+    probe_started: Option<Instant>,
+}
+
+#[derive(Debug)]
+struct Breaker {
+    config: CircuitBreakerConfig,
+    state: Mutex<BreakerState>,
+    trips: AtomicU64,
+    fallback_calls: AtomicU64,
+}
+
+impl Breaker {
+    fn route(&self) -> Route {
+        let mut state = self.state.lock();
+        let now = Instant::now();
+
+        match state.state {
+            CircuitState::Closed => Route::Primary,
+            CircuitState::Open if now.duration_since(state.opened_at) < self.config.reset_timeout => {
+                Route::Fallback
+            }
+            CircuitState::Open => {
+                state.state = CircuitState::HalfOpen;
+                state.consecutive_successes = 0;
+                state.probe_started = Some(now);
+                Route::Probe
+            }
+            CircuitState::HalfOpen => {
+                // A probe that never reported back (e.g. its future was
+                // dropped) must not wedge the breaker half-open
+                let stale = state
+                    .probe_started
+                    .is_none_or(|started| now.duration_since(started) >= self.config.reset_timeout);
+                if stale {
+                    state.probe_started = Some(now);
+                    Route::Probe
+                } else {
+                    Route::Fallback
+                }
+            }
+        }
+    }
+
+    fn record(&self, route: Route, healthy: bool) {
+        let mut state = self.state.lock();
+
+        match (route, healthy) {
+            (Route::Primary, true) => state.consecutive_failures = 0,
+            (Route::Primary, false) => {
+                state.consecutive_failures += 1;
+                if state.state == CircuitState::Closed
+                    && state.consecutive_failures >= self.config.failure_threshold
+                {
+                    self.trip(&mut state);
+                }
+            }
+            (Route::Probe, true) => {
+                state.probe_started = None;
+                state.consecutive_successes += 1;
+                if state.state == CircuitState::HalfOpen
+                    && state.consecutive_successes >= self.config.success_threshold
+                {
+                    state.state = CircuitState::Closed;
+                    state.consecutive_failures = 0;
+                    tracing::info!("storage circuit breaker closed");
+                }
+            }
+            (Route::Probe, false) => {
+                if state.state == CircuitState::HalfOpen {
+                    self.trip(&mut state);
+                }
+            }
+            (Route::Fallback, _) => {}
+        }
+    }
+
+    fn trip(&self, state: &mut BreakerState) {
+        state.state = CircuitState::Open;
+        state.opened_at = Instant::now();
+        state.probe_started = None;
+        self.trips.fetch_add(1, Ordering::Relaxed);
+        tracing::warn!(
+            failures = state.consecutive_failures,
+            "storage circuit breaker opened, using fallback storage"
+        );
+    }
+}
+
+/// Whether `error` indicates the backend itself is unhealthy.
+///
+/// Transient errors count, except CAS conflicts, which only mean the backend
+/// is busy doing its job.
+fn is_failure(error: &RateLimitError) -> bool {
+    error.is_retryable() && !matches!(error, RateLimitError::Storage(StorageError::AtomicConflict))
+}
+
+/// Storage wrapper that stops calling an unhealthy backend.
+///
+/// Calls go to the primary storage until it fails `failure_threshold` times
+/// in a row with a transient error (see
+/// [`RateLimitError::is_retryable`](crate::RateLimitError::is_retryable)).
+/// The circuit then opens and the fallback serves every call until
+/// `reset_timeout` has passed, after which calls probe the primary one at a
+/// time until it recovers.
+///
+/// A call that fails on the primary is served by the fallback instead,
+/// except `execute_atomic`, whose operation has already been consumed.
+///
+/// Limits enforced by the fallback are local to it: with a `MemoryStorage`
+/// fallback, each instance limits independently while the circuit is open.
+///
+/// # Example
+///
+/// ```ignore
+/// use skp_ratelimit::storage::{CircuitBreakerConfig, CircuitBreakerStorage, MemoryStorage};
+///
+/// let storage = CircuitBreakerStorage::new(
+///     redis,
+///     MemoryStorage::new(),
+///     CircuitBreakerConfig::new().with_failure_threshold(3),
+/// );
+///
+/// // Health check
+/// let healthy = storage.state() == CircuitState::Closed;
+/// ```
+#[derive(Debug, Clone)]
+pub struct CircuitBreakerStorage<P, F> {
+    primary: P,
+    fallback: F,
+    breaker: Arc<Breaker>,
+}
+
+impl<P: Storage, F: Storage> CircuitBreakerStorage<P, F> {
+    /// Wrap `primary`, using `fallback` while the circuit is open.
+    pub fn new(primary: P, fallback: F, config: CircuitBreakerConfig) -> Self {
+        Self {
+            primary,
+            fallback,
+            breaker: Arc::new(Breaker {
+                config,
+                state: Mutex::new(BreakerState {
+                    state: CircuitState::Closed,
+                    consecutive_failures: 0,
+                    consecutive_successes: 0,
+                    opened_at: Instant::now(),
+                    probe_started: None,
+                }),
+                trips: AtomicU64::new(0),
+                fallback_calls: AtomicU64::new(0),
+            }),
+        }
+    }
+
+    /// Get the primary storage.
+    pub fn primary(&self) -> &P {
+        &self.primary
+    }
+
+    /// Get the fallback storage.
+    pub fn fallback(&self) -> &F {
+        &self.fallback
+    }
+
+    /// Get the circuit breaker configuration.
+    pub fn config(&self) -> &CircuitBreakerConfig {
+        &self.breaker.config
+    }
+
+    /// Get the current circuit state.
+    pub fn state(&self) -> CircuitState {
+        self.breaker.state.lock().state
+    }
+
+    /// Get the current state and counters.
+    pub fn stats(&self) -> CircuitBreakerStats {
+        let state = self.breaker.state.lock();
+        CircuitBreakerStats {
+            state: state.state,
+            consecutive_failures: state.consecutive_failures,
+            trips: self.breaker.trips.load(Ordering::Relaxed),
+            fallback_calls: self.breaker.fallback_calls.load(Ordering::Relaxed),
+        }
+    }
+
+    /// Route a call to the primary or the fallback and record the outcome.
+    async fn call<'a, T, PF, FF>(
+        &'a self,
+        primary: impl FnOnce(&'a P) -> PF,
+        fallback: impl FnOnce(&'a F) -> FF,
+    ) -> Result<T>
+    where
+        PF: Future<Output = Result<T>>,
+        FF: Future<Output = Result<T>>,
+    {
+        let route = self.breaker.route();
+        if route != Route::Fallback {
+            let result = primary(&self.primary).await;
+            let failed = result.as_ref().is_err_and(is_failure);
+            self.breaker.record(route, !failed);
+            if !failed {
+                return result;
+            }
+        }
+
+        self.breaker.fallback_calls.fetch_add(1, Ordering::Relaxed);
+        fallback(&self.fallback).await
+    }
+}
+
+impl<P: Storage, F: Storage> Storage for CircuitBreakerStorage<P, F> {
+    async fn get(&self, key: &str) -> Result<Option<StorageEntry>> {
+        self.call(|s| s.get(key), |s| s.get(key)).await
+    }
+
+    async fn set(&self, key: &str, entry: StorageEntry, ttl: Duration) -> Result<()> {
+        let fallback_entry = entry.clone();
+        self.call(|s| s.set(key, entry, ttl), |s| s.set(key, fallback_entry, ttl))
+            .await
+    }
+
+    async fn delete(&self, key: &str) -> Result<()> {
+        self.call(|s| s.delete(key), |s| s.delete(key)).await
+    }
+
+    async fn increment(
+        &self,
+        key: &str,
+        delta: u64,
+        window_start: u64,
+        ttl: Duration,
+    ) -> Result<u64> {
+        self.call(
+            |s| s.increment(key, delta, window_start, ttl),
+            |s| s.increment(key, delta, window_start, ttl),
+        )
+        .await
+    }
+
+    async fn execute_atomic<Op, T>(&self, key: &str, ttl: Duration, operation: Op) -> Result<T>
+    where
+        Op: FnOnce(Option<StorageEntry>) -> (StorageEntry, T) + Send,
+        T: Send,
+    {
+        let route = self.breaker.route();
+        if route == Route::Fallback {
+            self.breaker.fallback_calls.fetch_add(1, Ordering::Relaxed);
+            return self.fallback.execute_atomic(key, ttl, operation).await;
+        }
+
+        let result = self.primary.execute_atomic(key, ttl, operation).await;
+        self.breaker
+            .record(route, !result.as_ref().is_err_and(is_failure));
+        result
+    }
+
+    async fn compare_and_swap(
+        &self,
+        key: &str,
+        expected: Option<&StorageEntry>,
+        new: StorageEntry,
+        ttl: Duration,
+    ) -> Result<bool> {
+        let fallback_new = new.clone();
+        self.call(
+            |s| s.compare_and_swap(key, expected, new, ttl),
+            |s| s.compare_and_swap(key, expected, fallback_new, ttl),
+        )
+        .await
+    }
+
+    async fn get_or_insert(&self, key: &str, entry: StorageEntry, ttl: Duration) -> Result<StorageEntry> {
+        let fallback_entry = entry.clone();
+        self.call(
+            |s| s.get_or_insert(key, entry, ttl),
+            |s| s.get_or_insert(key, fallback_entry, ttl),
+        )
+        .await
+    }
+
+    async fn update_tat(
+        &self,
+        key: &str,
+        now: u64,
+        period_ms: u64,
+        tolerance_ms: u64,
+        ttl: Duration,
+    ) -> Result<TatUpdate> {
+        self.call(
+            |s| s.update_tat(key, now, period_ms, tolerance_ms, ttl),
+            |s| s.update_tat(key, now, period_ms, tolerance_ms, ttl),
+        )
+        .await
+    }
+
+    async fn take_tokens(
+        &self,
+        key: &str,
+        now: u64,
+        capacity: f64,
+        refill_rate: f64,
+        cost: f64,
+        ttl: Duration,
+    ) -> Result<BucketUpdate> {
+        self.call(
+            |s| s.take_tokens(key, now, capacity, refill_rate, cost, ttl),
+            |s| s.take_tokens(key, now, capacity, refill_rate, cost, ttl),
+        )
+        .await
+    }
+
+    async fn append_log(
+        &self,
+        key: &str,
+        now: u64,
+        window_ms: u64,
+        limit: u64,
+        ttl: Duration,
+    ) -> Result<LogState> {
+        self.call(
+            |s| s.append_log(key, now, window_ms, limit, ttl),
+            |s| s.append_log(key, now, window_ms, limit, ttl),
+        )
+        .await
+    }
+
+    async fn increment_window(
+        &self,
+        key: &str,
+        now: u64,
+        window_ms: u64,
+        limit: u64,
+        ttl: Duration,
+    ) -> Result<WindowUpdate> {
+        self.call(
+            |s| s.increment_window(key, now, window_ms, limit, ttl),
+            |s| s.increment_window(key, now, window_ms, limit, ttl),
+        )
+        .await
+    }
+
+    async fn read_log(&self, key: &str, now: u64, window_ms: u64) -> Result<LogState> {
+        self.call(
+            |s| s.read_log(key, now, window_ms),
+            |s| s.read_log(key, now, window_ms),
+        )
+        .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::ConnectionError;
+    use crate::storage::MemoryStorage;
+    use std::sync::atomic::{AtomicBool, AtomicU32};
+
+    /// Memory storage that can be switched off; counts the calls it sees.
+    #[derive(Default)]
+    struct Switchable {
+        inner: MemoryStorage,
+        down: AtomicBool,
+        calls: AtomicU32,
+    }
+
+    impl Switchable {
+        fn check(&self) -> Result<()> {
+            self.calls.fetch_add(1, Ordering::SeqCst);
+            if self.down.load(Ordering::SeqCst) {
+                Err(ConnectionError::ConnectionFailed("down".into()).into())
+            } else {
+                Ok(())
+            }
+        }
+    }
+
+    impl Storage for Switchable {
+        async fn get(&self, key: &str) -> Result<Option<StorageEntry>> {
+            self.check()?;
+            self.inner.get(key).await
+        }
+
+        async fn set(&self, key: &str, entry: StorageEntry, ttl: Duration) -> Result<()> {
+            self.check()?;
+            self.inner.set(key, entry, ttl).await
+        }
+
+        async fn delete(&self, key: &str) -> Result<()> {
+            self.check()?;
+            self.inner.delete(key).await
+        }
+
+        async fn increment(
+            &self,
+            key: &str,
+            delta: u64,
+            window_start: u64,
+            ttl: Duration,
+        ) -> Result<u64> {
+            self.check()?;
+            self.inner.increment(key, delta, window_start, ttl).await
+        }
+
+        async fn execute_atomic<F, T>(&self, key: &str, ttl: Duration, operation: F) -> Result<T>
+        where
+            F: FnOnce(Option<StorageEntry>) -> (StorageEntry, T) + Send,
+            T: Send,
+        {
+            self.check()?;
+            self.inner.execute_atomic(key, ttl, operation).await
+        }
+
+        async fn compare_and_swap(
+            &self,
+            key: &str,
+            expected: Option<&StorageEntry>,
+            new: StorageEntry,
+            ttl: Duration,
+        ) -> Result<bool> {
+            self.check()?;
+            self.inner.compare_and_swap(key, expected, new, ttl).await
+        }
+    }
+
+    const TTL: Duration = Duration::from_secs(60);
+
+    fn breaker() -> CircuitBreakerStorage<Switchable, MemoryStorage> {
+        CircuitBreakerStorage::new(
+            Switchable::default(),
+            MemoryStorage::new(),
+            CircuitBreakerConfig::new()
+                .with_failure_threshold(3)
+                .with_reset_timeout(Duration::from_secs(5)),
+        )
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_trips_and_short_circuits() {
+        let storage = breaker();
+        storage.primary().down.store(true, Ordering::SeqCst);
+
+        // Failing calls are served by the fallback
+        for i in 1..=3 {
+            assert_eq!(storage.increment("key", 1, 0, TTL).await.unwrap(), i);
+        }
+        assert_eq!(storage.state(), CircuitState::Open);
+        assert_eq!(storage.primary().calls.load(Ordering::SeqCst), 3);
+
+        // While open the primary is not called at all
+        storage.increment("key", 1, 0, TTL).await.unwrap();
+        assert_eq!(storage.primary().calls.load(Ordering::SeqCst), 3);
+
+        let stats = storage.stats();
+        assert_eq!(stats.trips, 1);
+        assert_eq!(stats.fallback_calls, 4);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_half_open_probe() {
+        let storage = breaker();
+        storage.primary().down.store(true, Ordering::SeqCst);
+        for _ in 0..3 {
+            storage.get("key").await.unwrap();
+        }
+        assert_eq!(storage.state(), CircuitState::Open);
+
+        // A failed probe reopens the circuit
+        tokio::time::advance(Duration::from_secs(5)).await;
+        storage.get("key").await.unwrap();
+        assert_eq!(storage.state(), CircuitState::Open);
+        assert_eq!(storage.primary().calls.load(Ordering::SeqCst), 4);
+        assert_eq!(storage.stats().trips, 2);
+
+        // A successful probe closes it
+        storage.primary().down.store(false, Ordering::SeqCst);
+        tokio::time::advance(Duration::from_secs(5)).await;
+        storage.set("key", StorageEntry::new(9, 0), TTL).await.unwrap();
+        assert_eq!(storage.state(), CircuitState::Closed);
+        assert_eq!(storage.primary().inner.get("key").await.unwrap().unwrap().count, 9);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_successes_reset_failures() {
+        let storage = breaker();
+        let primary = storage.primary();
+
+        for down in [true, true, false, true, true] {
+            primary.down.store(down, Ordering::SeqCst);
+            storage.get("key").await.unwrap();
+        }
+
+        assert_eq!(storage.state(), CircuitState::Closed);
+        assert_eq!(storage.stats().consecutive_failures, 2);
+    }
+
+    #[test]
+    fn test_conflicts_are_not_failures() {
+        assert!(is_failure(&StorageError::PoolExhausted.into()));
+        assert!(!is_failure(&StorageError::AtomicConflict.into()));
+        assert!(!is_failure(&StorageError::Serialization("bad".into()).into()));
+    }
+}
@@ -0,0 +1,332 @@
+//! Wire encoding for `StorageEntry` values in remote backends.
+//!
+//! Two encodings are supported:
+//!
+//! - **JSON**: human-readable, the format used by earlier releases.
+//! - **Binary**: a compact, versioned format using varints and delta-encoded
+//!   timestamps. Sliding log entries shrink the most.
+//!
+//! Decoding auto-detects the format from the first byte, so values written
+//! with either codec (including JSON written by older versions) can always be
+//! read back regardless of which codec is configured for writing.
+//!
+//! # Binary Layout (version 1)
+//!
+//! ```text
+//! [0x01] [flags] [count] [window_start] [last_update]
+//!        [tat]? [tokens: f64 LE]? [prev_count]?
+//!        [len, first, zigzag deltas...]? [len, bytes...]?
+//! ```
+//!
+//! All integers are unsigned LEB128 varints. `flags` records which optional
+//! fields are present.
+
+use serde::{Deserialize, Serialize};
+
+use crate::error::{Result, StorageError};
+use crate::storage::StorageEntry;
+
+/// Header byte of binary format version 1.
+const BINARY_V1: u8 = 0x01;
+
+const FLAG_TAT: u8 = 1 << 0;
+const FLAG_TOKENS: u8 = 1 << 1;
+const FLAG_PREV_COUNT: u8 = 1 << 2;
+const FLAG_TIMESTAMPS: u8 = 1 << 3;
+const FLAG_METADATA: u8 = 1 << 4;
+const KNOWN_FLAGS: u8 =
+    FLAG_TAT | FLAG_TOKENS | FLAG_PREV_COUNT | FLAG_TIMESTAMPS | FLAG_METADATA;
+
+/// Encoding used when writing `StorageEntry` values.
+///
+/// Reading always accepts both formats.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum EntryCodec {
+    /// JSON encoding (compatible with all previous releases).
+    #[default]
+    Json,
+    /// Compact versioned binary encoding.
+    Binary,
+}
+
+impl EntryCodec {
+    /// Encode an entry with this codec.
+    pub fn encode(&self, entry: &StorageEntry) -> Result<Vec<u8>> {
+        match self {
+            Self::Json => serde_json::to_vec(entry)
+                .map_err(|e| StorageError::Serialization(e.to_string()).into()),
+            Self::Binary => Ok(encode_binary(entry)),
+        }
+    }
+
+    /// Decode an entry, detecting the format from its header byte.
+    pub fn decode(bytes: &[u8]) -> Result<StorageEntry> {
+        match bytes.first() {
+            Some(&BINARY_V1) => decode_binary(&bytes[1..]),
+            Some(b'{' | b' ' | b'\t' | b'\r' | b'\n') => serde_json::from_slice(bytes)
+                .map_err(|e| StorageError::Serialization(e.to_string()).into()),
+            Some(other) => Err(StorageError::Serialization(format!(
+                "unknown entry encoding header 0x{:02x}",
+                other
+            ))
+            .into()),
+            None => Err(StorageError::Serialization("empty entry".into()).into()),
+        }
+    }
+}
+
+fn encode_binary(entry: &StorageEntry) -> Vec<u8> {
+    let mut flags = 0;
+    if entry.tat.is_some() {
+        flags |= FLAG_TAT;
+    }
+    if entry.tokens.is_some() {
+        flags |= FLAG_TOKENS;
+    }
+    if entry.prev_count.is_some() {
+        flags |= FLAG_PREV_COUNT;
+    }
+    if entry.timestamps.is_some() {
+        flags |= FLAG_TIMESTAMPS;
+    }
+    if entry.metadata.is_some() {
+        flags |= FLAG_METADATA;
+    }
+
+    let extra = entry.timestamps.as_ref().map_or(0, |t| t.len() * 2)
+        + entry.metadata.as_ref().map_or(0, |m| m.len());
+    let mut buf = Vec::with_capacity(32 + extra);
+    buf.push(BINARY_V1);
+    buf.push(flags);
+    put_varint(&mut buf, entry.count);
+    put_varint(&mut buf, entry.window_start);
+    put_varint(&mut buf, entry.last_update);
+
+    if let Some(tat) = entry.tat {
+        put_varint(&mut buf, tat);
+    }
+    if let Some(tokens) = entry.tokens {
+        buf.extend_from_slice(&tokens.to_le_bytes());
+    }
+    if let Some(prev) = entry.prev_count {
+        put_varint(&mut buf, prev);
+    }
+    if let Some(timestamps) = &entry.timestamps {
+        put_varint(&mut buf, timestamps.len() as u64);
+        let mut prev = 0u64;
+        for (i, &ts) in timestamps.iter().enumerate() {
+            if i == 0 {
+                put_varint(&mut buf, ts);
+            } else {
+                put_varint(&mut buf, zigzag(ts.wrapping_sub(prev) as i64));
+            }
+            prev = ts;
+        }
+    }
+    if let Some(metadata) = &entry.metadata {
+        put_varint(&mut buf, metadata.len() as u64);
+        buf.extend_from_slice(metadata);
+    }
+
+    buf
+}
+
+fn decode_binary(bytes: &[u8]) -> Result<StorageEntry> {
+    let mut reader = Reader::new(bytes);
+
+    let flags = reader.byte()?;
+    if flags & !KNOWN_FLAGS != 0 {
+        return Err(reader.error("unknown flags"));
+    }
+
+    let mut entry = StorageEntry {
+        count: reader.varint()?,
+        window_start: reader.varint()?,
+        last_update: reader.varint()?,
+        ..Default::default()
+    };
+
+    if flags & FLAG_TAT != 0 {
+        entry.tat = Some(reader.varint()?);
+    }
+    if flags & FLAG_TOKENS != 0 {
+        let raw = reader.take(8)?;
+        let mut tokens = [0u8; 8];
+        tokens.copy_from_slice(raw);
+        entry.tokens = Some(f64::from_le_bytes(tokens));
+    }
+    if flags & FLAG_PREV_COUNT != 0 {
+        entry.prev_count = Some(reader.varint()?);
+    }
+    if flags & FLAG_TIMESTAMPS != 0 {
+        let len = reader.length_prefix()?;
+        let mut timestamps = Vec::with_capacity(len);
+        let mut prev = 0u64;
+        for i in 0..len {
+            let ts = if i == 0 {
+                reader.varint()?
+            } else {
+                prev.wrapping_add(unzigzag(reader.varint()?) as u64)
+            };
+            timestamps.push(ts);
+            prev = ts;
+        }
+        entry.timestamps = Some(timestamps);
+    }
+    if flags & FLAG_METADATA != 0 {
+        let len = reader.length_prefix()?;
+        entry.metadata = Some(reader.take(len)?.to_vec());
+    }
+
+    if reader.pos != bytes.len() {
+        return Err(reader.error("trailing bytes"));
+    }
+
+    Ok(entry)
+}
+
+pub(crate) fn put_varint(buf: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        buf.push((value as u8) | 0x80);
+        value >>= 7;
+    }
+    buf.push(value as u8);
+}
+
+fn zigzag(value: i64) -> u64 {
+    ((value << 1) ^ (value >> 63)) as u64
+}
+
+fn unzigzag(value: u64) -> i64 {
+    ((value >> 1) as i64) ^ -((value & 1) as i64)
+}
+
+/// Cursor over a binary entry.
+pub(crate) struct Reader<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    pub(crate) fn new(bytes: &'a [u8]) -> Self {
+        Self { bytes, pos: 0 }
+    }
+
+    fn error(&self, reason: &str) -> crate::error::RateLimitError {
+        StorageError::Serialization(format!(
+            "invalid binary entry at byte {}: {}",
+            self.pos + 1,
+            reason
+        ))
+        .into()
+    }
+
+    fn byte(&mut self) -> Result<u8> {
+        let byte = *self
+            .bytes
+            .get(self.pos)
+            .ok_or_else(|| self.error("unexpected end of input"))?;
+        self.pos += 1;
+        Ok(byte)
+    }
+
+    pub(crate) fn take(&mut self, len: usize) -> Result<&'a [u8]> {
+        let end = self
+            .pos
+            .checked_add(len)
+            .filter(|&end| end <= self.bytes.len())
+            .ok_or_else(|| self.error("unexpected end of input"))?;
+        let slice = &self.bytes[self.pos..end];
+        self.pos = end;
+        Ok(slice)
+    }
+
+    pub(crate) fn varint(&mut self) -> Result<u64> {
+        let mut value = 0u64;
+        for shift in (0..64).step_by(7) {
+            let byte = self.byte()?;
+            value |= u64::from(byte & 0x7f) << shift;
+            if byte & 0x80 == 0 {
+                return Ok(value);
+            }
+        }
+        Err(self.error("varint overflow"))
+    }
+
+    /// Read a length prefix, bounded by the remaining input.
+    pub(crate) fn length_prefix(&mut self) -> Result<usize> {
+        let len = self.varint()?;
+        if len > (self.bytes.len() - self.pos) as u64 {
+            return Err(self.error("length exceeds input"));
+        }
+        Ok(len as usize)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn full_entry() -> StorageEntry {
+        StorageEntry::new(42, 1_700_000_000_000)
+            .set_tat(1_700_000_000_500)
+            .set_tokens(3.25)
+            .set_prev_count(7)
+            .set_metadata(vec![1, 2, 3])
+    }
+
+    #[test]
+    fn test_binary_roundtrip() {
+        let entries = [
+            StorageEntry::default(),
+            StorageEntry::new(1, 1000),
+            StorageEntry::with_tat(u64::MAX),
+            StorageEntry::with_tokens(-0.5, 2000),
+            StorageEntry::with_timestamps(vec![5000, 5001, 4999, 9000]),
+            full_entry(),
+        ];
+
+        for entry in entries {
+            let bytes = EntryCodec::Binary.encode(&entry).unwrap();
+            assert_eq!(bytes[0], BINARY_V1);
+            assert_eq!(EntryCodec::decode(&bytes).unwrap(), entry);
+        }
+    }
+
+    #[test]
+    fn test_decode_legacy_json() {
+        let entry = full_entry();
+        let json = serde_json::to_string(&entry).unwrap();
+        assert_eq!(EntryCodec::decode(json.as_bytes()).unwrap(), entry);
+
+        let bytes = EntryCodec::Json.encode(&entry).unwrap();
+        assert_eq!(EntryCodec::decode(&bytes).unwrap(), entry);
+    }
+
+    #[test]
+    fn test_binary_is_smaller() {
+        let timestamps: Vec<u64> = (0..1000).map(|i| 1_700_000_000_000 + i * 37).collect();
+        let entry = StorageEntry::with_timestamps(timestamps);
+
+        let json = EntryCodec::Json.encode(&entry).unwrap();
+        let binary = EntryCodec::Binary.encode(&entry).unwrap();
+        assert!(binary.len() * 5 < json.len());
+    }
+
+    #[test]
+    fn test_decode_rejects_garbage() {
+        assert!(EntryCodec::decode(&[]).is_err());
+        assert!(EntryCodec::decode(&[0x7f, 0x00]).is_err());
+
+        let mut bytes = EntryCodec::Binary.encode(&full_entry()).unwrap();
+        bytes.truncate(bytes.len() - 1);
+        assert!(EntryCodec::decode(&bytes).is_err());
+
+        let mut bytes = EntryCodec::Binary.encode(&full_entry()).unwrap();
+        bytes.push(0);
+        assert!(EntryCodec::decode(&bytes).is_err());
+
+        // Timestamp count far larger than the payload
+        assert!(EntryCodec::decode(&[BINARY_V1, FLAG_TIMESTAMPS, 0, 0, 0, 0xff, 0x7f]).is_err());
+    }
+}
@@ -0,0 +1,406 @@
+//! Conformance checks for `Storage` implementations.
+//!
+//! Algorithms rely on every backend agreeing on the semantics of the
+//! `Storage` methods. These checks pin them down so custom backends (and the
+//! built-in ones) can verify they behave the same way. Each check panics
+//! with a descriptive message on the first violation, so they can be called
+//! directly from `#[tokio::test]` functions.
+//!
+//! Checks use unique keys, so they can run against a shared database.
+//! Use a multi-threaded runtime so the concurrency checks actually race.
+//!
+//! # Example
+//!
+//! ```ignore
+//! use std::sync::Arc;
+//! use skp_ratelimit::storage::conformance;
+//!
+//! #[tokio::test(flavor = "multi_thread")]
+//! async fn my_storage_conforms() {
+//!     conformance::run_all(Arc::new(MyStorage::new())).await;
+//! }
+//! ```
+
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use tokio::task::JoinSet;
+
+use crate::error::{RateLimitError, StorageError};
+use crate::storage::{
+    current_timestamp_ms, BucketUpdate, LogState, Storage, StorageEntry, TatUpdate, WindowUpdate,
+};
+
+/// TTL used by the expiry checks.
+const SHORT_TTL: Duration = Duration::from_millis(100);
+/// How long to wait for a `SHORT_TTL` entry to expire.
+const EXPIRY_WAIT: Duration = Duration::from_millis(250);
+const LONG_TTL: Duration = Duration::from_secs(60);
+
+const TASKS: u64 = 8;
+const OPS_PER_TASK: u64 = 25;
+
+/// Run every check against `storage`.
+pub async fn run_all<S: Storage>(storage: Arc<S>) {
+    get_set_delete(&*storage).await;
+    ttl_expiry(&*storage).await;
+    increment_rollover(&*storage).await;
+    compare_and_swap(&*storage).await;
+    get_or_insert(&*storage).await;
+    increment_window(&*storage).await;
+    update_tat(&*storage).await;
+    take_tokens(&*storage).await;
+    append_and_read_log(&*storage).await;
+    concurrent_increments(storage.clone()).await;
+    concurrent_atomic(storage).await;
+}
+
+/// `set` stores entries verbatim, `get` returns them, and `delete` removes
+/// them (deleting a missing key is not an error).
+pub async fn get_set_delete<S: Storage>(storage: &S) {
+    let key = unique_key("get_set_delete");
+    let entry = StorageEntry::new(7, 1000)
+        .set_tat(1234)
+        .set_prev_count(3)
+        .set_last_update(1500);
+
+    assert_eq!(storage.get(&key).await.unwrap(), None, "missing key should read as None");
+
+    storage.set(&key, entry.clone(), LONG_TTL).await.unwrap();
+    assert_eq!(storage.get(&key).await.unwrap(), Some(entry), "get should return what set stored");
+
+    let log = StorageEntry::with_timestamps(vec![1, 2, 3]);
+    storage.set(&key, log.clone(), LONG_TTL).await.unwrap();
+    assert_eq!(storage.get(&key).await.unwrap(), Some(log), "set should replace the entry");
+
+    storage.delete(&key).await.unwrap();
+    assert_eq!(storage.get(&key).await.unwrap(), None, "deleted key should read as None");
+    storage.delete(&key).await.unwrap();
+
+    let count = storage.increment(&key, 1, 1000, LONG_TTL).await.unwrap();
+    assert_eq!(count, 1, "increment after delete should start from zero");
+}
+
+/// Entries written by every method expire after their TTL, and expired
+/// entries behave exactly like missing ones.
+pub async fn ttl_expiry<S: Storage>(storage: &S) {
+    let set_key = unique_key("ttl_set");
+    let incr_key = unique_key("ttl_increment");
+    let atomic_key = unique_key("ttl_atomic");
+    let cas_key = unique_key("ttl_cas");
+    let long_key = unique_key("ttl_long");
+
+    storage.set(&set_key, StorageEntry::new(1, 1000), SHORT_TTL).await.unwrap();
+    storage.increment(&incr_key, 5, 1000, SHORT_TTL).await.unwrap();
+    storage
+        .execute_atomic(&atomic_key, SHORT_TTL, |_| (StorageEntry::new(1, 1000), ()))
+        .await
+        .unwrap();
+    assert!(
+        storage
+            .compare_and_swap(&cas_key, None, StorageEntry::new(1, 1000), SHORT_TTL)
+            .await
+            .unwrap()
+    );
+    storage.set(&long_key, StorageEntry::new(1, 1000), LONG_TTL).await.unwrap();
+
+    assert!(storage.get(&set_key).await.unwrap().is_some(), "sub-second TTL should not expire immediately");
+
+    tokio::time::sleep(EXPIRY_WAIT).await;
+
+    for key in [&set_key, &incr_key, &atomic_key, &cas_key] {
+        assert_eq!(storage.get(key).await.unwrap(), None, "{} should have expired", key);
+    }
+    assert!(storage.get(&long_key).await.unwrap().is_some(), "unexpired entry was dropped");
+
+    let count = storage.increment(&incr_key, 1, 1000, LONG_TTL).await.unwrap();
+    assert_eq!(count, 1, "increment should not see an expired count");
+
+    storage
+        .execute_atomic(&atomic_key, LONG_TTL, |current| {
+            assert_eq!(current, None, "execute_atomic should not see an expired entry");
+            (StorageEntry::new(1, 1000), ())
+        })
+        .await
+        .unwrap();
+
+    let swapped = storage
+        .compare_and_swap(&cas_key, None, StorageEntry::new(2, 1000), LONG_TTL)
+        .await
+        .unwrap();
+    assert!(swapped, "CAS expecting None should succeed on an expired key");
+}
+
+/// `increment` counts within a window and rolls over to a new window,
+/// keeping the previous count in `prev_count`.
+pub async fn increment_rollover<S: Storage>(storage: &S) {
+    let key = unique_key("increment_rollover");
+    let before = current_timestamp_ms();
+
+    assert_eq!(storage.increment(&key, 1, 1000, LONG_TTL).await.unwrap(), 1);
+    assert_eq!(storage.increment(&key, 2, 1000, LONG_TTL).await.unwrap(), 3);
+
+    let entry = storage.get(&key).await.unwrap().expect("incremented key should exist");
+    assert_eq!((entry.count, entry.window_start), (3, 1000));
+    assert!(entry.last_update >= before, "increment should set last_update to now");
+
+    assert_eq!(storage.increment(&key, 1, 2000, LONG_TTL).await.unwrap(), 1, "new window should restart the count");
+
+    let entry = storage.get(&key).await.unwrap().expect("incremented key should exist");
+    assert_eq!(entry.count, 1);
+    assert_eq!(entry.window_start, 2000);
+    assert_eq!(entry.prev_count, Some(3), "roll-over should keep the previous count");
+}
+
+/// `compare_and_swap` writes only when the current live entry equals
+/// `expected` (with `None` meaning "no entry").
+pub async fn compare_and_swap<S: Storage>(storage: &S) {
+    let key = unique_key("compare_and_swap");
+    let first = StorageEntry::new(1, 1000);
+    let second = StorageEntry::new(2, 1000);
+    let wrong = StorageEntry::new(999, 1000);
+
+    assert!(
+        !storage.compare_and_swap(&key, Some(&first), second.clone(), LONG_TTL).await.unwrap(),
+        "CAS expecting an entry should fail on a missing key"
+    );
+    assert_eq!(storage.get(&key).await.unwrap(), None, "failed CAS should not write");
+
+    assert!(storage.compare_and_swap(&key, None, first.clone(), LONG_TTL).await.unwrap());
+    assert_eq!(storage.get(&key).await.unwrap(), Some(first.clone()));
+
+    assert!(
+        !storage.compare_and_swap(&key, None, second.clone(), LONG_TTL).await.unwrap(),
+        "CAS expecting None should fail on an existing key"
+    );
+    assert!(
+        !storage.compare_and_swap(&key, Some(&wrong), second.clone(), LONG_TTL).await.unwrap(),
+        "CAS with a stale expectation should fail"
+    );
+    assert_eq!(storage.get(&key).await.unwrap(), Some(first.clone()), "failed CAS should not write");
+
+    assert!(storage.compare_and_swap(&key, Some(&first), second.clone(), SHORT_TTL).await.unwrap());
+    assert_eq!(storage.get(&key).await.unwrap(), Some(second));
+
+    tokio::time::sleep(EXPIRY_WAIT).await;
+    assert_eq!(storage.get(&key).await.unwrap(), None, "CAS should apply its TTL");
+}
+
+/// `get_or_insert` creates missing entries, returns existing ones unchanged
+/// and applies its TTL either way.
+pub async fn get_or_insert<S: Storage>(storage: &S) {
+    let key = unique_key("get_or_insert");
+    let first = StorageEntry::new(0, 1000);
+    let second = StorageEntry::new(0, 2000);
+
+    assert_eq!(storage.get_or_insert(&key, first.clone(), SHORT_TTL).await.unwrap(), first);
+    assert_eq!(
+        storage.get_or_insert(&key, second.clone(), SHORT_TTL).await.unwrap(),
+        first,
+        "get_or_insert should keep an existing entry"
+    );
+    assert_eq!(storage.get(&key).await.unwrap(), Some(first.clone()));
+
+    tokio::time::sleep(EXPIRY_WAIT).await;
+    assert_eq!(storage.get(&key).await.unwrap(), None, "get_or_insert should apply its TTL");
+    assert_eq!(
+        storage.get_or_insert(&key, second.clone(), LONG_TTL).await.unwrap(),
+        second,
+        "get_or_insert should recreate an expired entry"
+    );
+}
+
+/// `increment_window` counts requests while the weighted count of the
+/// current and previous windows is under the limit, rolls windows over and
+/// applies its TTL.
+pub async fn increment_window<S: Storage>(storage: &S) {
+    let key = unique_key("increment_window");
+    let update = |allowed, count, prev_count| WindowUpdate { allowed, count, prev_count };
+
+    for count in 1..=3 {
+        assert_eq!(storage.increment_window(&key, 10_000, 1000, 3, LONG_TTL).await.unwrap(), update(true, count, 0));
+    }
+    assert_eq!(
+        storage.increment_window(&key, 10_100, 1000, 3, LONG_TTL).await.unwrap(),
+        update(false, 3, 0),
+        "denied requests should not be counted"
+    );
+
+    // Half way into the next window the previous one weighs 1.5
+    assert_eq!(storage.increment_window(&key, 11_500, 1000, 3, LONG_TTL).await.unwrap(), update(true, 1, 3));
+    assert_eq!(storage.increment_window(&key, 11_500, 1000, 3, LONG_TTL).await.unwrap(), update(true, 2, 3));
+    assert_eq!(storage.increment_window(&key, 11_500, 1000, 3, LONG_TTL).await.unwrap(), update(false, 2, 3));
+
+    let entry = storage.get(&key).await.unwrap().expect("counted key should exist");
+    assert_eq!((entry.count, entry.window_start, entry.prev_count), (2, 11_000, Some(3)));
+    assert_eq!(entry.last_update, 11_500);
+
+    // After a skipped window both counts are stale
+    assert_eq!(storage.increment_window(&key, 13_000, 1000, 3, SHORT_TTL).await.unwrap(), update(true, 1, 0));
+
+    tokio::time::sleep(EXPIRY_WAIT).await;
+    assert_eq!(storage.get(&key).await.unwrap(), None, "increment_window should apply its TTL");
+}
+
+/// `update_tat` advances the TAT of conforming requests by one period,
+/// leaves it unchanged for non-conforming ones and applies its TTL.
+pub async fn update_tat<S: Storage>(storage: &S) {
+    let key = unique_key("update_tat");
+    // 100ms period, burst of 3
+    let update = |now| storage.update_tat(&key, now, 100, 200, LONG_TTL);
+
+    for tat in [10_100, 10_200, 10_300] {
+        assert_eq!(update(10_000).await.unwrap(), TatUpdate { allowed: true, tat });
+    }
+    assert_eq!(
+        update(10_000).await.unwrap(),
+        TatUpdate { allowed: false, tat: 10_300 },
+        "non-conforming requests should not advance the TAT"
+    );
+
+    // The TAT drains with time
+    assert_eq!(update(10_250).await.unwrap(), TatUpdate { allowed: true, tat: 10_400 });
+    assert_eq!(storage.get(&key).await.unwrap().and_then(|e| e.tat), Some(10_400));
+
+    // An idle key restarts from `now`
+    assert_eq!(update(20_000).await.unwrap(), TatUpdate { allowed: true, tat: 20_100 });
+
+    let ttl_key = unique_key("update_tat_ttl");
+    storage.update_tat(&ttl_key, 10_000, 100, 200, SHORT_TTL).await.unwrap();
+    tokio::time::sleep(EXPIRY_WAIT).await;
+    assert_eq!(storage.get(&ttl_key).await.unwrap(), None, "update_tat should apply its TTL");
+}
+
+/// `take_tokens` starts buckets full, refills them at the rate up to the
+/// capacity, takes tokens only when enough are left and applies its TTL.
+pub async fn take_tokens<S: Storage>(storage: &S) {
+    let key = unique_key("take_tokens");
+    // Capacity 2, 4 tokens per second
+    let take = |now| storage.take_tokens(&key, now, 2.0, 4.0, 1.0, LONG_TTL);
+
+    assert_eq!(take(10_000).await.unwrap(), BucketUpdate { allowed: true, tokens: 1.0 });
+    assert_eq!(take(10_000).await.unwrap(), BucketUpdate { allowed: true, tokens: 0.0 });
+    assert_eq!(
+        take(10_000).await.unwrap(),
+        BucketUpdate { allowed: false, tokens: 0.0 },
+        "an empty bucket should deny"
+    );
+
+    // 250ms refills one token
+    assert_eq!(take(10_250).await.unwrap(), BucketUpdate { allowed: true, tokens: 0.0 });
+
+    // Refills stop at the capacity
+    assert_eq!(take(20_000).await.unwrap(), BucketUpdate { allowed: true, tokens: 1.0 });
+    let entry = storage.get(&key).await.unwrap().expect("bucket should exist");
+    assert_eq!((entry.tokens, entry.last_update), (Some(1.0), 20_000));
+
+    let ttl_key = unique_key("take_tokens_ttl");
+    storage.take_tokens(&ttl_key, 10_000, 2.0, 4.0, 1.0, SHORT_TTL).await.unwrap();
+    tokio::time::sleep(EXPIRY_WAIT).await;
+    assert_eq!(storage.get(&ttl_key).await.unwrap(), None, "take_tokens should apply its TTL");
+}
+
+/// `append_log` trims timestamps older than the window and appends while
+/// under the limit; `read_log` reports the same state without recording.
+pub async fn append_and_read_log<S: Storage>(storage: &S) {
+    let key = unique_key("append_log");
+    // 1s window, 2 requests
+    let append = |now| storage.append_log(&key, now, 1000, 2, LONG_TTL);
+    let state = |recorded, count, oldest| LogState { recorded, count, oldest };
+
+    assert_eq!(storage.read_log(&key, 10_000, 1000).await.unwrap(), state(false, 0, None));
+
+    assert_eq!(append(10_000).await.unwrap(), state(true, 1, Some(10_000)));
+    assert_eq!(append(10_100).await.unwrap(), state(true, 2, Some(10_000)));
+    assert_eq!(
+        append(10_200).await.unwrap(),
+        state(false, 2, Some(10_000)),
+        "a full log should not record"
+    );
+
+    // 10_000 falls out of the window starting at 10_050
+    assert_eq!(append(11_050).await.unwrap(), state(true, 2, Some(10_100)));
+    assert_eq!(storage.read_log(&key, 11_050, 1000).await.unwrap(), state(false, 2, Some(10_100)));
+    assert_eq!(storage.read_log(&key, 11_500, 1000).await.unwrap(), state(false, 1, Some(11_050)));
+    assert_eq!(storage.read_log(&key, 12_100, 1000).await.unwrap(), state(false, 0, None));
+
+    let ttl_key = unique_key("append_log_ttl");
+    storage.append_log(&ttl_key, 10_000, 1000, 2, SHORT_TTL).await.unwrap();
+    tokio::time::sleep(EXPIRY_WAIT).await;
+    assert_eq!(
+        storage.read_log(&ttl_key, 10_000, 1000).await.unwrap(),
+        state(false, 0, None),
+        "append_log should apply its TTL"
+    );
+}
+
+/// Concurrent `increment` calls never lose updates.
+pub async fn concurrent_increments<S: Storage>(storage: Arc<S>) {
+    let key = Arc::new(unique_key("concurrent_increments"));
+    let mut tasks = JoinSet::new();
+
+    for _ in 0..TASKS {
+        let storage = storage.clone();
+        let key = key.clone();
+        tasks.spawn(async move {
+            let mut counts = Vec::new();
+            for _ in 0..OPS_PER_TASK {
+                counts.push(storage.increment(&key, 1, 1000, LONG_TTL).await.unwrap());
+            }
+            counts
+        });
+    }
+
+    let mut counts: Vec<u64> = tasks.join_all().await.into_iter().flatten().collect();
+    counts.sort_unstable();
+    let expected: Vec<u64> = (1..=TASKS * OPS_PER_TASK).collect();
+    assert_eq!(counts, expected, "each increment should observe a distinct count");
+}
+
+/// Concurrent `execute_atomic` calls are serializable: each call either
+/// applies on top of the latest entry or fails with the retryable
+/// `StorageError::AtomicConflict`, never overwriting another update.
+pub async fn concurrent_atomic<S: Storage>(storage: Arc<S>) {
+    let key = Arc::new(unique_key("concurrent_atomic"));
+    let mut tasks = JoinSet::new();
+
+    for _ in 0..TASKS {
+        let storage = storage.clone();
+        let key = key.clone();
+        tasks.spawn(async move {
+            let mut applied = 0;
+            for _ in 0..OPS_PER_TASK {
+                let result = storage
+                    .execute_atomic(&key, LONG_TTL, |current| {
+                        let count = current.map_or(0, |e| e.count);
+                        (StorageEntry::new(count + 1, 1000), ())
+                    })
+                    .await;
+                match result {
+                    Ok(()) => applied += 1,
+                    Err(RateLimitError::Storage(StorageError::AtomicConflict)) => {}
+                    Err(e) => panic!("execute_atomic failed: {}", e),
+                }
+            }
+            applied
+        });
+    }
+
+    let applied: u64 = tasks.join_all().await.into_iter().sum();
+    let entry = storage.get(&key).await.unwrap().expect("updated key should exist");
+    assert!(applied > 0, "no execute_atomic call succeeded");
+    assert_eq!(entry.count, applied, "execute_atomic lost updates");
+}
+
+/// A key no other check (or concurrent run) uses.
+fn unique_key(check: &str) -> String {
+    static SEQ: AtomicU64 = AtomicU64::new(0);
+    format!(
+        "conformance:{}:{}-{}-{}",
+        check,
+        std::process::id(),
+        current_timestamp_ms(),
+        SEQ.fetch_add(1, Ordering::Relaxed)
+    )
+}
@@ -0,0 +1,739 @@
+//! Embedded persistent storage backed by a redb database file.
+//!
+//! For single-host deployments that need limits to survive restarts but
+//! cannot run Redis. Every operation runs in a single redb transaction, and
+//! write transactions are serialized by redb, so `increment`,
+//! `compare_and_swap` and the algorithm primitives are atomic across tasks
+//! and threads of the process.
+//!
+//! # Layout
+//!
+//! | Table | Key | Value |
+//! |-------|-----|-------|
+//! | `entries` | rate limit key | `expires_at` (u64 LE) + binary `StorageEntry` |
+//! | `expiry` | `(expires_at, key)` | - |
+//!
+//! Reads ignore expired entries. A background task deletes them in batches
+//! using the `expiry` index, and can periodically compact the file.
+//!
+//! Transactions do blocking file I/O, so they run on Tokio's blocking
+//! thread pool (`spawn_blocking`) rather than on the async workers.
+//! `execute_atomic` takes a closure that may borrow from the caller and so
+//! can't move to that pool: it reads the entry, applies the closure on the
+//! calling task and writes back with a compare-and-swap, failing with the
+//! retryable `StorageError::AtomicConflict` if the entry changed meanwhile.
+//!
+//! # Durability
+//!
+//! By default, commits are not durable: redb only persists them with the
+//! next durable commit, so a crash loses every update since the last one.
+//! A durable checkpoint bounds that window to `checkpoint_interval`
+//! (default: 1 second). A background task commits durably every interval
+//! while there are unsynced updates, and without a Tokio runtime the first
+//! write after an interval commits durably itself (so updates since the
+//! last write are only persisted by the next one, or by dropping the
+//! storage). The file itself always stays consistent.
+//!
+//! Rate limit state is short-lived, so losing up to a second of it only
+//! resets a few limits, while an fsync per request caps throughput at the
+//! disk's sync rate. `RedbConfig::with_durable_commits` fsyncs every commit
+//! instead.
+
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Weak};
+use std::time::{Duration, Instant};
+
+use parking_lot::{Mutex, RwLock};
+use redb::{Database, Durability, ReadableDatabase, ReadableTable, Table, TableDefinition};
+use tokio::sync::Notify;
+
+use crate::error::{RateLimitError, Result, StorageError};
+use crate::storage::{
+    current_timestamp_ms, primitives, BucketUpdate, EntryCodec, LogState, Storage, StorageEntry,
+    TatUpdate, WindowUpdate,
+};
+
+const ENTRIES: TableDefinition<&str, &[u8]> = TableDefinition::new("entries");
+const EXPIRY: TableDefinition<(u64, &str), ()> = TableDefinition::new("expiry");
+
+/// Embedded storage configuration.
+#[derive(Debug, Clone)]
+pub struct RedbConfig {
+    /// Database file path (created if missing).
+    pub path: PathBuf,
+    /// How often expired entries are purged (default: 60 seconds).
+    pub cleanup_interval: Option<Duration>,
+    /// Maximum entries purged per transaction (default: 10000).
+    pub cleanup_batch: usize,
+    /// How often the database file is compacted (default: never).
+    pub compaction_interval: Option<Duration>,
+    /// Whether every commit waits for an fsync (default: false).
+    pub durable_commits: bool,
+    /// Maximum time between durable commits when commits are not durable
+    /// (default: 1 second).
+    pub checkpoint_interval: Duration,
+}
+
+impl RedbConfig {
+    /// Create a configuration for the database at `path`.
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self {
+            path: path.into(),
+            cleanup_interval: Some(Duration::from_secs(60)),
+            cleanup_batch: 10_000,
+            compaction_interval: None,
+            durable_commits: false,
+            checkpoint_interval: Duration::from_secs(1),
+        }
+    }
+
+    /// Set the cleanup interval (`None` disables background cleanup).
+    pub fn with_cleanup_interval(mut self, interval: Option<Duration>) -> Self {
+        self.cleanup_interval = interval;
+        self
+    }
+
+    /// Set the maximum entries purged per transaction.
+    pub fn with_cleanup_batch(mut self, batch: usize) -> Self {
+        self.cleanup_batch = batch.max(1);
+        self
+    }
+
+    /// Compact the database file every `interval`.
+    ///
+    /// Compaction blocks all other operations while it runs.
+    pub fn with_compaction_interval(mut self, interval: Duration) -> Self {
+        self.compaction_interval = Some(interval);
+        self
+    }
+
+    /// Set whether every commit waits for an fsync.
+    ///
+    /// Without durable commits, a crash loses every update since the last
+    /// durable checkpoint, up to `checkpoint_interval` of them (the
+    /// database itself stays consistent). With them, every update waits
+    /// for the disk.
+    pub fn with_durable_commits(mut self, durable: bool) -> Self {
+        self.durable_commits = durable;
+        self
+    }
+
+    /// Set the maximum time between durable commits when commits are not
+    /// durable, which bounds what a crash can lose.
+    pub fn with_checkpoint_interval(mut self, interval: Duration) -> Self {
+        self.checkpoint_interval = interval;
+        self
+    }
+}
+
+/// Embedded persistent storage on a redb database file.
+///
+/// # Example
+///
+/// ```ignore
+/// use skp_ratelimit::storage::{RedbStorage, RedbConfig};
+/// use std::time::Duration;
+///
+/// let storage = RedbStorage::new(
+///     RedbConfig::new("/var/lib/gateway/ratelimit.redb")
+///         .with_compaction_interval(Duration::from_secs(3600)),
+/// )?;
+/// ```
+pub struct RedbStorage {
+    inner: Arc<Inner>,
+    shutdown: Arc<Notify>,
+}
+
+/// State shared with background tasks.
+struct Inner {
+    /// Compaction needs exclusive access; everything else shares it.
+    db: RwLock<Database>,
+    config: RedbConfig,
+    /// When the last durable commit started.
+    last_durable: Mutex<Instant>,
+    /// Whether there are commits since the last checkpoint.
+    unsynced: AtomicBool,
+}
+
+impl std::fmt::Debug for RedbStorage {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("RedbStorage")
+            .field("path", &self.inner.config.path)
+            .finish()
+    }
+}
+
+impl RedbStorage {
+    /// Open (or create) the database described by `config`.
+    ///
+    /// Background cleanup and compaction run on the current Tokio runtime;
+    /// they are skipped when called outside of one.
+    pub fn new(config: RedbConfig) -> Result<Self> {
+        let db = Database::create(&config.path).map_err(db_error)?;
+
+        // Create tables up front so reads never see them missing
+        let txn = db.begin_write().map_err(db_error)?;
+        txn.open_table(ENTRIES).map_err(db_error)?;
+        txn.open_table(EXPIRY).map_err(db_error)?;
+        txn.commit().map_err(db_error)?;
+
+        let storage = Self {
+            inner: Arc::new(Inner {
+                db: RwLock::new(db),
+                config,
+                last_durable: Mutex::new(Instant::now()),
+                unsynced: AtomicBool::new(false),
+            }),
+            shutdown: Arc::new(Notify::new()),
+        };
+
+        if tokio::runtime::Handle::try_current().is_ok() {
+            if let Some(interval) = storage.inner.config.cleanup_interval {
+                storage.start_task(interval, |inner| inner.purge_expired().map(drop));
+            }
+            if let Some(interval) = storage.inner.config.compaction_interval {
+                storage.start_task(interval, Inner::compact);
+            }
+            // With a zero interval every write is durable already
+            let checkpoint = storage.inner.config.checkpoint_interval;
+            if !storage.inner.config.durable_commits && !checkpoint.is_zero() {
+                storage.start_task(checkpoint, Inner::checkpoint);
+            }
+        }
+
+        Ok(storage)
+    }
+
+    /// Path of the database file.
+    pub fn path(&self) -> &Path {
+        &self.inner.config.path
+    }
+
+    /// Delete expired entries now, returning how many were removed.
+    ///
+    /// Blocks the calling thread.
+    pub fn purge_expired(&self) -> Result<usize> {
+        self.inner.purge_expired()
+    }
+
+    /// Compact the database file now.
+    ///
+    /// Blocks the calling thread.
+    pub fn compact(&self) -> Result<()> {
+        self.inner.compact()
+    }
+
+    /// Start a periodic background task.
+    fn start_task(&self, interval: Duration, task: fn(&Inner) -> Result<()>) {
+        let inner: Weak<Inner> = Arc::downgrade(&self.inner);
+        let shutdown = self.shutdown.clone();
+
+        tokio::spawn(async move {
+            loop {
+                tokio::select! {
+                    _ = tokio::time::sleep(interval) => {
+                        let Some(inner) = Weak::upgrade(&inner) else {
+                            break;
+                        };
+                        let result = tokio::task::spawn_blocking(move || task(&inner)).await;
+                        if let Ok(Err(e)) = result {
+                            tracing::warn!(error = %e, "embedded storage maintenance failed");
+                        }
+                    }
+                    _ = shutdown.notified() => {
+                        break;
+                    }
+                }
+            }
+        });
+    }
+
+    /// Run `operation` on the blocking thread pool.
+    async fn blocking<T: Send + 'static>(
+        &self,
+        operation: impl FnOnce(&Inner) -> Result<T> + Send + 'static,
+    ) -> Result<T> {
+        let inner = self.inner.clone();
+        match tokio::task::spawn_blocking(move || operation(&inner)).await {
+            Ok(result) => result,
+            Err(e) if e.is_panic() => std::panic::resume_unwind(e.into_panic()),
+            Err(e) => Err(StorageError::operation_failed(e.to_string(), false).into()),
+        }
+    }
+
+    /// Run an atomic operation in a write transaction on the blocking pool.
+    async fn atomic<T: Send + 'static>(
+        &self,
+        key: &str,
+        ttl: Duration,
+        operation: impl primitives::AtomicOp<T>,
+    ) -> Result<T> {
+        let key = key.to_string();
+        self.blocking(move |inner| {
+            inner.write(|tables| {
+                let current = tables.get(&key, current_timestamp_ms())?;
+                let (new_entry, result) = operation(current);
+                tables.put(&key, &new_entry, ttl)?;
+                Ok(result)
+            })
+        })
+        .await
+    }
+}
+
+impl Drop for RedbStorage {
+    fn drop(&mut self) {
+        self.shutdown.notify_waiters();
+    }
+}
+
+/// Open tables of a write transaction.
+struct Tables<'txn> {
+    entries: Table<'txn, &'static str, &'static [u8]>,
+    expiry: Table<'txn, (u64, &'static str), ()>,
+}
+
+impl Tables<'_> {
+    /// Read the live entry for `key`, if any.
+    fn get(&self, key: &str, now: u64) -> Result<Option<StorageEntry>> {
+        let value = self.entries.get(key).map_err(db_error)?;
+        match value {
+            Some(value) => decode_live(value.value(), now),
+            None => Ok(None),
+        }
+    }
+
+    /// Store `entry` under `key`, keeping the expiry index in sync.
+    fn put(&mut self, key: &str, entry: &StorageEntry, ttl: Duration) -> Result<()> {
+        let expires_at = current_timestamp_ms() + ttl.as_millis() as u64;
+
+        let mut value = expires_at.to_le_bytes().to_vec();
+        value.extend_from_slice(&EntryCodec::Binary.encode(entry)?);
+
+        let old = self
+            .entries
+            .insert(key, value.as_slice())
+            .map_err(db_error)?
+            .and_then(|old| split_value(old.value()).map(|(expires_at, _)| expires_at));
+        if let Some(old) = old {
+            self.expiry.remove((old, key)).map_err(db_error)?;
+        }
+        self.expiry.insert((expires_at, key), ()).map_err(db_error)?;
+        Ok(())
+    }
+
+    fn remove(&mut self, key: &str) -> Result<()> {
+        let old = self
+            .entries
+            .remove(key)
+            .map_err(db_error)?
+            .and_then(|old| split_value(old.value()).map(|(expires_at, _)| expires_at));
+        if let Some(old) = old {
+            self.expiry.remove((old, key)).map_err(db_error)?;
+        }
+        Ok(())
+    }
+}
+
+impl Inner {
+    /// Read the live entry for `key`, if any.
+    fn read(&self, key: &str) -> Result<Option<StorageEntry>> {
+        let db = self.db.read();
+        let txn = db.begin_read().map_err(db_error)?;
+        let table = txn.open_table(ENTRIES).map_err(db_error)?;
+
+        match table.get(key).map_err(db_error)? {
+            Some(value) => decode_live(value.value(), current_timestamp_ms()),
+            None => Ok(None),
+        }
+    }
+
+    /// Run `operation` in a write transaction over both tables.
+    ///
+    /// Commits durably if configured to, or if the last durable commit is
+    /// older than `checkpoint_interval`.
+    fn write<T>(&self, operation: impl FnOnce(&mut Tables<'_>) -> Result<T>) -> Result<T> {
+        let db = self.db.read();
+        let mut txn = db.begin_write().map_err(db_error)?;
+        let durable = self.config.durable_commits || self.checkpoint_due();
+        if durable {
+            *self.last_durable.lock() = Instant::now();
+        } else {
+            txn.set_durability(Durability::None).map_err(db_error)?;
+        }
+
+        let result = {
+            let mut tables = Tables {
+                entries: txn.open_table(ENTRIES).map_err(db_error)?,
+                expiry: txn.open_table(EXPIRY).map_err(db_error)?,
+            };
+            operation(&mut tables)?
+        };
+
+        txn.commit().map_err(db_error)?;
+        if !durable {
+            self.unsynced.store(true, Ordering::Release);
+        }
+        Ok(result)
+    }
+
+    fn checkpoint_due(&self) -> bool {
+        self.last_durable.lock().elapsed() >= self.config.checkpoint_interval
+    }
+
+    /// Persist earlier non-durable commits with an empty durable commit.
+    fn checkpoint(&self) -> Result<()> {
+        // Cleared before committing, so later commits are synced next time
+        if !self.unsynced.swap(false, Ordering::AcqRel) {
+            return Ok(());
+        }
+
+        let db = self.db.read();
+        let txn = db.begin_write().map_err(db_error)?;
+        *self.last_durable.lock() = Instant::now();
+        txn.commit().map_err(db_error)?;
+        Ok(())
+    }
+
+    /// Delete expired entries in batches of `cleanup_batch`.
+    fn purge_expired(&self) -> Result<usize> {
+        let now = current_timestamp_ms();
+        let mut total = 0;
+
+        loop {
+            let db = self.db.read();
+            let txn = db.begin_write().map_err(db_error)?;
+            let removed = {
+                let mut entries = txn.open_table(ENTRIES).map_err(db_error)?;
+                let mut expiry = txn.open_table(EXPIRY).map_err(db_error)?;
+
+                let due: Vec<(u64, String)> = expiry
+                    .range::<(u64, &str)>(..(now + 1, ""))
+                    .map_err(db_error)?
+                    .take(self.config.cleanup_batch)
+                    .map(|item| item.map(|(key, _)| (key.value().0, key.value().1.to_string())))
+                    .collect::<std::result::Result<_, _>>()
+                    .map_err(db_error)?;
+
+                for (expires_at, key) in &due {
+                    expiry.remove((*expires_at, key.as_str())).map_err(db_error)?;
+                    entries.remove(key.as_str()).map_err(db_error)?;
+                }
+                due.len()
+            };
+            txn.commit().map_err(db_error)?;
+
+            total += removed;
+            if removed < self.config.cleanup_batch {
+                return Ok(total);
+            }
+        }
+    }
+
+    fn compact(&self) -> Result<()> {
+        self.db.write().compact().map_err(db_error)?;
+        Ok(())
+    }
+}
+
+/// Split a stored value into its expiry and encoded entry.
+fn split_value(value: &[u8]) -> Option<(u64, &[u8])> {
+    let (expires_at, entry) = value.split_first_chunk::<8>()?;
+    Some((u64::from_le_bytes(*expires_at), entry))
+}
+
+/// Decode a stored value, treating expired entries as missing.
+fn decode_live(value: &[u8], now: u64) -> Result<Option<StorageEntry>> {
+    let (expires_at, entry) = split_value(value)
+        .ok_or_else(|| StorageError::Serialization("truncated embedded entry".into()))?;
+    if expires_at <= now {
+        return Ok(None);
+    }
+    EntryCodec::decode(entry).map(Some)
+}
+
+fn db_error(e: impl Into<redb::Error>) -> RateLimitError {
+    StorageError::operation_failed(e.into().to_string(), false).into()
+}
+
+impl Storage for RedbStorage {
+    async fn get(&self, key: &str) -> Result<Option<StorageEntry>> {
+        let key = key.to_string();
+        self.blocking(move |inner| inner.read(&key)).await
+    }
+
+    async fn set(&self, key: &str, entry: StorageEntry, ttl: Duration) -> Result<()> {
+        let key = key.to_string();
+        self.blocking(move |inner| inner.write(|tables| tables.put(&key, &entry, ttl)))
+            .await
+    }
+
+    async fn delete(&self, key: &str) -> Result<()> {
+        let key = key.to_string();
+        self.blocking(move |inner| inner.write(|tables| tables.remove(&key)))
+            .await
+    }
+
+    async fn increment(
+        &self,
+        key: &str,
+        delta: u64,
+        window_start: u64,
+        ttl: Duration,
+    ) -> Result<u64> {
+        self.atomic(key, ttl, move |current| {
+            let entry = primitives::increment_entry(current, delta, window_start, current_timestamp_ms());
+            let count = entry.count;
+            (entry, count)
+        })
+        .await
+    }
+
+    async fn execute_atomic<F, T>(&self, key: &str, ttl: Duration, operation: F) -> Result<T>
+    where
+        F: FnOnce(Option<StorageEntry>) -> (StorageEntry, T) + Send,
+        T: Send,
+    {
+        // `operation` may borrow from the caller, so it can't run on the
+        // blocking pool
+        let current = self.get(key).await?;
+        let (new_entry, result) = operation(current.clone());
+
+        if self.compare_and_swap(key, current.as_ref(), new_entry, ttl).await? {
+            Ok(result)
+        } else {
+            Err(StorageError::AtomicConflict.into())
+        }
+    }
+
+    async fn compare_and_swap(
+        &self,
+        key: &str,
+        expected: Option<&StorageEntry>,
+        new: StorageEntry,
+        ttl: Duration,
+    ) -> Result<bool> {
+        let key = key.to_string();
+        let expected = expected.cloned();
+
+        self.blocking(move |inner| {
+            inner.write(|tables| {
+                let current = tables.get(&key, current_timestamp_ms())?;
+                let matches = current == expected;
+                if matches {
+                    tables.put(&key, &new, ttl)?;
+                }
+                Ok(matches)
+            })
+        })
+        .await
+    }
+
+    async fn get_or_insert(&self, key: &str, entry: StorageEntry, ttl: Duration) -> Result<StorageEntry> {
+        self.atomic(key, ttl, primitives::get_or_insert_op(entry)).await
+    }
+
+    async fn update_tat(
+        &self,
+        key: &str,
+        now: u64,
+        period_ms: u64,
+        tolerance_ms: u64,
+        ttl: Duration,
+    ) -> Result<TatUpdate> {
+        self.atomic(key, ttl, primitives::update_tat_op(now, period_ms, tolerance_ms))
+            .await
+    }
+
+    async fn take_tokens(
+        &self,
+        key: &str,
+        now: u64,
+        capacity: f64,
+        refill_rate: f64,
+        cost: f64,
+        ttl: Duration,
+    ) -> Result<BucketUpdate> {
+        self.atomic(key, ttl, primitives::take_tokens_op(now, capacity, refill_rate, cost))
+            .await
+    }
+
+    async fn increment_window(
+        &self,
+        key: &str,
+        now: u64,
+        window_ms: u64,
+        limit: u64,
+        ttl: Duration,
+    ) -> Result<WindowUpdate> {
+        self.atomic(key, ttl, primitives::increment_window_op(now, window_ms, limit))
+            .await
+    }
+
+    async fn append_log(
+        &self,
+        key: &str,
+        now: u64,
+        window_ms: u64,
+        limit: u64,
+        ttl: Duration,
+    ) -> Result<LogState> {
+        self.atomic(key, ttl, primitives::append_log_op(now, window_ms, limit))
+            .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Database path unique to a test, removed on drop.
+    struct TempDb(PathBuf);
+
+    impl TempDb {
+        fn new(name: &str) -> Self {
+            let path = std::env::temp_dir()
+                .join(format!("skp-ratelimit-{}-{}.redb", name, std::process::id()));
+            let _ = std::fs::remove_file(&path);
+            Self(path)
+        }
+    }
+
+    impl Drop for TempDb {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_file(&self.0);
+        }
+    }
+
+    fn config(db: &TempDb) -> RedbConfig {
+        RedbConfig::new(&db.0)
+            .with_cleanup_interval(None)
+            .with_durable_commits(false)
+    }
+
+    #[tokio::test]
+    async fn test_redb_basic_and_persistence() {
+        let db = TempDb::new("basic");
+        let entry = StorageEntry::new(5, 1000);
+
+        {
+            let storage = RedbStorage::new(config(&db)).unwrap();
+            storage.set("key1", entry.clone(), Duration::from_secs(60)).await.unwrap();
+            assert_eq!(storage.get("key1").await.unwrap(), Some(entry.clone()));
+        }
+
+        // Survives reopening
+        let storage = RedbStorage::new(config(&db)).unwrap();
+        assert_eq!(storage.get("key1").await.unwrap(), Some(entry));
+
+        storage.delete("key1").await.unwrap();
+        assert!(storage.get("key1").await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_redb_increment_and_atomic() {
+        let db = TempDb::new("increment");
+        let storage = RedbStorage::new(config(&db)).unwrap();
+        let ttl = Duration::from_secs(60);
+
+        assert_eq!(storage.increment("key1", 1, 1000, ttl).await.unwrap(), 1);
+        assert_eq!(storage.increment("key1", 2, 1000, ttl).await.unwrap(), 3);
+        assert_eq!(storage.increment("key1", 1, 2000, ttl).await.unwrap(), 1);
+        assert_eq!(storage.get("key1").await.unwrap().unwrap().prev_count, Some(3));
+
+        let result = storage
+            .execute_atomic("key2", ttl, |current| {
+                let count = current.map(|e| e.count).unwrap_or(0);
+                (StorageEntry::new(count + 1, 1000), count + 1)
+            })
+            .await
+            .unwrap();
+        assert_eq!(result, 1);
+
+        let current = storage.get("key2").await.unwrap();
+        let wrong = StorageEntry::new(999, 1000);
+        let next = StorageEntry::new(2, 1000);
+        assert!(!storage.compare_and_swap("key2", Some(&wrong), next.clone(), ttl).await.unwrap());
+        assert!(storage.compare_and_swap("key2", current.as_ref(), next, ttl).await.unwrap());
+    }
+
+    /// Database path passed to the child of `test_redb_checkpoint_survives_crash`.
+    const CRASH_CHILD_DB: &str = "SKP_RATELIMIT_CRASH_CHILD_DB";
+
+    #[tokio::test]
+    async fn test_redb_checkpoint_survives_crash() {
+        let ttl = Duration::from_secs(60);
+
+        if let Ok(path) = std::env::var(CRASH_CHILD_DB) {
+            let config = RedbConfig::new(path).with_checkpoint_interval(Duration::from_millis(100));
+            let storage = RedbStorage::new(config).unwrap();
+            for i in 0..100 {
+                storage.set(&format!("key{}", i), StorageEntry::new(i, 0), ttl).await.unwrap();
+            }
+            tokio::time::sleep(Duration::from_millis(500)).await;
+            std::process::abort();
+        }
+
+        // Crash a child process after its writes, leaving time for a checkpoint
+        let db = TempDb::new("crash");
+        let status = std::process::Command::new(std::env::current_exe().unwrap())
+            .args(["--exact", "storage::embedded::tests::test_redb_checkpoint_survives_crash"])
+            .env(CRASH_CHILD_DB, &db.0)
+            .stdout(std::process::Stdio::null())
+            .stderr(std::process::Stdio::null())
+            .status()
+            .unwrap();
+        assert!(!status.success(), "child should have aborted");
+
+        let storage = RedbStorage::new(config(&db)).unwrap();
+        for i in 0..100 {
+            let entry = storage.get(&format!("key{}", i)).await.unwrap();
+            assert_eq!(entry.map(|e| e.count), Some(i), "key{} lost in the crash", i);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_redb_execute_atomic_conflict() {
+        let db = TempDb::new("conflict");
+        let storage = RedbStorage::new(config(&db)).unwrap();
+        let ttl = Duration::from_secs(60);
+
+        let result = storage
+            .execute_atomic("key1", ttl, |current| {
+                // Another writer lands between the read and the swap
+                storage
+                    .inner
+                    .write(|tables| tables.put("key1", &StorageEntry::new(7, 1000), ttl))
+                    .unwrap();
+                (StorageEntry::new(current.map_or(0, |e| e.count) + 1, 1000), ())
+            })
+            .await;
+
+        assert!(matches!(result, Err(RateLimitError::Storage(StorageError::AtomicConflict))));
+        assert_eq!(storage.get("key1").await.unwrap().unwrap().count, 7);
+    }
+
+    #[tokio::test]
+    async fn test_redb_expiry_and_purge() {
+        let db = TempDb::new("expiry");
+        let storage = RedbStorage::new(config(&db).with_cleanup_batch(2)).unwrap();
+
+        for i in 0..5 {
+            let key = format!("short{}", i);
+            storage.set(&key, StorageEntry::new(1, 0), Duration::from_millis(5)).await.unwrap();
+        }
+        storage.set("long", StorageEntry::new(1, 0), Duration::from_secs(60)).await.unwrap();
+        // Re-setting moves the key's expiry index entry
+        storage.set("short0", StorageEntry::new(2, 0), Duration::from_millis(5)).await.unwrap();
+        tokio::time::sleep(Duration::from_millis(20)).await;
+
+        assert!(storage.get("short1").await.unwrap().is_none());
+        assert_eq!(storage.purge_expired().unwrap(), 5);
+        assert_eq!(storage.purge_expired().unwrap(), 0);
+        assert!(storage.get("long").await.unwrap().is_some());
+
+        storage.compact().unwrap();
+        assert!(storage.get("long").await.unwrap().is_some());
+    }
+}
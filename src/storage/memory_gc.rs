@@ -2,17 +2,51 @@
 //!
 //! This storage backend uses `DashMap` for thread-safe concurrent access
 //! and includes configurable garbage collection to prevent memory growth.
+//!
+//! Garbage collection alone cannot bound memory between sweeps: a client
+//! rotating IPv6 addresses or API keys creates a new entry per request. Set
+//! [`GcConfig::with_max_entries`] and/or [`GcConfig::with_max_bytes`] to cap
+//! the store. When a cap is exceeded, expired entries are dropped first, then
+//! live entries are evicted according to the [`EvictionPolicy`] until the
+//! store is back under the cap (with a small slack so that evictions are
+//! batched rather than run on every insert). Victims are picked by sampling
+//! a few entries per eviction, so eviction never locks or scans the whole
+//! map, and the policy is approximate on large stores.
+//!
+//! # Expiry
+//!
+//! Each entry is scheduled in a hierarchical timing wheel (one per shard) at
+//! the time it becomes collectable. A GC run only visits entries whose
+//! deadline has passed, at most [`GcConfig::sweep_limit`] of them per
+//! automatic run, and removes each with a single-key lock, so sweeps never
+//! hold a whole `DashMap` shard while requests are waiting. Entries whose TTL
+//! was extended since they were scheduled are simply rescheduled. Removing
+//! or evicting an entry cancels its wheel item, so the wheels never hold
+//! more items than the store holds entries.
 
-use std::sync::atomic::{AtomicU64, Ordering};
-use std::sync::Arc;
+use std::hash::BuildHasher;
+#[cfg(feature = "snapshot")]
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Weak};
 use std::time::Duration;
 
+use dashmap::mapref::entry::Entry;
 use dashmap::DashMap;
 use parking_lot::Mutex;
 use tokio::sync::Notify;
 
 use crate::error::Result;
-use crate::storage::{current_timestamp_ms, Storage, StorageEntry};
+#[cfg(feature = "snapshot")]
+use crate::storage::snapshot::{self, SnapshotConfig, SnapshotStats, SnapshotWriter};
+use crate::storage::timer_wheel::{TimerId, TimerWheel};
+use crate::storage::{current_timestamp_ms, primitives, Storage, StorageEntry};
+
+/// Resolution of the expiry wheels, in milliseconds.
+const TICK_MS: u64 = 10;
+
+/// Entries sampled per eviction.
+const EVICTION_SAMPLES: usize = 8;
 
 /// Garbage collection interval configuration.
 #[derive(Debug, Clone)]
@@ -31,6 +65,19 @@ impl Default for GcInterval {
     }
 }
 
+/// Which live entries to evict when the storage is over capacity.
+///
+/// Expired entries are always evicted first, regardless of policy. Each
+/// eviction picks the best candidate among a few sampled entries.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum EvictionPolicy {
+    /// Evict the least recently used entries.
+    #[default]
+    Lru,
+    /// Evict the least frequently used entries (ties broken by recency).
+    Lfu,
+}
+
 /// Garbage collection configuration.
 #[derive(Debug, Clone)]
 pub struct GcConfig {
@@ -38,6 +85,15 @@ pub struct GcConfig {
     pub interval: GcInterval,
     /// Maximum age of entries before cleanup (default: 1 hour).
     pub max_age: Duration,
+    /// Maximum number of entries (default: unbounded).
+    pub max_entries: Option<usize>,
+    /// Approximate memory budget in bytes (default: unbounded).
+    pub max_bytes: Option<usize>,
+    /// Eviction policy used when a limit is exceeded.
+    pub eviction: EvictionPolicy,
+    /// Maximum number of expiring entries visited per automatic GC run
+    /// (default: 10000). Manual [`MemoryStorage::run_gc`] calls are unbounded.
+    pub sweep_limit: usize,
 }
 
 impl Default for GcConfig {
@@ -45,6 +101,10 @@ impl Default for GcConfig {
         Self {
             interval: GcInterval::default(),
             max_age: Duration::from_secs(3600),
+            max_entries: None,
+            max_bytes: None,
+            eviction: EvictionPolicy::default(),
+            sweep_limit: 10_000,
         }
     }
 }
@@ -79,13 +139,179 @@ impl GcConfig {
         self.max_age = max_age;
         self
     }
+
+    /// Cap the number of stored entries.
+    pub fn with_max_entries(mut self, max_entries: usize) -> Self {
+        self.max_entries = Some(max_entries);
+        self
+    }
+
+    /// Cap the approximate memory used by stored entries.
+    ///
+    /// Sizes are estimated from the key length, the entry struct, any
+    /// sliding log timestamps or metadata and the entry's expiry wheel item;
+    /// allocator overhead is not counted.
+    pub fn with_max_bytes(mut self, max_bytes: usize) -> Self {
+        self.max_bytes = Some(max_bytes);
+        self
+    }
+
+    /// Set the eviction policy.
+    pub fn with_eviction(mut self, eviction: EvictionPolicy) -> Self {
+        self.eviction = eviction;
+        self
+    }
+
+    /// Set the maximum number of expiring entries visited per automatic GC run.
+    pub fn with_sweep_limit(mut self, sweep_limit: usize) -> Self {
+        self.sweep_limit = sweep_limit.max(1);
+        self
+    }
 }
 
-/// Internal entry with expiration tracking.
-#[derive(Debug, Clone)]
+/// Eviction counters for a [`MemoryStorage`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct EvictionStats {
+    /// Expired entries dropped to make room.
+    pub expired: u64,
+    /// Live entries evicted by the eviction policy.
+    pub evicted: u64,
+}
+
+/// Internal entry with expiration and access tracking.
+#[derive(Debug)]
 struct InternalEntry {
+    /// Identifies this entry in the expiry wheels.
+    id: u64,
+    /// This entry's item in the expiry wheels.
+    timer: TimerId,
     entry: StorageEntry,
     expires_at: u64,
+    /// Approximate size in bytes, including the key.
+    size: usize,
+    /// Logical clock value of the last access.
+    last_access: AtomicU64,
+    /// Number of accesses.
+    hits: AtomicU64,
+}
+
+impl InternalEntry {
+    /// Earliest time at which GC may remove the entry.
+    fn collectable_at(&self, max_age_ms: u64) -> u64 {
+        self.expires_at.max(self.entry.last_update.saturating_add(max_age_ms))
+    }
+
+    /// Eviction order at `now`: lower ranks are evicted first, starting
+    /// with expired entries.
+    fn rank(&self, policy: EvictionPolicy, now: u64) -> (bool, u64, u64) {
+        let live = self.expires_at > now;
+        let last_access = self.last_access.load(Ordering::Relaxed);
+        match policy {
+            EvictionPolicy::Lru => (live, last_access, 0),
+            EvictionPolicy::Lfu => (live, self.hits.load(Ordering::Relaxed), last_access),
+        }
+    }
+}
+
+/// Timing wheel of `(key, entry id)` pairs.
+type ExpiryWheel = TimerWheel<(String, u64)>;
+
+/// Per-shard expiry wheels.
+///
+/// Every stored entry has one wheel item, so the wheels double as the pool
+/// eviction candidates are sampled from.
+struct ExpiryIndex {
+    hasher: std::collections::hash_map::RandomState,
+    shards: Box<[Mutex<ExpiryWheel>]>,
+    /// Shard the next sweep starts at, so bounded sweeps don't starve any.
+    next_shard: AtomicUsize,
+    /// Counter seeding the next sample.
+    samples: AtomicU64,
+}
+
+impl ExpiryIndex {
+    fn new(now: u64) -> Self {
+        let shards = std::thread::available_parallelism().map_or(1, |n| n.get()) * 4;
+        Self {
+            hasher: Default::default(),
+            shards: (0..shards.next_power_of_two())
+                .map(|_| Mutex::new(TimerWheel::new(now / TICK_MS)))
+                .collect(),
+            next_shard: AtomicUsize::new(0),
+            samples: AtomicU64::new(0),
+        }
+    }
+
+    fn shard(&self, key: &str) -> &Mutex<ExpiryWheel> {
+        &self.shards[self.hasher.hash_one(key) as usize & (self.shards.len() - 1)]
+    }
+
+    fn schedule(&self, key: &str, id: u64, at: u64) -> TimerId {
+        self.shard(key)
+            .lock()
+            .insert(at.div_ceil(TICK_MS), (key.to_string(), id))
+    }
+
+    /// Cancel the wheel item of `key`, unless it already fired.
+    fn cancel(&self, key: &str, timer: TimerId) {
+        self.shard(key).lock().cancel(timer);
+    }
+
+    /// Number of scheduled items.
+    #[cfg(test)]
+    fn len(&self) -> usize {
+        self.shards.iter().map(|shard| shard.lock().len()).sum()
+    }
+
+    /// Sample up to `limit` scheduled items, starting at a random shard.
+    fn sample(&self, limit: usize) -> Vec<(String, u64)> {
+        let seed = self.hasher.hash_one(self.samples.fetch_add(1, Ordering::Relaxed));
+        let mut sample = Vec::with_capacity(limit);
+
+        for offset in 0..self.shards.len() {
+            let wheel = self.shards[(seed as usize + offset) & (self.shards.len() - 1)].lock();
+            sample.extend(wheel.sample(seed, limit - sample.len()).into_iter().cloned());
+            if sample.len() >= limit {
+                break;
+            }
+        }
+        sample
+    }
+
+    /// Collect up to `limit` items that are due at `now`.
+    fn due(&self, now: u64, limit: usize) -> Vec<(String, u64)> {
+        let mut due = Vec::new();
+        let start = self.next_shard.fetch_add(1, Ordering::Relaxed);
+
+        for offset in 0..self.shards.len() {
+            let mut wheel = self.shards[(start + offset) & (self.shards.len() - 1)].lock();
+            wheel.advance(now / TICK_MS);
+            while due.len() < limit {
+                match wheel.pop_due() {
+                    Some(item) => due.push(item),
+                    None => break,
+                }
+            }
+            if due.len() >= limit {
+                break;
+            }
+        }
+        due
+    }
+
+    fn clear(&self) {
+        for shard in self.shards.iter() {
+            shard.lock().clear();
+        }
+    }
+}
+
+/// Approximate memory used by an entry stored under `key`.
+fn entry_size(key: &str, entry: &StorageEntry) -> usize {
+    let timestamps = entry.timestamps.as_ref().map_or(0, |t| t.capacity() * 8);
+    let metadata = entry.metadata.as_ref().map_or(0, |m| m.capacity());
+    let wheel_item = ExpiryWheel::NODE_SIZE + key.len();
+    std::mem::size_of::<(String, InternalEntry)>() + key.len() + timestamps + metadata + wheel_item
 }
 
 /// In-memory storage with garbage collection.
@@ -96,7 +322,7 @@ struct InternalEntry {
 /// # Example
 ///
 /// ```ignore
-/// use skp_ratelimit::storage::{MemoryStorage, GcConfig};
+/// use skp_ratelimit::storage::{MemoryStorage, GcConfig, EvictionPolicy};
 /// use std::time::Duration;
 ///
 /// // Default GC (every 10000 requests)
@@ -105,25 +331,49 @@ struct InternalEntry {
 /// // Custom GC interval
 /// let storage = MemoryStorage::with_gc(GcConfig::on_duration(Duration::from_secs(60)));
 ///
+/// // Bounded to 100k keys, evicting least recently used first
+/// let storage = MemoryStorage::with_gc(
+///     GcConfig::default()
+///         .with_max_entries(100_000)
+///         .with_eviction(EvictionPolicy::Lru),
+/// );
+///
 /// // Manual GC only
 /// let storage = MemoryStorage::with_gc(GcConfig::manual());
 /// storage.run_gc().await;
 /// ```
 pub struct MemoryStorage {
+    inner: Arc<Inner>,
+    shutdown: Arc<Notify>,
+    #[cfg(feature = "snapshot")]
+    snapshot: Option<SnapshotConfig>,
+}
+
+/// State shared with the background GC task.
+struct Inner {
     data: DashMap<String, InternalEntry>,
     gc_config: GcConfig,
     request_count: AtomicU64,
-    #[allow(dead_code)]
-    last_gc: AtomicU64,
     gc_lock: Mutex<()>,
-    shutdown: Arc<Notify>,
+    /// Logical clock for LRU ordering.
+    clock: AtomicU64,
+    /// Approximate bytes used by all entries.
+    bytes: AtomicUsize,
+    expiry: ExpiryIndex,
+    next_id: AtomicU64,
+    evict_lock: Mutex<()>,
+    evicted_expired: AtomicU64,
+    evicted_live: AtomicU64,
+    /// Serializes snapshot writes to the same file.
+    #[cfg(feature = "snapshot")]
+    snapshot_lock: Mutex<()>,
 }
 
 impl std::fmt::Debug for MemoryStorage {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         f.debug_struct("MemoryStorage")
-            .field("entries", &self.data.len())
-            .field("gc_config", &self.gc_config)
+            .field("entries", &self.inner.data.len())
+            .field("gc_config", &self.inner.gc_config)
             .finish()
     }
 }
@@ -142,17 +392,30 @@ impl MemoryStorage {
 
     /// Create a new memory storage with custom GC configuration.
     pub fn with_gc(gc_config: GcConfig) -> Self {
+        let interval = gc_config.interval.clone();
         let storage = Self {
-            data: DashMap::new(),
-            gc_config: gc_config.clone(),
-            request_count: AtomicU64::new(0),
-            last_gc: AtomicU64::new(current_timestamp_ms()),
-            gc_lock: Mutex::new(()),
+            inner: Arc::new(Inner {
+                data: DashMap::new(),
+                gc_config,
+                request_count: AtomicU64::new(0),
+                gc_lock: Mutex::new(()),
+                clock: AtomicU64::new(0),
+                bytes: AtomicUsize::new(0),
+                expiry: ExpiryIndex::new(current_timestamp_ms()),
+                next_id: AtomicU64::new(0),
+                evict_lock: Mutex::new(()),
+                evicted_expired: AtomicU64::new(0),
+                evicted_live: AtomicU64::new(0),
+                #[cfg(feature = "snapshot")]
+                snapshot_lock: Mutex::new(()),
+            }),
             shutdown: Arc::new(Notify::new()),
+            #[cfg(feature = "snapshot")]
+            snapshot: None,
         };
 
         // Start background GC task if duration-based
-        if let GcInterval::Duration(interval) = gc_config.interval {
+        if let GcInterval::Duration(interval) = interval {
             storage.start_gc_task(interval);
         }
 
@@ -161,15 +424,17 @@ impl MemoryStorage {
 
     /// Start background GC task.
     fn start_gc_task(&self, interval: Duration) {
-        let data = self.data.clone();
-        let max_age = self.gc_config.max_age;
+        let inner = Arc::downgrade(&self.inner);
         let shutdown = self.shutdown.clone();
 
         tokio::spawn(async move {
             loop {
                 tokio::select! {
                     _ = tokio::time::sleep(interval) => {
-                        run_gc_on_map(&data, max_age);
+                        let Some(inner) = Weak::upgrade(&inner) else {
+                            break;
+                        };
+                        inner.run_gc(Some(inner.gc_config.sweep_limit));
                     }
                     _ = shutdown.notified() => {
                         break;
@@ -180,55 +445,386 @@ impl MemoryStorage {
     }
 
     /// Manually trigger garbage collection.
+    ///
+    /// Collects every entry that is due, regardless of `sweep_limit`.
     pub async fn run_gc(&self) {
-        run_gc_on_map(&self.data, self.gc_config.max_age);
+        self.inner.run_gc(None);
     }
 
     /// Get the number of entries currently stored.
     pub fn len(&self) -> usize {
-        self.data.len()
+        self.inner.data.len()
     }
 
     /// Check if the storage is empty.
     pub fn is_empty(&self) -> bool {
-        self.data.is_empty()
+        self.inner.data.is_empty()
+    }
+
+    /// Approximate memory used by stored entries, in bytes.
+    pub fn approx_bytes(&self) -> usize {
+        self.inner.bytes.load(Ordering::Relaxed)
+    }
+
+    /// Eviction counters since creation.
+    pub fn eviction_stats(&self) -> EvictionStats {
+        EvictionStats {
+            expired: self.inner.evicted_expired.load(Ordering::Relaxed),
+            evicted: self.inner.evicted_live.load(Ordering::Relaxed),
+        }
     }
 
     /// Clear all entries.
     pub fn clear(&self) {
-        self.data.clear();
+        self.inner.data.retain(|_, internal| {
+            self.inner.bytes.fetch_sub(internal.size, Ordering::Relaxed);
+            false
+        });
+        self.inner.expiry.clear();
     }
 
     /// Check if GC should run and run it if needed.
     fn maybe_run_gc(&self) {
-        if let GcInterval::Requests(threshold) = self.gc_config.interval {
-            let count = self.request_count.fetch_add(1, Ordering::Relaxed);
+        if let GcInterval::Requests(threshold) = self.inner.gc_config.interval {
+            let count = self.inner.request_count.fetch_add(1, Ordering::Relaxed);
             if count.is_multiple_of(threshold) && count > 0 {
                 // Try to acquire GC lock (non-blocking)
-                if let Some(_guard) = self.gc_lock.try_lock() {
-                    run_gc_on_map(&self.data, self.gc_config.max_age);
+                if let Some(_guard) = self.inner.gc_lock.try_lock() {
+                    self.inner.run_gc(Some(self.inner.gc_config.sweep_limit));
+                }
+            }
+        }
+    }
+
+    /// Read a live entry, recording the access.
+    fn read(&self, key: &str, now: u64) -> Option<StorageEntry> {
+        let internal = self.inner.data.get(key)?;
+        if internal.expires_at > now {
+            self.inner.touch(&internal);
+            Some(internal.entry.clone())
+        } else {
+            None
+        }
+    }
+
+    /// Insert or replace an entry, then enforce the configured limits.
+    fn write(&self, key: &str, entry: StorageEntry, expires_at: u64) {
+        self.update(key, expires_at, |_| (Some(entry), ()));
+    }
+
+    /// Read-modify-write `key` while holding its shard lock, then enforce the
+    /// configured limits.
+    ///
+    /// `operation` receives the live entry, if any, and returns the entry to
+    /// store (or `None` to leave the key untouched).
+    fn update<T>(
+        &self,
+        key: &str,
+        expires_at: u64,
+        operation: impl FnOnce(Option<StorageEntry>) -> (Option<StorageEntry>, T),
+    ) -> T {
+        let now = current_timestamp_ms();
+
+        let result = match self.inner.data.entry(key.to_string()) {
+            Entry::Occupied(mut occupied) => {
+                let internal = occupied.get_mut();
+                let current = (internal.expires_at > now).then(|| internal.entry.clone());
+                let (new, result) = operation(current);
+
+                if let Some(entry) = new {
+                    let size = entry_size(key, &entry);
+                    self.inner.bytes.fetch_add(size, Ordering::Relaxed);
+                    self.inner.bytes.fetch_sub(internal.size, Ordering::Relaxed);
+                    internal.entry = entry;
+                    internal.expires_at = expires_at;
+                    internal.size = size;
+                }
+                self.inner.touch(internal);
+                result
+            }
+            Entry::Vacant(vacant) => {
+                let (new, result) = operation(None);
+
+                if let Some(entry) = new {
+                    let size = entry_size(key, &entry);
+                    self.inner.bytes.fetch_add(size, Ordering::Relaxed);
+                    let internal = self.inner.new_entry(key, entry, expires_at, size);
+                    vacant.insert(internal);
                 }
+                result
             }
+        };
+
+        self.inner.enforce_limits();
+        result
+    }
+}
+
+#[cfg(feature = "snapshot")]
+impl MemoryStorage {
+    /// Restore from and persist to a snapshot file.
+    ///
+    /// Entries in an existing snapshot are restored immediately. A missing,
+    /// unreadable or corrupted file is logged and otherwise ignored so that
+    /// startup never fails because of it. Snapshots are then written every
+    /// `interval` (if set) and when the storage is dropped (if enabled).
+    ///
+    /// # Example
+    ///
+    /// ```ignore
+    /// use skp_ratelimit::storage::{MemoryStorage, SnapshotConfig};
+    /// use std::time::Duration;
+    ///
+    /// let storage = MemoryStorage::new().with_snapshot(
+    ///     SnapshotConfig::new("/var/lib/gateway/ratelimit.snap")
+    ///         .with_interval(Duration::from_secs(30)),
+    /// );
+    /// ```
+    pub fn with_snapshot(mut self, config: SnapshotConfig) -> Self {
+        match self.load_snapshot(&config.path) {
+            Ok(stats) if stats.corrupted > 0 => tracing::warn!(
+                path = %config.path.display(),
+                corrupted = stats.corrupted,
+                restored = stats.restored,
+                "skipped corrupted rate limit snapshot records"
+            ),
+            Ok(_) => {}
+            Err(e) => tracing::warn!(
+                path = %config.path.display(),
+                error = %e,
+                "ignoring unusable rate limit snapshot"
+            ),
+        }
+
+        if let Some(interval) = config.interval {
+            self.start_snapshot_task(config.path.clone(), interval);
+        }
+        self.snapshot = Some(config);
+        self
+    }
+
+    /// Write all live entries to `path`, returning how many were written.
+    ///
+    /// The file is replaced atomically.
+    pub fn save_snapshot(&self, path: impl AsRef<Path>) -> Result<usize> {
+        self.inner.save_snapshot(path.as_ref())
+    }
+
+    /// Restore live entries from the snapshot at `path`.
+    ///
+    /// A missing file restores nothing. Corrupted records are skipped and
+    /// counted; only an unreadable file or header is an error.
+    pub fn load_snapshot(&self, path: impl AsRef<Path>) -> Result<SnapshotStats> {
+        let Some(bytes) = snapshot::read(path.as_ref())? else {
+            return Ok(SnapshotStats::default());
+        };
+
+        let (records, stats) = snapshot::decode(&bytes, current_timestamp_ms())?;
+        for (key, entry, expires_at) in records {
+            self.write(&key, entry, expires_at);
         }
+        Ok(stats)
+    }
+
+    /// Start background snapshot task.
+    fn start_snapshot_task(&self, path: PathBuf, interval: Duration) {
+        let inner = Arc::downgrade(&self.inner);
+        let shutdown = self.shutdown.clone();
+
+        tokio::spawn(async move {
+            loop {
+                tokio::select! {
+                    _ = tokio::time::sleep(interval) => {
+                        let Some(inner) = Weak::upgrade(&inner) else {
+                            break;
+                        };
+                        let path = path.clone();
+                        let result = tokio::task::spawn_blocking(move || inner.save_snapshot(&path)).await;
+                        if let Ok(Err(e)) = result {
+                            tracing::warn!(error = %e, "periodic rate limit snapshot failed");
+                        }
+                    }
+                    _ = shutdown.notified() => {
+                        break;
+                    }
+                }
+            }
+        });
     }
 }
 
 impl Drop for MemoryStorage {
     fn drop(&mut self) {
         self.shutdown.notify_waiters();
+
+        #[cfg(feature = "snapshot")]
+        if let Some(config) = self.snapshot.as_ref().filter(|c| c.save_on_drop)
+            && let Err(e) = self.inner.save_snapshot(&config.path)
+        {
+            tracing::warn!(error = %e, "final rate limit snapshot failed");
+        }
     }
 }
 
-/// Run garbage collection on a DashMap.
-fn run_gc_on_map(data: &DashMap<String, InternalEntry>, max_age: Duration) {
-    let now = current_timestamp_ms();
-    let max_age_ms = max_age.as_millis() as u64;
-    let cutoff = now.saturating_sub(max_age_ms);
+impl Inner {
+    #[cfg(feature = "snapshot")]
+    fn save_snapshot(&self, path: &Path) -> Result<usize> {
+        let _guard = self.snapshot_lock.lock();
+        let now = current_timestamp_ms();
+
+        let mut writer = SnapshotWriter::new(now);
+        let mut written = 0;
+        for item in self.data.iter() {
+            let internal = item.value();
+            if internal.expires_at > now {
+                writer.push(item.key(), &internal.entry, internal.expires_at)?;
+                written += 1;
+            }
+        }
+
+        snapshot::write_atomic(path, &writer.finish())?;
+        Ok(written)
+    }
+
+    /// Build a new entry and schedule its expiry.
+    fn new_entry(
+        &self,
+        key: &str,
+        entry: StorageEntry,
+        expires_at: u64,
+        size: usize,
+    ) -> InternalEntry {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        let collectable_at = expires_at.max(entry.last_update.saturating_add(self.max_age_ms()));
+        InternalEntry {
+            id,
+            timer: self.expiry.schedule(key, id, collectable_at),
+            entry,
+            expires_at,
+            size,
+            last_access: AtomicU64::new(self.clock.fetch_add(1, Ordering::Relaxed) + 1),
+            hits: AtomicU64::new(1),
+        }
+    }
+
+    fn max_age_ms(&self) -> u64 {
+        self.gc_config.max_age.as_millis() as u64
+    }
+
+    fn touch(&self, internal: &InternalEntry) {
+        let tick = self.clock.fetch_add(1, Ordering::Relaxed) + 1;
+        internal.last_access.store(tick, Ordering::Relaxed);
+        internal.hits.fetch_add(1, Ordering::Relaxed);
+    }
+
+    fn remove(&self, key: &str) {
+        if let Some((_, internal)) = self.data.remove(key) {
+            self.unlink(key, &internal);
+        }
+    }
+
+    /// Release the accounting and wheel item of an entry removed from `data`.
+    fn unlink(&self, key: &str, internal: &InternalEntry) {
+        self.bytes.fetch_sub(internal.size, Ordering::Relaxed);
+        self.expiry.cancel(key, internal.timer);
+    }
+
+    /// Remove due entries that are expired and older than `max_age`,
+    /// returning how many were removed.
+    fn run_gc(&self, limit: Option<usize>) -> usize {
+        let now = current_timestamp_ms();
+        let max_age_ms = self.max_age_ms();
+        let mut removed_count = 0;
+
+        for (key, id) in self.expiry.due(now, limit.unwrap_or(usize::MAX)) {
+            // Remove if expired and too old
+            let removed = self.data.remove_if(&key, |_, internal| {
+                internal.id == id && internal.collectable_at(max_age_ms) <= now
+            });
+            if let Some((_, internal)) = removed {
+                self.bytes.fetch_sub(internal.size, Ordering::Relaxed);
+                removed_count += 1;
+                continue;
+            }
+
+            // Still alive: reschedule at its new deadline
+            if let Some(mut internal) = self.data.get_mut(&key).filter(|internal| internal.id == id) {
+                let deadline = internal.collectable_at(max_age_ms);
+                internal.timer = self.expiry.schedule(&key, id, deadline);
+            }
+        }
+        removed_count
+    }
+
+    fn over(&self, max_entries: Option<usize>, max_bytes: Option<usize>) -> bool {
+        max_entries.is_some_and(|max| self.data.len() > max)
+            || max_bytes.is_some_and(|max| self.bytes.load(Ordering::Relaxed) > max)
+    }
+
+    /// Evict entries until the store is within its configured limits.
+    ///
+    /// Only takes single-key locks: expired entries come from the due items
+    /// of the expiry wheels, and each further eviction removes the lowest
+    /// ranked of a few sampled entries.
+    fn enforce_limits(&self) {
+        let config = &self.gc_config;
+        if !self.over(config.max_entries, config.max_bytes) {
+            return;
+        }
+
+        let _guard = self.evict_lock.lock();
+        if !self.over(config.max_entries, config.max_bytes) {
+            return;
+        }
+
+        // Evict down to a low-water mark so evictions are batched once the
+        // store is at capacity.
+        let low_water = |max: usize| max - max / 16;
+        let max_entries = config.max_entries.map(low_water);
+        let max_bytes = config.max_bytes.map(low_water);
 
-    data.retain(|_, entry| {
-        // Keep if not expired and not too old
-        entry.expires_at > now || entry.entry.last_update > cutoff
-    });
+        // Expired entries go first
+        let mut expired = self.run_gc(Some(config.sweep_limit)) as u64;
+        let mut evicted = 0;
+
+        while self.over(max_entries, max_bytes) {
+            let now = current_timestamp_ms();
+            let Some((rank, key, id)) = self.pick_victim(now) else {
+                break;
+            };
+
+            // Skip entries touched since they were sampled
+            let removed = self.data.remove_if(&key, |_, internal| {
+                internal.id == id && internal.rank(config.eviction, now) == rank
+            });
+            if let Some((key, internal)) = removed {
+                self.unlink(&key, &internal);
+                let (live, ..) = rank;
+                if live {
+                    evicted += 1;
+                } else {
+                    expired += 1;
+                }
+            }
+        }
+
+        self.evicted_expired.fetch_add(expired, Ordering::Relaxed);
+        self.evicted_live.fetch_add(evicted, Ordering::Relaxed);
+    }
+
+    /// Sample a few entries and return the lowest ranked one.
+    fn pick_victim(&self, now: u64) -> Option<((bool, u64, u64), String, u64)> {
+        self.expiry
+            .sample(EVICTION_SAMPLES)
+            .into_iter()
+            .filter_map(|(key, id)| {
+                let internal = self.data.get(&key).filter(|internal| internal.id == id)?;
+                let rank = internal.rank(self.gc_config.eviction, now);
+                drop(internal);
+                Some((rank, key, id))
+            })
+            .min_by_key(|(rank, ..)| *rank)
+    }
 }
 
 impl Storage for MemoryStorage {
@@ -236,14 +832,14 @@ impl Storage for MemoryStorage {
         self.maybe_run_gc();
 
         let now = current_timestamp_ms();
-        if let Some(internal) = self.data.get(key) {
-            if internal.expires_at > now {
-                return Ok(Some(internal.entry.clone()));
-            }
-            // Entry expired, remove it
-            drop(internal);
-            self.data.remove(key);
+        if let Some(entry) = self.read(key, now) {
+            return Ok(Some(entry));
         }
+        // Entry expired, remove it
+        self.inner
+            .data
+            .remove_if(key, |_, internal| internal.expires_at <= now)
+            .inspect(|(key, internal)| self.inner.unlink(key, internal));
         Ok(None)
     }
 
@@ -251,15 +847,12 @@ impl Storage for MemoryStorage {
         self.maybe_run_gc();
 
         let expires_at = current_timestamp_ms() + ttl.as_millis() as u64;
-        self.data.insert(
-            key.to_string(),
-            InternalEntry { entry, expires_at },
-        );
+        self.write(key, entry, expires_at);
         Ok(())
     }
 
     async fn delete(&self, key: &str) -> Result<()> {
-        self.data.remove(key);
+        self.inner.remove(key);
         Ok(())
     }
 
@@ -275,27 +868,11 @@ impl Storage for MemoryStorage {
         let expires_at = current_timestamp_ms() + ttl.as_millis() as u64;
         let now = current_timestamp_ms();
 
-        let new_count = self.data
-            .entry(key.to_string())
-            .and_modify(|internal| {
-                // Check if we're in a new window
-                if internal.entry.window_start != window_start {
-                    // Store old count as prev_count for sliding window
-                    internal.entry.prev_count = Some(internal.entry.count);
-                    internal.entry.count = delta;
-                    internal.entry.window_start = window_start;
-                } else {
-                    internal.entry.count += delta;
-                }
-                internal.entry.last_update = now;
-                internal.expires_at = expires_at;
-            })
-            .or_insert_with(|| InternalEntry {
-                entry: StorageEntry::new(delta, window_start).set_last_update(now),
-                expires_at,
-            })
-            .entry
-            .count;
+        let new_count = self.update(key, expires_at, |current| {
+            let entry = primitives::increment_entry(current, delta, window_start, now);
+            let count = entry.count;
+            (Some(entry), count)
+        });
 
         Ok(new_count)
     }
@@ -308,29 +885,13 @@ impl Storage for MemoryStorage {
         self.maybe_run_gc();
 
         let expires_at = current_timestamp_ms() + ttl.as_millis() as u64;
-        let now = current_timestamp_ms();
 
-        // Get current entry
-        let current = self.data.get(key).and_then(|internal| {
-            if internal.expires_at > now {
-                Some(internal.entry.clone())
-            } else {
-                None
-            }
+        // Read, modify and store under the shard lock
+        let result = self.update(key, expires_at, |current| {
+            let (new_entry, result) = operation(current);
+            (Some(new_entry), result)
         });
 
-        // Execute operation
-        let (new_entry, result) = operation(current);
-
-        // Store new entry
-        self.data.insert(
-            key.to_string(),
-            InternalEntry {
-                entry: new_entry,
-                expires_at,
-            },
-        );
-
         Ok(result)
     }
 
@@ -344,36 +905,18 @@ impl Storage for MemoryStorage {
         self.maybe_run_gc();
 
         let expires_at = current_timestamp_ms() + ttl.as_millis() as u64;
-        let now = current_timestamp_ms();
 
-        // Get current entry
-        let current = self.data.get(key).and_then(|internal| {
-            if internal.expires_at > now {
-                Some(internal.entry.clone())
-            } else {
-                None
-            }
+        let swapped = self.update(key, expires_at, |current| {
+            // Check if expected matches current
+            let matches = match (expected, &current) {
+                (None, None) => true,
+                (Some(exp), Some(cur)) => exp == cur,
+                _ => false,
+            };
+            (matches.then_some(new), matches)
         });
 
-        // Check if expected matches current
-        let matches = match (expected, &current) {
-            (None, None) => true,
-            (Some(exp), Some(cur)) => exp == cur,
-            _ => false,
-        };
-
-        if matches {
-            self.data.insert(
-                key.to_string(),
-                InternalEntry {
-                    entry: new,
-                    expires_at,
-                },
-            );
-            Ok(true)
-        } else {
-            Ok(false)
-        }
+        Ok(swapped)
     }
 }
 
@@ -489,4 +1032,197 @@ mod tests {
         assert!(matches!(config.interval, GcInterval::Requests(1000)));
         assert_eq!(config.max_age, Duration::from_secs(3600));
     }
+
+    #[tokio::test]
+    async fn test_max_entries_evicts_lru() {
+        let storage = MemoryStorage::with_gc(GcConfig::manual().with_max_entries(3));
+        let ttl = Duration::from_secs(60);
+
+        for key in ["a", "b", "c"] {
+            storage.set(key, StorageEntry::new(1, 1000), ttl).await.unwrap();
+        }
+        // Touch "a" so "b" becomes least recently used
+        storage.get("a").await.unwrap();
+        storage.set("d", StorageEntry::new(1, 1000), ttl).await.unwrap();
+
+        assert_eq!(storage.len(), 3);
+        assert!(storage.get("b").await.unwrap().is_none());
+        assert!(storage.get("a").await.unwrap().is_some());
+        assert_eq!(storage.eviction_stats(), EvictionStats { expired: 0, evicted: 1 });
+    }
+
+    #[tokio::test]
+    async fn test_max_entries_evicts_lfu() {
+        let config = GcConfig::manual()
+            .with_max_entries(2)
+            .with_eviction(EvictionPolicy::Lfu);
+        let storage = MemoryStorage::with_gc(config);
+        let ttl = Duration::from_secs(60);
+
+        storage.increment("hot", 1, 1000, ttl).await.unwrap();
+        storage.increment("cold", 1, 1000, ttl).await.unwrap();
+        for _ in 0..5 {
+            storage.increment("hot", 1, 1000, ttl).await.unwrap();
+        }
+        storage.increment("new", 1, 1000, ttl).await.unwrap();
+
+        assert_eq!(storage.len(), 2);
+        assert!(storage.get("cold").await.unwrap().is_none());
+        assert!(storage.get("hot").await.unwrap().is_some());
+    }
+
+    #[tokio::test]
+    async fn test_sampled_eviction_keeps_hot_keys() {
+        let storage = MemoryStorage::with_gc(GcConfig::manual().with_max_entries(1000));
+        let ttl = Duration::from_secs(60);
+
+        for i in 0..700 {
+            storage.set(&format!("cold-{}", i), StorageEntry::new(1, 1000), ttl).await.unwrap();
+        }
+        for i in 0..300 {
+            storage.set(&format!("hot-{}", i), StorageEntry::new(1, 1000), ttl).await.unwrap();
+        }
+        for i in 0..100 {
+            storage.set(&format!("new-{}", i), StorageEntry::new(1, 1000), ttl).await.unwrap();
+        }
+
+        assert!(storage.len() <= 1000);
+        assert!(storage.eviction_stats().evicted >= 100);
+        let mut hot = 0;
+        for i in 0..300 {
+            hot += storage.get(&format!("hot-{}", i)).await.unwrap().is_some() as usize;
+        }
+        // Sampling is approximate, but cold keys make up most of every sample
+        assert!(hot >= 295, "only {} hot keys survived", hot);
+    }
+
+    #[tokio::test]
+    async fn test_eviction_prefers_expired() {
+        let storage = MemoryStorage::with_gc(GcConfig::manual().with_max_entries(2));
+
+        storage.set("old", StorageEntry::new(1, 1000), Duration::from_millis(5)).await.unwrap();
+        storage.set("live", StorageEntry::new(1, 1000), Duration::from_secs(60)).await.unwrap();
+        tokio::time::sleep(Duration::from_millis(10)).await;
+        storage.set("new", StorageEntry::new(1, 1000), Duration::from_secs(60)).await.unwrap();
+
+        assert_eq!(storage.len(), 2);
+        assert!(storage.get("live").await.unwrap().is_some());
+        assert_eq!(storage.eviction_stats(), EvictionStats { expired: 1, evicted: 0 });
+    }
+
+    #[tokio::test]
+    async fn test_max_bytes() {
+        let entry = StorageEntry::with_timestamps(vec![0; 64]);
+        let size = entry_size("key-0", &entry);
+        let storage = MemoryStorage::with_gc(GcConfig::manual().with_max_bytes(size * 4));
+        let ttl = Duration::from_secs(60);
+
+        for i in 0..10 {
+            storage.set(&format!("key-{}", i), entry.clone(), ttl).await.unwrap();
+            assert!(storage.approx_bytes() <= size * 4);
+        }
+        assert_eq!(storage.len(), 4);
+
+        storage.clear();
+        assert_eq!(storage.approx_bytes(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_rotating_keys_keep_wheels_bounded() {
+        let storage = MemoryStorage::with_gc(GcConfig::manual().with_max_entries(100));
+        let ttl = Duration::from_secs(60);
+
+        for i in 0..5000 {
+            let key = format!("client-{}", i);
+            storage.increment(&key, 1, 1000, ttl).await.unwrap();
+            match i % 3 {
+                0 => storage.delete(&key).await.unwrap(),
+                1 => {
+                    storage.set(&key, StorageEntry::new(1, 1000), Duration::ZERO).await.unwrap();
+                    storage.get(&key).await.unwrap();
+                }
+                _ => {}
+            }
+            assert!(storage.inner.expiry.len() <= 100);
+        }
+
+        assert!(storage.eviction_stats().evicted > 0);
+        assert_eq!(storage.inner.expiry.len(), storage.len());
+        storage.clear();
+        assert_eq!(storage.inner.expiry.len(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_duration_gc_sees_live_data() {
+        let config = GcConfig::on_duration(Duration::from_millis(10))
+            .with_max_age(Duration::ZERO);
+        let storage = MemoryStorage::with_gc(config);
+
+        storage.set("key1", StorageEntry::new(1, 0), Duration::from_millis(1)).await.unwrap();
+        tokio::time::sleep(Duration::from_millis(50)).await;
+
+        assert!(storage.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_gc_sweep_is_bounded() {
+        let config = GcConfig::manual()
+            .with_max_age(Duration::ZERO)
+            .with_sweep_limit(2);
+        let storage = MemoryStorage::with_gc(config);
+
+        for i in 0..5 {
+            let key = format!("key{}", i);
+            storage.set(&key, StorageEntry::new(1, 0), Duration::from_millis(1)).await.unwrap();
+        }
+        tokio::time::sleep(Duration::from_millis(30)).await;
+
+        storage.inner.run_gc(Some(storage.inner.gc_config.sweep_limit));
+        assert_eq!(storage.len(), 3);
+
+        storage.run_gc().await;
+        assert!(storage.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_gc_reschedules_extended_entries() {
+        let storage = MemoryStorage::with_gc(GcConfig::manual().with_max_age(Duration::ZERO));
+
+        storage.set("key1", StorageEntry::new(1, 0), Duration::from_millis(1)).await.unwrap();
+        storage.increment("key1", 1, 0, Duration::from_secs(60)).await.unwrap();
+        tokio::time::sleep(Duration::from_millis(30)).await;
+
+        storage.run_gc().await;
+        assert_eq!(storage.get("key1").await.unwrap().map(|e| e.count), Some(2));
+    }
+
+    #[cfg(feature = "snapshot")]
+    #[tokio::test]
+    async fn test_snapshot_roundtrip() {
+        let path = std::env::temp_dir().join(format!("skp-ratelimit-{}.snap", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+
+        {
+            let storage = MemoryStorage::new().with_snapshot(SnapshotConfig::new(&path));
+            storage.set("live", StorageEntry::with_tat(42), Duration::from_secs(60)).await.unwrap();
+            storage.set("short", StorageEntry::new(1, 0), Duration::from_millis(5)).await.unwrap();
+            tokio::time::sleep(Duration::from_millis(10)).await;
+            // Saved on drop
+        }
+
+        let storage = MemoryStorage::new();
+        let stats = storage.load_snapshot(&path).unwrap();
+        assert_eq!(stats, SnapshotStats { restored: 1, expired: 0, corrupted: 0 });
+        assert_eq!(storage.get("live").await.unwrap(), Some(StorageEntry::with_tat(42)));
+        assert!(storage.get("short").await.unwrap().is_none());
+
+        // A corrupted file doesn't prevent startup
+        std::fs::write(&path, b"garbage").unwrap();
+        let storage = MemoryStorage::new()
+            .with_snapshot(SnapshotConfig::new(&path).with_save_on_drop(false));
+        assert!(storage.is_empty());
+        assert!(storage.load_snapshot(&path).is_err());
+
+        std::fs::remove_file(&path).unwrap();
+    }
 }
@@ -1,23 +1,50 @@
 //! Storage backend trait and implementations.
 //!
 //! This module defines the `Storage` trait that all storage backends must implement,
-//! along with built-in implementations for in-memory and Redis storage.
+//! along with built-in implementations for in-memory, Redis and embedded
+//! (redb) storage.
 
+mod circuit_breaker;
+mod codec;
+#[cfg(feature = "testing")]
+pub mod conformance;
+#[cfg(feature = "redb")]
+mod embedded;
 mod entry;
 #[cfg(feature = "memory")]
 mod memory_gc;
+pub mod primitives;
 #[cfg(feature = "redis")]
 mod redis_cluster;
+mod retry;
+#[cfg(feature = "snapshot")]
+mod snapshot;
+mod timeout;
+#[cfg(feature = "memory")]
+mod timer_wheel;
 
+pub use circuit_breaker::{
+    CircuitBreakerConfig, CircuitBreakerStats, CircuitBreakerStorage, CircuitState,
+};
+pub use codec::EntryCodec;
 pub use entry::StorageEntry;
+pub use primitives::{BucketUpdate, LogState, TatUpdate, WindowUpdate};
+pub use retry::{RetryConfig, RetryStorage};
+pub use timeout::TimeoutStorage;
 
 #[cfg(feature = "memory")]
-pub use memory_gc::{GcConfig, GcInterval, MemoryStorage};
+pub use memory_gc::{EvictionPolicy, EvictionStats, GcConfig, GcInterval, MemoryStorage};
+#[cfg(feature = "snapshot")]
+pub use snapshot::{SnapshotConfig, SnapshotStats};
 
 // RedisStorage with connection pooling
 #[cfg(feature = "redis")]
 pub use redis_cluster::{RedisConfig, RedisStorage};
 
+// Embedded persistent storage
+#[cfg(feature = "redb")]
+pub use embedded::{RedbConfig, RedbStorage};
+
 use std::future::Future;
 use std::time::Duration;
 
@@ -35,6 +62,14 @@ use crate::error::Result;
 /// - `delete`: Remove an entry
 /// - `increment`: Atomically increment a counter
 /// - `execute_atomic`: Execute an atomic read-modify-write operation
+/// - `get_or_insert`: Read an entry, creating it if missing
+///
+/// # Algorithm Primitives
+///
+/// `update_tat`, `take_tokens`, `increment_window`, `append_log` and
+/// `read_log` have default
+/// implementations built on the operations above (see [`primitives`]).
+/// Backends can override them to use native data structures.
 ///
 /// # Example
 ///
@@ -115,6 +150,89 @@ pub trait Storage: Send + Sync + 'static {
         new: StorageEntry,
         ttl: Duration,
     ) -> impl Future<Output = Result<bool>> + Send;
+
+    /// Get the entry of `key`, inserting `entry` if there is none.
+    ///
+    /// Either way the key expires `ttl` from now, but an existing entry is
+    /// not rewritten. Used for per-key metadata that is written once, such
+    /// as a warm-up's first-seen time.
+    fn get_or_insert(
+        &self,
+        key: &str,
+        entry: StorageEntry,
+        ttl: Duration,
+    ) -> impl Future<Output = Result<StorageEntry>> + Send {
+        self.execute_atomic(key, ttl, primitives::get_or_insert_op(entry))
+    }
+
+    /// Atomically advance a GCRA theoretical arrival time.
+    ///
+    /// The request conforms if the new TAT would be at most
+    /// `tolerance_ms + period_ms` ahead of `now`.
+    fn update_tat(
+        &self,
+        key: &str,
+        now: u64,
+        period_ms: u64,
+        tolerance_ms: u64,
+        ttl: Duration,
+    ) -> impl Future<Output = Result<TatUpdate>> + Send {
+        primitives::update_tat(self, key, now, period_ms, tolerance_ms, ttl)
+    }
+
+    /// Atomically refill a token bucket and take `cost` tokens if available.
+    ///
+    /// Missing buckets start full. `refill_rate` is in tokens per second.
+    fn take_tokens(
+        &self,
+        key: &str,
+        now: u64,
+        capacity: f64,
+        refill_rate: f64,
+        cost: f64,
+        ttl: Duration,
+    ) -> impl Future<Output = Result<BucketUpdate>> + Send {
+        primitives::take_tokens(self, key, now, capacity, refill_rate, cost, ttl)
+    }
+
+    /// Atomically count a request in a sliding window if the weighted count
+    /// of the current and previous windows of `window_ms` is under `limit`.
+    ///
+    /// Windows are aligned to multiples of `window_ms`. Denied requests
+    /// leave the entry unchanged.
+    fn increment_window(
+        &self,
+        key: &str,
+        now: u64,
+        window_ms: u64,
+        limit: u64,
+        ttl: Duration,
+    ) -> impl Future<Output = Result<WindowUpdate>> + Send {
+        primitives::increment_window(self, key, now, window_ms, limit, ttl)
+    }
+
+    /// Atomically drop timestamps older than `now - window_ms` and append
+    /// `now` if fewer than `limit` remain.
+    fn append_log(
+        &self,
+        key: &str,
+        now: u64,
+        window_ms: u64,
+        limit: u64,
+        ttl: Duration,
+    ) -> impl Future<Output = Result<LogState>> + Send {
+        primitives::append_log(self, key, now, window_ms, limit, ttl)
+    }
+
+    /// Read the sliding log state within `now - window_ms` without recording.
+    fn read_log(
+        &self,
+        key: &str,
+        now: u64,
+        window_ms: u64,
+    ) -> impl Future<Output = Result<LogState>> + Send {
+        primitives::read_log(self, key, now, window_ms)
+    }
 }
 
 impl<S: Storage + ?Sized> Storage for std::sync::Arc<S> {
@@ -157,6 +275,61 @@ impl<S: Storage + ?Sized> Storage for std::sync::Arc<S> {
     ) -> Result<bool> {
         (**self).compare_and_swap(key, expected, new, ttl).await
     }
+
+    async fn get_or_insert(&self, key: &str, entry: StorageEntry, ttl: Duration) -> Result<StorageEntry> {
+        (**self).get_or_insert(key, entry, ttl).await
+    }
+
+    async fn update_tat(
+        &self,
+        key: &str,
+        now: u64,
+        period_ms: u64,
+        tolerance_ms: u64,
+        ttl: Duration,
+    ) -> Result<TatUpdate> {
+        (**self).update_tat(key, now, period_ms, tolerance_ms, ttl).await
+    }
+
+    async fn take_tokens(
+        &self,
+        key: &str,
+        now: u64,
+        capacity: f64,
+        refill_rate: f64,
+        cost: f64,
+        ttl: Duration,
+    ) -> Result<BucketUpdate> {
+        (**self)
+            .take_tokens(key, now, capacity, refill_rate, cost, ttl)
+            .await
+    }
+
+    async fn append_log(
+        &self,
+        key: &str,
+        now: u64,
+        window_ms: u64,
+        limit: u64,
+        ttl: Duration,
+    ) -> Result<LogState> {
+        (**self).append_log(key, now, window_ms, limit, ttl).await
+    }
+
+    async fn increment_window(
+        &self,
+        key: &str,
+        now: u64,
+        window_ms: u64,
+        limit: u64,
+        ttl: Duration,
+    ) -> Result<WindowUpdate> {
+        (**self).increment_window(key, now, window_ms, limit, ttl).await
+    }
+
+    async fn read_log(&self, key: &str, now: u64, window_ms: u64) -> Result<LogState> {
+        (**self).read_log(key, now, window_ms).await
+    }
 }
 
 impl<S: Storage + ?Sized> Storage for Box<S> {
@@ -199,6 +372,61 @@ impl<S: Storage + ?Sized> Storage for Box<S> {
     ) -> Result<bool> {
         (**self).compare_and_swap(key, expected, new, ttl).await
     }
+
+    async fn get_or_insert(&self, key: &str, entry: StorageEntry, ttl: Duration) -> Result<StorageEntry> {
+        (**self).get_or_insert(key, entry, ttl).await
+    }
+
+    async fn update_tat(
+        &self,
+        key: &str,
+        now: u64,
+        period_ms: u64,
+        tolerance_ms: u64,
+        ttl: Duration,
+    ) -> Result<TatUpdate> {
+        (**self).update_tat(key, now, period_ms, tolerance_ms, ttl).await
+    }
+
+    async fn take_tokens(
+        &self,
+        key: &str,
+        now: u64,
+        capacity: f64,
+        refill_rate: f64,
+        cost: f64,
+        ttl: Duration,
+    ) -> Result<BucketUpdate> {
+        (**self)
+            .take_tokens(key, now, capacity, refill_rate, cost, ttl)
+            .await
+    }
+
+    async fn append_log(
+        &self,
+        key: &str,
+        now: u64,
+        window_ms: u64,
+        limit: u64,
+        ttl: Duration,
+    ) -> Result<LogState> {
+        (**self).append_log(key, now, window_ms, limit, ttl).await
+    }
+
+    async fn increment_window(
+        &self,
+        key: &str,
+        now: u64,
+        window_ms: u64,
+        limit: u64,
+        ttl: Duration,
+    ) -> Result<WindowUpdate> {
+        (**self).increment_window(key, now, window_ms, limit, ttl).await
+    }
+
+    async fn read_log(&self, key: &str, now: u64, window_ms: u64) -> Result<LogState> {
+        (**self).read_log(key, now, window_ms).await
+    }
 }
 
 /// Get the current timestamp in milliseconds since Unix epoch.
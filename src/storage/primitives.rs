@@ -0,0 +1,374 @@
+//! Algorithm-level storage primitives.
+//!
+//! Most algorithms only need a handful of atomic state transitions. Exposing
+//! them as `Storage` methods lets backends implement them natively (e.g. Redis
+//! scripts over hashes and sorted sets) instead of shipping a whole
+//! `StorageEntry` back and forth on every request.
+//!
+//! The functions in this module define the reference semantics. They operate
+//! on the portable `StorageEntry` representation and back the default
+//! implementations of the trait methods.
+
+use std::time::Duration;
+
+use crate::error::Result;
+use crate::storage::{Storage, StorageEntry};
+
+/// Outcome of an atomic GCRA update.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TatUpdate {
+    /// Whether the request conforms.
+    pub allowed: bool,
+    /// Theoretical arrival time stored after the update (Unix milliseconds).
+    pub tat: u64,
+}
+
+/// Outcome of an atomic token bucket update.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BucketUpdate {
+    /// Whether the tokens were taken.
+    pub allowed: bool,
+    /// Tokens left in the bucket after the update.
+    pub tokens: f64,
+}
+
+/// Outcome of an atomic sliding window update.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WindowUpdate {
+    /// Whether the request was counted.
+    pub allowed: bool,
+    /// Requests counted in the current window (including any just counted).
+    pub count: u64,
+    /// Requests counted in the previous window.
+    pub prev_count: u64,
+}
+
+/// State of a sliding log within the current window.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct LogState {
+    /// Whether a timestamp was appended by this operation.
+    pub recorded: bool,
+    /// Number of timestamps in the window (including any just appended).
+    pub count: u64,
+    /// Oldest timestamp still in the window.
+    pub oldest: Option<u64>,
+}
+
+/// Compute a GCRA step from the current TAT.
+///
+/// A request conforms if the new TAT would be at most `tolerance_ms + period_ms`
+/// ahead of `now`. Non-conforming requests leave the TAT unchanged.
+pub(crate) fn gcra_step(current_tat: Option<u64>, now: u64, period_ms: u64, tolerance_ms: u64) -> TatUpdate {
+    // Get effective TAT (starts from now if first request)
+    let effective_tat = current_tat.unwrap_or(now);
+
+    // New TAT would be max(now, current_tat) + period
+    let new_tat = effective_tat.max(now) + period_ms;
+
+    // Check if within burst tolerance
+    if new_tat.saturating_sub(now) <= tolerance_ms + period_ms {
+        TatUpdate {
+            allowed: true,
+            tat: new_tat,
+        }
+    } else {
+        TatUpdate {
+            allowed: false,
+            tat: effective_tat,
+        }
+    }
+}
+
+/// Refill a bucket read from `entry` up to `now`.
+///
+/// Missing entries start full.
+pub(crate) fn bucket_level(entry: Option<&StorageEntry>, now: u64, capacity: f64, refill_rate: f64) -> f64 {
+    let (tokens, last_update) = match entry {
+        Some(e) => (e.tokens.unwrap_or(capacity), e.last_update),
+        None => (capacity, now),
+    };
+
+    if now > last_update {
+        let elapsed_secs = (now - last_update) as f64 / 1000.0;
+        (tokens + elapsed_secs * refill_rate).min(capacity)
+    } else {
+        tokens
+    }
+}
+
+/// Summarize the timestamps of `entry` that fall within `[window_start, ..)`.
+pub(crate) fn log_state(entry: Option<&StorageEntry>, window_start: u64) -> LogState {
+    let timestamps = entry.and_then(|e| e.timestamps.as_deref()).unwrap_or_default();
+    let mut in_window = timestamps.iter().filter(|&&ts| ts >= window_start);
+
+    let oldest = in_window.next().copied();
+    let count = oldest.map_or(0, |_| 1 + in_window.count() as u64);
+
+    LogState {
+        recorded: false,
+        count,
+        oldest,
+    }
+}
+
+/// Start of the window of length `window_ms` containing `now`.
+pub(crate) fn window_start(now: u64, window_ms: u64) -> u64 {
+    (now / window_ms) * window_ms
+}
+
+/// Counts of the current and previous windows read from `entry`.
+///
+/// Entries from older windows count as empty.
+pub(crate) fn window_counts(entry: Option<&StorageEntry>, window_start: u64, window_ms: u64) -> (u64, u64) {
+    match entry {
+        Some(e) if e.window_start == window_start => (e.count, e.prev_count.unwrap_or(0)),
+        Some(e) if e.window_start == window_start.saturating_sub(window_ms) => (0, e.count),
+        _ => (0, 0),
+    }
+}
+
+/// Weighted request count of a sliding window at `now`.
+///
+/// The previous window counts in proportion to how much of it still
+/// overlaps the sliding window.
+pub(crate) fn weighted_count(count: u64, prev_count: u64, now: u64, window_ms: u64) -> f64 {
+    let progress = (now - window_start(now, window_ms)) as f64 / window_ms as f64;
+    count as f64 + prev_count as f64 * (1.0 - progress)
+}
+
+/// Apply an increment to the current entry.
+///
+/// Rolling over to a new window keeps the previous window's count in
+/// `prev_count` (used by the sliding window algorithm).
+#[cfg(any(feature = "memory", feature = "redb"))]
+pub(crate) fn increment_entry(
+    current: Option<StorageEntry>,
+    delta: u64,
+    window_start: u64,
+    now: u64,
+) -> StorageEntry {
+    let entry = match current {
+        Some(mut entry) if entry.window_start == window_start => {
+            entry.count += delta;
+            entry
+        }
+        Some(mut entry) => {
+            entry.prev_count = Some(entry.count);
+            entry.count = delta;
+            entry.window_start = window_start;
+            entry
+        }
+        None => StorageEntry::new(delta, window_start),
+    };
+    entry.set_last_update(now)
+}
+
+/// An `execute_atomic` operation owning everything it captures, so
+/// backends can move it to another thread.
+pub(crate) trait AtomicOp<T>: FnOnce(Option<StorageEntry>) -> (StorageEntry, T) + Send + 'static {}
+
+impl<F, T> AtomicOp<T> for F where F: FnOnce(Option<StorageEntry>) -> (StorageEntry, T) + Send + 'static {}
+
+/// `Storage::get_or_insert` as an atomic operation.
+pub(crate) fn get_or_insert_op(entry: StorageEntry) -> impl AtomicOp<StorageEntry> {
+    move |current| {
+        let entry = current.unwrap_or(entry);
+        (entry.clone(), entry)
+    }
+}
+
+/// `Storage::update_tat` as an atomic operation.
+pub(crate) fn update_tat_op(now: u64, period_ms: u64, tolerance_ms: u64) -> impl AtomicOp<TatUpdate> {
+    move |entry| {
+        let update = gcra_step(entry.and_then(|e| e.tat), now, period_ms, tolerance_ms);
+        (StorageEntry::with_tat(update.tat), update)
+    }
+}
+
+/// `Storage::take_tokens` as an atomic operation.
+pub(crate) fn take_tokens_op(now: u64, capacity: f64, refill_rate: f64, cost: f64) -> impl AtomicOp<BucketUpdate> {
+    move |entry| {
+        let mut tokens = bucket_level(entry.as_ref(), now, capacity, refill_rate);
+        let allowed = tokens >= cost;
+        if allowed {
+            tokens -= cost;
+        }
+        (
+            StorageEntry::with_tokens(tokens, now),
+            BucketUpdate { allowed, tokens },
+        )
+    }
+}
+
+/// `Storage::append_log` as an atomic operation.
+pub(crate) fn append_log_op(now: u64, window_ms: u64, limit: u64) -> impl AtomicOp<LogState> {
+    let window_start = now.saturating_sub(window_ms);
+
+    move |entry| {
+        let mut timestamps: Vec<u64> = entry
+            .and_then(|e| e.timestamps)
+            .unwrap_or_default()
+            .into_iter()
+            .filter(|&ts| ts >= window_start)
+            .collect();
+
+        let recorded = (timestamps.len() as u64) < limit;
+        if recorded {
+            timestamps.push(now);
+        }
+
+        let state = LogState {
+            recorded,
+            count: timestamps.len() as u64,
+            oldest: timestamps.first().copied(),
+        };
+        (StorageEntry::with_timestamps(timestamps), state)
+    }
+}
+
+/// `Storage::increment_window` as an atomic operation.
+pub(crate) fn increment_window_op(now: u64, window_ms: u64, limit: u64) -> impl AtomicOp<WindowUpdate> {
+    let window_start = window_start(now, window_ms);
+
+    move |entry| {
+        let (count, prev_count) = window_counts(entry.as_ref(), window_start, window_ms);
+        if weighted_count(count, prev_count, now, window_ms) < limit as f64 {
+            let entry = StorageEntry::new(count + 1, window_start)
+                .set_prev_count(prev_count)
+                .set_last_update(now);
+            (entry, WindowUpdate { allowed: true, count: count + 1, prev_count })
+        } else {
+            // Denied requests leave the entry as it was
+            let entry = entry.unwrap_or_else(|| StorageEntry::new(0, window_start));
+            (entry, WindowUpdate { allowed: false, count, prev_count })
+        }
+    }
+}
+
+/// Default `Storage::update_tat` on top of `execute_atomic`.
+pub(crate) async fn update_tat<S: Storage + ?Sized>(
+    storage: &S,
+    key: &str,
+    now: u64,
+    period_ms: u64,
+    tolerance_ms: u64,
+    ttl: Duration,
+) -> Result<TatUpdate> {
+    storage
+        .execute_atomic(key, ttl, update_tat_op(now, period_ms, tolerance_ms))
+        .await
+}
+
+/// Default `Storage::take_tokens` on top of `execute_atomic`.
+pub(crate) async fn take_tokens<S: Storage + ?Sized>(
+    storage: &S,
+    key: &str,
+    now: u64,
+    capacity: f64,
+    refill_rate: f64,
+    cost: f64,
+    ttl: Duration,
+) -> Result<BucketUpdate> {
+    storage
+        .execute_atomic(key, ttl, take_tokens_op(now, capacity, refill_rate, cost))
+        .await
+}
+
+/// Default `Storage::append_log` on top of `execute_atomic`.
+pub(crate) async fn append_log<S: Storage + ?Sized>(
+    storage: &S,
+    key: &str,
+    now: u64,
+    window_ms: u64,
+    limit: u64,
+    ttl: Duration,
+) -> Result<LogState> {
+    storage
+        .execute_atomic(key, ttl, append_log_op(now, window_ms, limit))
+        .await
+}
+
+/// Default `Storage::increment_window` on top of `execute_atomic`.
+pub(crate) async fn increment_window<S: Storage + ?Sized>(
+    storage: &S,
+    key: &str,
+    now: u64,
+    window_ms: u64,
+    limit: u64,
+    ttl: Duration,
+) -> Result<WindowUpdate> {
+    storage
+        .execute_atomic(key, ttl, increment_window_op(now, window_ms, limit))
+        .await
+}
+
+/// Default `Storage::read_log` on top of `get`.
+pub(crate) async fn read_log<S: Storage + ?Sized>(
+    storage: &S,
+    key: &str,
+    now: u64,
+    window_ms: u64,
+) -> Result<LogState> {
+    let entry = storage.get(key).await?;
+    Ok(log_state(entry.as_ref(), now.saturating_sub(window_ms)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_gcra_step() {
+        // 100ms period, burst of 3 (tolerance = 2 periods)
+        let first = gcra_step(None, 1000, 100, 200);
+        assert_eq!(first, TatUpdate { allowed: true, tat: 1100 });
+
+        let second = gcra_step(Some(1100), 1000, 100, 200);
+        let third = gcra_step(Some(second.tat), 1000, 100, 200);
+        assert!(second.allowed && third.allowed);
+
+        let denied = gcra_step(Some(third.tat), 1000, 100, 200);
+        assert_eq!(denied, TatUpdate { allowed: false, tat: 1300 });
+    }
+
+    #[test]
+    fn test_window_counts() {
+        let entry = StorageEntry::new(4, 2000).set_prev_count(6);
+        assert_eq!(window_counts(Some(&entry), 2000, 1000), (4, 6));
+        assert_eq!(window_counts(Some(&entry), 3000, 1000), (0, 4));
+        assert_eq!(window_counts(Some(&entry), 4000, 1000), (0, 0));
+
+        // A quarter into the window, three quarters of the previous count
+        assert_eq!(weighted_count(4, 8, 3250, 1000), 10.0);
+    }
+
+    #[test]
+    #[cfg(any(feature = "memory", feature = "redb"))]
+    fn test_increment_entry_rollover() {
+        let entry = increment_entry(None, 2, 1000, 1500);
+        assert_eq!(entry, StorageEntry::new(2, 1000).set_last_update(1500));
+
+        let entry = increment_entry(Some(entry), 3, 1000, 1600);
+        assert_eq!((entry.count, entry.prev_count), (5, None));
+
+        let entry = increment_entry(Some(entry), 1, 2000, 2100);
+        assert_eq!((entry.count, entry.prev_count, entry.window_start), (1, Some(5), 2000));
+    }
+
+    #[test]
+    fn test_bucket_level_refills() {
+        let entry = StorageEntry::with_tokens(1.0, 1000);
+        assert_eq!(bucket_level(Some(&entry), 1500, 10.0, 4.0), 3.0);
+        assert_eq!(bucket_level(Some(&entry), 10_000, 10.0, 4.0), 10.0);
+        assert_eq!(bucket_level(None, 1000, 10.0, 4.0), 10.0);
+    }
+
+    #[test]
+    fn test_log_state() {
+        let entry = StorageEntry::with_timestamps(vec![100, 200, 300]);
+        let state = log_state(Some(&entry), 150);
+        assert_eq!(state.count, 2);
+        assert_eq!(state.oldest, Some(200));
+        assert_eq!(log_state(None, 0), LogState::default());
+    }
+}
@@ -1,13 +1,237 @@
 //! Redis storage backend for distributed rate limiting.
 //!
 //! Uses connection pooling for high performance.
+//!
+//! # Key Layout
+//!
+//! Algorithm primitives use native Redis types, updated atomically by Lua scripts:
+//!
+//! | Primitive | Type | Fields |
+//! |-----------|------|--------|
+//! | `increment`, `increment_window` (Fixed/Sliding Window) | HASH | `count`, `window_start`, `prev_count`, `last_update` |
+//! | `update_tat` (GCRA) | HASH | `tat` |
+//! | `take_tokens` (Token/Leaky Bucket) | HASH | `tokens`, `last_update` |
+//! | `append_log` (Sliding Log) | ZSET | one member per request, scored by timestamp |
+//!
+//! `get_or_insert` writes a STRING only when the key is missing, and otherwise
+//! just extends its TTL.
+//!
+//! Generic operations (`set`, `execute_atomic`, ...) store the
+//! encoded `StorageEntry` as a STRING, updated in `WATCH`/`MULTI`
+//! transactions. `get` reads any of these layouts back
+//! as a `StorageEntry`. A primitive that finds a key of a different type
+//! (e.g. a JSON sliding log written by an older release) starts it afresh.
 
+use std::sync::atomic::{AtomicU64, Ordering};
 use std::time::Duration;
 
-use deadpool_redis::{Config, Pool, Runtime, Connection, redis::{cmd, AsyncCommands}};
+use deadpool_redis::{
+    redis::{cmd, pipe, AsyncCommands, Script, Value},
+    Config, Connection, Pool, Runtime,
+};
 
 use crate::error::{ConnectionError, Result, StorageError};
-use crate::storage::{Storage, StorageEntry};
+use crate::storage::{
+    current_timestamp_ms, primitives, BucketUpdate, EntryCodec, LogState, Storage, StorageEntry,
+    TatUpdate, WindowUpdate,
+};
+
+/// Read a key of any supported layout as `{type, payload}`.
+const LOAD_ENTRY: &str = r#"
+local kind = redis.call('TYPE', KEYS[1]).ok
+if kind == 'string' then
+    return {kind, redis.call('GET', KEYS[1])}
+elseif kind == 'hash' then
+    return {kind, redis.call('HGETALL', KEYS[1])}
+elseif kind == 'zset' then
+    return {kind, redis.call('ZRANGE', KEYS[1], 0, -1, 'WITHSCORES')}
+end
+return {}
+"#;
+
+/// Counter step over a HASH holding `count`, `window_start`, `prev_count`
+/// and `last_update`.
+///
+/// ARGV: delta, window_start, now, ttl_ms
+const INCREMENT: &str = r#"
+local key = KEYS[1]
+local count = tonumber(ARGV[1])
+local kind = redis.call('TYPE', key).ok
+if kind == 'hash' then
+    local state = redis.call('HMGET', key, 'count', 'window_start')
+    if state[2] == ARGV[2] then
+        count = count + (tonumber(state[1]) or 0)
+    elseif state[1] then
+        redis.call('HSET', key, 'prev_count', state[1])
+    end
+elseif kind ~= 'none' then
+    redis.call('DEL', key)
+end
+
+local encoded = string.format('%.0f', count)
+redis.call('HSET', key, 'count', encoded, 'window_start', ARGV[2], 'last_update', ARGV[3])
+redis.call('PEXPIRE', key, ARGV[4])
+return encoded
+"#;
+
+/// Sliding window step over the same HASH as `INCREMENT`.
+///
+/// ARGV: now, window_ms, limit, ttl_ms
+const INCREMENT_WINDOW: &str = r#"
+local key = KEYS[1]
+local now = tonumber(ARGV[1])
+local window_ms = tonumber(ARGV[2])
+local window_start = now - now % window_ms
+local start = string.format('%.0f', window_start)
+
+local count, prev = 0, 0
+local kind = redis.call('TYPE', key).ok
+if kind == 'hash' then
+    local state = redis.call('HMGET', key, 'count', 'window_start', 'prev_count')
+    if state[2] == start then
+        count = tonumber(state[1]) or 0
+        prev = tonumber(state[3]) or 0
+    elseif state[2] == string.format('%.0f', window_start - window_ms) then
+        prev = tonumber(state[1]) or 0
+    end
+elseif kind ~= 'none' then
+    redis.call('DEL', key)
+end
+
+local weighted = count + prev * (1 - (now - window_start) / window_ms)
+local allowed = 0
+if weighted < tonumber(ARGV[3]) then
+    allowed = 1
+    count = count + 1
+    redis.call('HSET', key, 'count', string.format('%.0f', count), 'window_start', start,
+        'prev_count', string.format('%.0f', prev), 'last_update', ARGV[1])
+    redis.call('PEXPIRE', key, ARGV[4])
+end
+return {allowed, count, prev}
+"#;
+
+/// GCRA step over a HASH holding `tat`.
+///
+/// ARGV: now, period_ms, tolerance_ms, ttl_ms
+const UPDATE_TAT: &str = r#"
+local key = KEYS[1]
+local now = tonumber(ARGV[1])
+local period = tonumber(ARGV[2])
+local tolerance = tonumber(ARGV[3])
+
+local tat = nil
+local kind = redis.call('TYPE', key).ok
+if kind == 'hash' then
+    tat = tonumber(redis.call('HGET', key, 'tat'))
+elseif kind ~= 'none' then
+    redis.call('DEL', key)
+end
+
+local effective = tat or now
+local new_tat = math.max(effective, now) + period
+local allowed = 0
+if new_tat - now <= tolerance + period then
+    allowed = 1
+    effective = new_tat
+end
+
+local encoded = string.format('%.0f', effective)
+redis.call('HSET', key, 'tat', encoded)
+redis.call('PEXPIRE', key, ARGV[4])
+return {allowed, encoded}
+"#;
+
+/// Token bucket step over a HASH holding `tokens` and `last_update`.
+///
+/// ARGV: now, capacity, refill_rate (per second), cost, ttl_ms
+const TAKE_TOKENS: &str = r#"
+local key = KEYS[1]
+local now = tonumber(ARGV[1])
+local capacity = tonumber(ARGV[2])
+local rate = tonumber(ARGV[3])
+local cost = tonumber(ARGV[4])
+
+local tokens = capacity
+local last = now
+local kind = redis.call('TYPE', key).ok
+if kind == 'hash' then
+    local state = redis.call('HMGET', key, 'tokens', 'last_update')
+    tokens = tonumber(state[1]) or capacity
+    last = tonumber(state[2]) or now
+elseif kind ~= 'none' then
+    redis.call('DEL', key)
+end
+
+if now > last then
+    tokens = math.min(tokens + (now - last) / 1000 * rate, capacity)
+end
+
+local allowed = 0
+if tokens >= cost then
+    tokens = tokens - cost
+    allowed = 1
+end
+
+local encoded = string.format('%.17g', tokens)
+redis.call('HSET', key, 'tokens', encoded, 'last_update', ARGV[1])
+redis.call('PEXPIRE', key, ARGV[5])
+return {allowed, encoded}
+"#;
+
+/// Sliding log step over a ZSET scored by timestamp.
+///
+/// ARGV: now, window_start, limit, ttl_ms, member
+const APPEND_LOG: &str = r#"
+local key = KEYS[1]
+if redis.call('TYPE', key).ok ~= 'zset' then
+    redis.call('DEL', key)
+end
+
+redis.call('ZREMRANGEBYSCORE', key, '-inf', '(' .. ARGV[2])
+local count = redis.call('ZCARD', key)
+local recorded = 0
+if count < tonumber(ARGV[3]) then
+    redis.call('ZADD', key, ARGV[1], ARGV[5])
+    count = count + 1
+    recorded = 1
+end
+redis.call('PEXPIRE', key, ARGV[4])
+
+local oldest = redis.call('ZRANGE', key, 0, 0, 'WITHSCORES')
+return {recorded, count, oldest[2] or false}
+"#;
+
+/// Count ZSET members scored at or after ARGV[1].
+///
+/// Returns nil for keys of another type so the caller can fall back to `get`.
+const READ_LOG: &str = r#"
+local kind = redis.call('TYPE', KEYS[1]).ok
+if kind == 'none' then
+    return {0, false}
+elseif kind ~= 'zset' then
+    return false
+end
+
+local count = redis.call('ZCOUNT', KEYS[1], ARGV[1], '+inf')
+local oldest = redis.call('ZRANGEBYSCORE', KEYS[1], ARGV[1], '+inf', 'WITHSCORES', 'LIMIT', 0, 1)
+return {count, oldest[2] or false}
+"#;
+
+/// Return the STRING at KEYS[1] and extend its TTL, or store ARGV[1] and
+/// return nil if it's missing (or of another type).
+///
+/// ARGV: encoded entry, ttl_ms
+const GET_OR_INSERT: &str = r#"
+local kind = redis.call('TYPE', KEYS[1]).ok
+if kind == 'string' then
+    redis.call('PEXPIRE', KEYS[1], ARGV[2])
+    return redis.call('GET', KEYS[1])
+elseif kind ~= 'none' then
+    redis.call('DEL', KEYS[1])
+end
+redis.call('SET', KEYS[1], ARGV[1], 'PX', ARGV[2])
+return false
+"#;
 
 /// Redis storage configuration.
 #[derive(Debug, Clone)]
@@ -20,6 +244,8 @@ pub struct RedisConfig {
     pub key_prefix: String,
     /// Connection timeout
     pub connection_timeout: Duration,
+    /// Encoding used when writing entries
+    pub codec: EntryCodec,
 }
 
 impl Default for RedisConfig {
@@ -29,6 +255,7 @@ impl Default for RedisConfig {
             pool_size: 10,
             key_prefix: "rl:".to_string(),
             connection_timeout: Duration::from_secs(5),
+            codec: EntryCodec::default(),
         }
     }
 }
@@ -53,6 +280,15 @@ impl RedisConfig {
         self.pool_size = size;
         self
     }
+
+    /// Set the encoding used when writing entries.
+    ///
+    /// Entries written with either codec can always be read back, so the
+    /// codec can be switched without flushing existing keys.
+    pub fn with_codec(mut self, codec: EntryCodec) -> Self {
+        self.codec = codec;
+        self
+    }
 }
 
 /// Redis storage backend for distributed rate limiting.
@@ -73,12 +309,46 @@ impl RedisConfig {
 pub struct RedisStorage {
     pool: Pool,
     key_prefix: String,
+    codec: EntryCodec,
+    scripts: Scripts,
+    /// Distinguishes sliding log members written by different instances.
+    instance_id: u64,
+    /// Distinguishes sliding log members written in the same millisecond.
+    member_seq: AtomicU64,
+}
+
+/// Lua scripts, hashed once at construction.
+struct Scripts {
+    load_entry: Script,
+    get_or_insert: Script,
+    increment: Script,
+    increment_window: Script,
+    update_tat: Script,
+    take_tokens: Script,
+    append_log: Script,
+    read_log: Script,
+}
+
+impl Scripts {
+    fn new() -> Self {
+        Self {
+            load_entry: Script::new(LOAD_ENTRY),
+            get_or_insert: Script::new(GET_OR_INSERT),
+            increment: Script::new(INCREMENT),
+            increment_window: Script::new(INCREMENT_WINDOW),
+            update_tat: Script::new(UPDATE_TAT),
+            take_tokens: Script::new(TAKE_TOKENS),
+            append_log: Script::new(APPEND_LOG),
+            read_log: Script::new(READ_LOG),
+        }
+    }
 }
 
 impl std::fmt::Debug for RedisStorage {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         f.debug_struct("RedisStorage")
             .field("key_prefix", &self.key_prefix)
+            .field("codec", &self.codec)
             .finish()
     }
 }
@@ -101,9 +371,18 @@ impl RedisStorage {
             .await
             .map_err(|e| ConnectionError::ConnectionFailed(e.to_string()))?;
 
+        let nanos = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map(|d| d.subsec_nanos() as u64)
+            .unwrap_or(0);
+
         Ok(Self {
             pool,
             key_prefix: config.key_prefix,
+            codec: config.codec,
+            scripts: Scripts::new(),
+            instance_id: ((std::process::id() as u64) << 32) ^ nanos,
+            member_seq: AtomicU64::new(0),
         })
     }
 
@@ -124,37 +403,211 @@ impl RedisStorage {
             .await
             .map_err(|_| StorageError::PoolExhausted.into())
     }
-}
 
-impl Storage for RedisStorage {
-    async fn get(&self, key: &str) -> Result<Option<StorageEntry>> {
-        let mut conn = self.get_conn().await?;
-        let full_key = self.full_key(key);
+    /// Read an entry stored in any of the supported layouts.
+    async fn load_entry(&self, conn: &mut Connection, full_key: &str) -> Result<Option<StorageEntry>> {
+        let reply: Vec<Value> = self
+            .scripts
+            .load_entry
+            .key(full_key)
+            .invoke_async(conn)
+            .await
+            .map_err(|e| StorageError::operation_failed(e.to_string(), true))?;
+
+        if reply.is_empty() {
+            return Ok(None);
+        }
+
+        let [kind, payload] = <[Value; 2]>::try_from(reply).map_err(|_| unexpected_reply())?;
+        match (bulk(&kind), payload) {
+            (Some(b"string"), Value::BulkString(bytes)) => EntryCodec::decode(&bytes).map(Some),
+            (Some(b"hash"), Value::Array(fields)) => entry_from_hash(&fields).map(Some),
+            (Some(b"zset"), Value::Array(members)) => {
+                let timestamps = members
+                    .chunks(2)
+                    .map(|pair| pair.get(1).and_then(parse_number).map(|score| score as u64))
+                    .collect::<Option<Vec<_>>>()
+                    .ok_or_else(unexpected_reply)?;
+                Ok(Some(StorageEntry::with_timestamps(timestamps)))
+            }
+            _ => Err(unexpected_reply()),
+        }
+    }
 
-        let result: Option<String> = conn
-            .get(&full_key)
+    /// `WATCH` a key and read its entry.
+    ///
+    /// Must be followed by `commit_watched` or `unwatch` on the same
+    /// connection, held in a [`WatchedConnection`].
+    async fn watch_entry(&self, conn: &mut Connection, full_key: &str) -> Result<Option<StorageEntry>> {
+        cmd("WATCH")
+            .arg(full_key)
+            .query_async::<()>(conn)
             .await
             .map_err(|e| StorageError::operation_failed(e.to_string(), true))?;
 
-        match result {
-            Some(json) => {
-                let entry: StorageEntry = serde_json::from_str(&json)
-                    .map_err(|e| StorageError::Serialization(e.to_string()))?;
-                Ok(Some(entry))
+        match self.load_entry(conn, full_key).await {
+            Ok(entry) => Ok(entry),
+            Err(e) => {
+                unwatch(conn).await?;
+                Err(e)
             }
-            None => Ok(None),
         }
     }
 
+    /// Store `entry` if the watched key is unchanged since `watch_entry`.
+    async fn commit_watched(
+        &self,
+        conn: &mut Connection,
+        full_key: &str,
+        entry: &StorageEntry,
+        ttl: Duration,
+    ) -> Result<bool> {
+        let bytes = match self.codec.encode(entry) {
+            Ok(bytes) => bytes,
+            Err(e) => {
+                unwatch(conn).await?;
+                return Err(e);
+            }
+        };
+
+        // EXEC replies nil when a watched key was modified
+        let reply: Value = pipe()
+            .atomic()
+            .pset_ex(full_key, bytes, ttl_millis(ttl))
+            .ignore()
+            .query_async(conn)
+            .await
+            .map_err(|e| StorageError::operation_failed(e.to_string(), true))?;
+
+        Ok(!matches!(reply, Value::Nil))
+    }
+}
+
+/// A pooled connection used for a `WATCH`/`MULTI` transaction.
+///
+/// If the future running the transaction is dropped (e.g. by
+/// `TimeoutStorage`) or fails before [`finish`](Self::finish), the
+/// connection may still be watching a key. It is then closed instead of
+/// being returned to the pool, where a later transaction on it could fail
+/// spuriously.
+struct WatchedConnection {
+    conn: Option<Connection>,
+}
+
+impl WatchedConnection {
+    fn new(conn: Connection) -> Self {
+        Self { conn: Some(conn) }
+    }
+
+    /// Return the connection to the pool after `EXEC` or `UNWATCH`.
+    fn finish(mut self) {
+        self.conn.take();
+    }
+}
+
+impl std::ops::Deref for WatchedConnection {
+    type Target = Connection;
+
+    fn deref(&self) -> &Connection {
+        self.conn.as_ref().expect("connection is present until finished")
+    }
+}
+
+impl std::ops::DerefMut for WatchedConnection {
+    fn deref_mut(&mut self) -> &mut Connection {
+        self.conn.as_mut().expect("connection is present until finished")
+    }
+}
+
+impl Drop for WatchedConnection {
+    fn drop(&mut self) {
+        if let Some(conn) = self.conn.take() {
+            drop(Connection::take(conn));
+        }
+    }
+}
+
+/// Release a `WATCH` without writing.
+async fn unwatch(conn: &mut Connection) -> Result<()> {
+    cmd("UNWATCH")
+        .query_async::<()>(conn)
+        .await
+        .map_err(|e| StorageError::operation_failed(e.to_string(), true).into())
+}
+
+/// Error for script replies that don't match the expected shape.
+fn unexpected_reply() -> crate::error::RateLimitError {
+    StorageError::Serialization("unexpected reply from Redis script".into()).into()
+}
+
+/// Get the bytes of a bulk string reply.
+fn bulk(value: &Value) -> Option<&[u8]> {
+    match value {
+        Value::BulkString(bytes) => Some(bytes),
+        _ => None,
+    }
+}
+
+/// Parse a numeric bulk string reply.
+fn parse_number(value: &Value) -> Option<f64> {
+    std::str::from_utf8(bulk(value)?).ok()?.parse().ok()
+}
+
+/// Build an entry from flattened `HGETALL` output.
+fn entry_from_hash(fields: &[Value]) -> Result<StorageEntry> {
+    let mut count = None;
+    let mut window_start = None;
+    let mut prev_count = None;
+    let mut tat = None;
+    let mut tokens = None;
+    let mut last_update = None;
+
+    for pair in fields.chunks(2) {
+        let [name, value] = pair else {
+            return Err(unexpected_reply());
+        };
+        let value = parse_number(value).ok_or_else(unexpected_reply)?;
+        match bulk(name) {
+            Some(b"count") => count = Some(value as u64),
+            Some(b"window_start") => window_start = Some(value as u64),
+            Some(b"prev_count") => prev_count = Some(value as u64),
+            Some(b"tat") => tat = Some(value as u64),
+            Some(b"tokens") => tokens = Some(value),
+            Some(b"last_update") => last_update = Some(value as u64),
+            _ => {}
+        }
+    }
+
+    Ok(match (count, tat, tokens) {
+        (Some(count), _, _) => {
+            let entry = StorageEntry::new(count, window_start.unwrap_or_default())
+                .set_last_update(last_update.unwrap_or_default());
+            match prev_count {
+                Some(prev_count) => entry.set_prev_count(prev_count),
+                None => entry,
+            }
+        }
+        (None, _, Some(tokens)) => StorageEntry::with_tokens(tokens, last_update.unwrap_or_default()),
+        (None, Some(tat), None) => StorageEntry::with_tat(tat),
+        (None, None, None) => StorageEntry::default(),
+    })
+}
+
+impl Storage for RedisStorage {
+    async fn get(&self, key: &str) -> Result<Option<StorageEntry>> {
+        let mut conn = self.get_conn().await?;
+        let full_key = self.full_key(key);
+
+        self.load_entry(&mut conn, &full_key).await
+    }
+
     async fn set(&self, key: &str, entry: StorageEntry, ttl: Duration) -> Result<()> {
         let mut conn = self.get_conn().await?;
         let full_key = self.full_key(key);
-        let ttl_secs = ttl.as_secs();
 
-        let json = serde_json::to_string(&entry)
-            .map_err(|e| StorageError::Serialization(e.to_string()))?;
+        let bytes = self.codec.encode(&entry)?;
 
-        conn.set_ex::<_, _, ()>(&full_key, json, ttl_secs)
+        conn.pset_ex::<_, _, ()>(&full_key, bytes, ttl_millis(ttl))
             .await
             .map_err(|e| StorageError::operation_failed(e.to_string(), true))?;
 
@@ -181,132 +634,251 @@ impl Storage for RedisStorage {
     ) -> Result<u64> {
         let mut conn = self.get_conn().await?;
         let full_key = self.full_key(key);
-        let ttl_secs = ttl.as_secs();
 
-        // Get current value
-        let current: Option<String> = conn
-            .get(&full_key)
+        self.scripts
+            .increment
+            .key(&full_key)
+            .arg(delta)
+            .arg(window_start)
+            .arg(current_timestamp_ms())
+            .arg(ttl_millis(ttl))
+            .invoke_async(&mut conn)
             .await
-            .map_err(|e| StorageError::operation_failed(e.to_string(), true))?;
+            .map_err(|e| StorageError::operation_failed(e.to_string(), true).into())
+    }
 
-        let new_count = match current {
-            Some(json) => {
-                if let Ok(entry) = serde_json::from_str::<StorageEntry>(&json) {
-                    if entry.window_start == window_start {
-                        entry.count + delta
-                    } else {
-                        delta
-                    }
-                } else {
-                    delta
-                }
-            }
-            None => delta,
-        };
+    /// Runs `operation` inside a `WATCH`/`MULTI` transaction.
+    ///
+    /// `operation` cannot be re-run, so if the key is modified concurrently
+    /// this returns the retryable `StorageError::AtomicConflict` instead of
+    /// overwriting the other update.
+    async fn execute_atomic<F, T>(&self, key: &str, ttl: Duration, operation: F) -> Result<T>
+    where
+        F: FnOnce(Option<StorageEntry>) -> (StorageEntry, T) + Send,
+        T: Send,
+    {
+        let mut conn = WatchedConnection::new(self.get_conn().await?);
+        let full_key = self.full_key(key);
 
-        let now = crate::storage::current_timestamp_ms();
-        let new_entry = StorageEntry {
-            count: new_count,
-            window_start,
-            last_update: now,
-            ..Default::default()
+        let current = self.watch_entry(&mut conn, &full_key).await?;
+        let (new_entry, result) = operation(current);
+
+        let committed = self.commit_watched(&mut conn, &full_key, &new_entry, ttl).await?;
+        conn.finish();
+        if committed {
+            Ok(result)
+        } else {
+            Err(StorageError::AtomicConflict.into())
+        }
+    }
+
+    async fn compare_and_swap(
+        &self,
+        key: &str,
+        expected: Option<&StorageEntry>,
+        new: StorageEntry,
+        ttl: Duration,
+    ) -> Result<bool> {
+        let mut conn = WatchedConnection::new(self.get_conn().await?);
+        let full_key = self.full_key(key);
+
+        let current = self.watch_entry(&mut conn, &full_key).await?;
+
+        // Check if expected matches current
+        let matches = match (expected, &current) {
+            (None, None) => true,
+            (Some(exp), Some(cur)) => exp == cur,
+            _ => false,
         };
 
-        let json = serde_json::to_string(&new_entry)
-            .map_err(|e| StorageError::Serialization(e.to_string()))?;
+        if !matches {
+            unwatch(&mut conn).await?;
+            conn.finish();
+            return Ok(false);
+        }
+
+        // A concurrent write means the value no longer matches `expected`
+        let committed = self.commit_watched(&mut conn, &full_key, &new, ttl).await?;
+        conn.finish();
+        Ok(committed)
+    }
+
+    async fn get_or_insert(&self, key: &str, entry: StorageEntry, ttl: Duration) -> Result<StorageEntry> {
+        let mut conn = self.get_conn().await?;
+        let full_key = self.full_key(key);
 
-        conn.set_ex::<_, _, ()>(&full_key, json, ttl_secs)
+        let existing: Option<Vec<u8>> = self
+            .scripts
+            .get_or_insert
+            .key(&full_key)
+            .arg(self.codec.encode(&entry)?)
+            .arg(ttl_millis(ttl))
+            .invoke_async(&mut conn)
             .await
             .map_err(|e| StorageError::operation_failed(e.to_string(), true))?;
 
-        Ok(new_count)
+        match existing {
+            Some(bytes) => EntryCodec::decode(&bytes),
+            None => Ok(entry),
+        }
     }
 
-    async fn execute_atomic<F, T>(&self, key: &str, ttl: Duration, operation: F) -> Result<T>
-    where
-        F: FnOnce(Option<StorageEntry>) -> (StorageEntry, T) + Send,
-        T: Send,
-    {
+    async fn update_tat(
+        &self,
+        key: &str,
+        now: u64,
+        period_ms: u64,
+        tolerance_ms: u64,
+        ttl: Duration,
+    ) -> Result<TatUpdate> {
         let mut conn = self.get_conn().await?;
         let full_key = self.full_key(key);
-        let ttl_secs = ttl.as_secs();
 
-        // Get current value
-        let current: Option<String> = conn
-            .get(&full_key)
+        let (allowed, tat): (u8, u64) = self
+            .scripts
+            .update_tat
+            .key(&full_key)
+            .arg(now)
+            .arg(period_ms)
+            .arg(tolerance_ms)
+            .arg(ttl_millis(ttl))
+            .invoke_async(&mut conn)
             .await
             .map_err(|e| StorageError::operation_failed(e.to_string(), true))?;
 
-        let entry = match current {
-            Some(json) => Some(
-                serde_json::from_str(&json)
-                    .map_err(|e| StorageError::Serialization(e.to_string()))?,
-            ),
-            None => None,
-        };
-
-        // Execute the operation
-        let (new_entry, result) = operation(entry);
+        Ok(TatUpdate {
+            allowed: allowed == 1,
+            tat,
+        })
+    }
 
-        // Store the new entry
-        let json = serde_json::to_string(&new_entry)
-            .map_err(|e| StorageError::Serialization(e.to_string()))?;
+    async fn increment_window(
+        &self,
+        key: &str,
+        now: u64,
+        window_ms: u64,
+        limit: u64,
+        ttl: Duration,
+    ) -> Result<WindowUpdate> {
+        let mut conn = self.get_conn().await?;
+        let full_key = self.full_key(key);
 
-        conn.set_ex::<_, _, ()>(&full_key, json, ttl_secs)
+        let (allowed, count, prev_count): (u8, u64, u64) = self
+            .scripts
+            .increment_window
+            .key(&full_key)
+            .arg(now)
+            .arg(window_ms)
+            .arg(limit)
+            .arg(ttl_millis(ttl))
+            .invoke_async(&mut conn)
             .await
             .map_err(|e| StorageError::operation_failed(e.to_string(), true))?;
 
-        Ok(result)
+        Ok(WindowUpdate {
+            allowed: allowed == 1,
+            count,
+            prev_count,
+        })
     }
 
-    async fn compare_and_swap(
+    async fn take_tokens(
         &self,
         key: &str,
-        expected: Option<&StorageEntry>,
-        new: StorageEntry,
+        now: u64,
+        capacity: f64,
+        refill_rate: f64,
+        cost: f64,
         ttl: Duration,
-    ) -> Result<bool> {
+    ) -> Result<BucketUpdate> {
         let mut conn = self.get_conn().await?;
         let full_key = self.full_key(key);
-        let ttl_secs = ttl.as_secs();
 
-        // Get current value
-        let current: Option<String> = conn
-            .get(&full_key)
+        let (allowed, tokens): (u8, f64) = self
+            .scripts
+            .take_tokens
+            .key(&full_key)
+            .arg(now)
+            .arg(capacity)
+            .arg(refill_rate)
+            .arg(cost)
+            .arg(ttl_millis(ttl))
+            .invoke_async(&mut conn)
             .await
             .map_err(|e| StorageError::operation_failed(e.to_string(), true))?;
 
-        let current_entry: Option<StorageEntry> = match current {
-            Some(json) => Some(
-                serde_json::from_str(&json)
-                    .map_err(|e| StorageError::Serialization(e.to_string()))?,
-            ),
-            None => None,
-        };
+        Ok(BucketUpdate {
+            allowed: allowed == 1,
+            tokens,
+        })
+    }
 
-        // Check if expected matches current
-        let matches = match (expected, &current_entry) {
-            (None, None) => true,
-            (Some(exp), Some(cur)) => exp == cur,
-            _ => false,
-        };
+    async fn append_log(
+        &self,
+        key: &str,
+        now: u64,
+        window_ms: u64,
+        limit: u64,
+        ttl: Duration,
+    ) -> Result<LogState> {
+        let mut conn = self.get_conn().await?;
+        let full_key = self.full_key(key);
+        let seq = self.member_seq.fetch_add(1, Ordering::Relaxed);
+        let member = format!("{}-{:x}-{}", now, self.instance_id, seq);
+
+        let (recorded, count, oldest): (u8, u64, Option<f64>) = self
+            .scripts
+            .append_log
+            .key(&full_key)
+            .arg(now)
+            .arg(now.saturating_sub(window_ms))
+            .arg(limit)
+            .arg(ttl_millis(ttl))
+            .arg(member)
+            .invoke_async(&mut conn)
+            .await
+            .map_err(|e| StorageError::operation_failed(e.to_string(), true))?;
 
-        if !matches {
-            return Ok(false);
-        }
+        Ok(LogState {
+            recorded: recorded == 1,
+            count,
+            oldest: oldest.map(|score| score as u64),
+        })
+    }
 
-        // Set the new value
-        let json = serde_json::to_string(&new)
-            .map_err(|e| StorageError::Serialization(e.to_string()))?;
+    async fn read_log(&self, key: &str, now: u64, window_ms: u64) -> Result<LogState> {
+        let mut conn = self.get_conn().await?;
+        let full_key = self.full_key(key);
 
-        conn.set_ex::<_, _, ()>(&full_key, json, ttl_secs)
+        let state: Option<(u64, Option<f64>)> = self
+            .scripts
+            .read_log
+            .key(&full_key)
+            .arg(now.saturating_sub(window_ms))
+            .invoke_async(&mut conn)
             .await
             .map_err(|e| StorageError::operation_failed(e.to_string(), true))?;
 
-        Ok(true)
+        match state {
+            Some((count, oldest)) => Ok(LogState {
+                recorded: false,
+                count,
+                oldest: oldest.map(|score| score as u64),
+            }),
+            // Legacy layout, read it through `get`
+            None => {
+                drop(conn);
+                primitives::read_log(self, key, now, window_ms).await
+            }
+        }
     }
 }
 
+/// Convert a TTL to milliseconds, rounding sub-millisecond TTLs up.
+fn ttl_millis(ttl: Duration) -> u64 {
+    (ttl.as_millis() as u64).max(1)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -320,5 +892,38 @@ mod tests {
         assert_eq!(config.url, "redis://localhost:6380");
         assert_eq!(config.key_prefix, "test:");
         assert_eq!(config.pool_size, 5);
+        assert_eq!(config.codec, EntryCodec::Json);
+    }
+
+    #[test]
+    fn test_entry_from_hash() {
+        let bulk = |s: &str| Value::BulkString(s.as_bytes().to_vec());
+
+        let entry = entry_from_hash(&[bulk("tat"), bulk("1700000000100")]).unwrap();
+        assert_eq!(entry, StorageEntry::with_tat(1_700_000_000_100));
+
+        let entry = entry_from_hash(&[
+            bulk("tokens"),
+            bulk("4.5"),
+            bulk("last_update"),
+            bulk("1700000000000"),
+        ])
+        .unwrap();
+        assert_eq!(entry, StorageEntry::with_tokens(4.5, 1_700_000_000_000));
+
+        assert!(entry_from_hash(&[bulk("tat")]).is_err());
+        assert!(entry_from_hash(&[bulk("tat"), bulk("abc")]).is_err());
+    }
+
+    #[test]
+    fn test_ttl_millis() {
+        assert_eq!(ttl_millis(Duration::from_secs(2)), 2000);
+        assert_eq!(ttl_millis(Duration::from_micros(10)), 1);
+    }
+
+    #[test]
+    fn test_redis_config_codec() {
+        let config = RedisConfig::new("redis://localhost:6379").with_codec(EntryCodec::Binary);
+        assert_eq!(config.codec, EntryCodec::Binary);
     }
 }
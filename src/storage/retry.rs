@@ -0,0 +1,400 @@
+//! Retrying storage wrapper.
+//!
+//! `RetryStorage` re-runs operations that fail with a retryable error (see
+//! [`RateLimitError::is_retryable`](crate::RateLimitError::is_retryable)),
+//! sleeping with capped exponential backoff and jitter between attempts.
+
+use std::collections::hash_map::RandomState;
+use std::future::Future;
+use std::hash::BuildHasher;
+use std::time::Duration;
+
+use crate::error::Result;
+use crate::storage::{BucketUpdate, LogState, Storage, StorageEntry, TatUpdate, WindowUpdate};
+
+/// Retry configuration for `RetryStorage`.
+#[derive(Debug, Clone)]
+pub struct RetryConfig {
+    /// Total attempts per operation, including the first (default: 3).
+    pub max_attempts: u32,
+    /// Backoff before the first retry (default: 10ms).
+    pub initial_backoff: Duration,
+    /// Upper bound on the backoff between attempts (default: 500ms).
+    pub max_backoff: Duration,
+    /// Whether to randomize each backoff to `[backoff / 2, backoff]`
+    /// (default: true), so clients recovering from the same outage spread out.
+    pub jitter: bool,
+}
+
+impl Default for RetryConfig {
+    fn default() -> Self {
+        Self {
+            max_attempts: 3,
+            initial_backoff: Duration::from_millis(10),
+            max_backoff: Duration::from_millis(500),
+            jitter: true,
+        }
+    }
+}
+
+impl RetryConfig {
+    /// Create a config with default settings.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set the total attempts per operation (at least 1).
+    pub fn with_max_attempts(mut self, max_attempts: u32) -> Self {
+        self.max_attempts = max_attempts.max(1);
+        self
+    }
+
+    /// Set the backoff before the first retry; it doubles on each retry.
+    pub fn with_initial_backoff(mut self, backoff: Duration) -> Self {
+        self.initial_backoff = backoff;
+        self
+    }
+
+    /// Set the upper bound on the backoff between attempts.
+    pub fn with_max_backoff(mut self, backoff: Duration) -> Self {
+        self.max_backoff = backoff;
+        self
+    }
+
+    /// Enable or disable backoff jitter.
+    pub fn with_jitter(mut self, jitter: bool) -> Self {
+        self.jitter = jitter;
+        self
+    }
+
+    /// Backoff before retry number `retry` (starting at 0).
+    fn backoff(&self, retry: u32) -> Duration {
+        let backoff = self
+            .initial_backoff
+            .saturating_mul(1 << retry.min(31))
+            .min(self.max_backoff);
+
+        if self.jitter {
+            let random = RandomState::new().hash_one(retry);
+            backoff / 2 + backoff.mul_f64((random >> 11) as f64 / (1u64 << 53) as f64 / 2.0)
+        } else {
+            backoff
+        }
+    }
+}
+
+/// Storage wrapper that retries transient failures.
+///
+/// Only errors for which
+/// [`RateLimitError::is_retryable`](crate::RateLimitError::is_retryable)
+/// holds are retried. `execute_atomic` runs at most once since its operation
+/// cannot be replayed; its retryable errors are returned to the caller.
+///
+/// A retried write may have been applied by an attempt whose reply was lost
+/// (e.g. a timeout from [`TimeoutStorage`](super::TimeoutStorage)), so under
+/// failures a request can be counted more than once. This errs on the side
+/// of limiting.
+///
+/// # Example
+///
+/// ```ignore
+/// use skp_ratelimit::storage::{RetryConfig, RetryStorage, TimeoutStorage};
+///
+/// let storage = RetryStorage::new(
+///     TimeoutStorage::new(redis, Duration::from_millis(50)),
+///     RetryConfig::new().with_max_attempts(3),
+/// );
+/// ```
+#[derive(Debug, Clone)]
+pub struct RetryStorage<S> {
+    inner: S,
+    config: RetryConfig,
+}
+
+impl<S: Storage> RetryStorage<S> {
+    /// Wrap `inner`, retrying according to `config`.
+    pub fn new(inner: S, config: RetryConfig) -> Self {
+        Self { inner, config }
+    }
+
+    /// Get the wrapped storage.
+    pub fn inner(&self) -> &S {
+        &self.inner
+    }
+
+    /// Get the retry configuration.
+    pub fn config(&self) -> &RetryConfig {
+        &self.config
+    }
+
+    /// Run `operation` until it succeeds, fails permanently or runs out of
+    /// attempts.
+    async fn retry<T, F, Fut>(&self, mut operation: F) -> Result<T>
+    where
+        F: FnMut() -> Fut,
+        Fut: Future<Output = Result<T>>,
+    {
+        let mut retry = 0;
+        loop {
+            match operation().await {
+                Err(e) if retry + 1 < self.config.max_attempts && e.is_retryable() => {
+                    tracing::debug!(error = %e, retry, "retrying storage operation");
+                    tokio::time::sleep(self.config.backoff(retry)).await;
+                    retry += 1;
+                }
+                result => return result,
+            }
+        }
+    }
+}
+
+impl<S: Storage> Storage for RetryStorage<S> {
+    async fn get(&self, key: &str) -> Result<Option<StorageEntry>> {
+        self.retry(|| self.inner.get(key)).await
+    }
+
+    async fn set(&self, key: &str, entry: StorageEntry, ttl: Duration) -> Result<()> {
+        self.retry(|| self.inner.set(key, entry.clone(), ttl)).await
+    }
+
+    async fn delete(&self, key: &str) -> Result<()> {
+        self.retry(|| self.inner.delete(key)).await
+    }
+
+    async fn increment(
+        &self,
+        key: &str,
+        delta: u64,
+        window_start: u64,
+        ttl: Duration,
+    ) -> Result<u64> {
+        self.retry(|| self.inner.increment(key, delta, window_start, ttl))
+            .await
+    }
+
+    async fn execute_atomic<F, T>(&self, key: &str, ttl: Duration, operation: F) -> Result<T>
+    where
+        F: FnOnce(Option<StorageEntry>) -> (StorageEntry, T) + Send,
+        T: Send,
+    {
+        self.inner.execute_atomic(key, ttl, operation).await
+    }
+
+    async fn compare_and_swap(
+        &self,
+        key: &str,
+        expected: Option<&StorageEntry>,
+        new: StorageEntry,
+        ttl: Duration,
+    ) -> Result<bool> {
+        self.retry(|| self.inner.compare_and_swap(key, expected, new.clone(), ttl))
+            .await
+    }
+
+    async fn get_or_insert(&self, key: &str, entry: StorageEntry, ttl: Duration) -> Result<StorageEntry> {
+        self.retry(|| self.inner.get_or_insert(key, entry.clone(), ttl))
+            .await
+    }
+
+    async fn update_tat(
+        &self,
+        key: &str,
+        now: u64,
+        period_ms: u64,
+        tolerance_ms: u64,
+        ttl: Duration,
+    ) -> Result<TatUpdate> {
+        self.retry(|| self.inner.update_tat(key, now, period_ms, tolerance_ms, ttl))
+            .await
+    }
+
+    async fn take_tokens(
+        &self,
+        key: &str,
+        now: u64,
+        capacity: f64,
+        refill_rate: f64,
+        cost: f64,
+        ttl: Duration,
+    ) -> Result<BucketUpdate> {
+        self.retry(|| {
+            self.inner
+                .take_tokens(key, now, capacity, refill_rate, cost, ttl)
+        })
+        .await
+    }
+
+    async fn append_log(
+        &self,
+        key: &str,
+        now: u64,
+        window_ms: u64,
+        limit: u64,
+        ttl: Duration,
+    ) -> Result<LogState> {
+        self.retry(|| self.inner.append_log(key, now, window_ms, limit, ttl))
+            .await
+    }
+
+    async fn increment_window(
+        &self,
+        key: &str,
+        now: u64,
+        window_ms: u64,
+        limit: u64,
+        ttl: Duration,
+    ) -> Result<WindowUpdate> {
+        self.retry(|| self.inner.increment_window(key, now, window_ms, limit, ttl))
+            .await
+    }
+
+    async fn read_log(&self, key: &str, now: u64, window_ms: u64) -> Result<LogState> {
+        self.retry(|| self.inner.read_log(key, now, window_ms)).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::{ConnectionError, RateLimitError, StorageError};
+    use crate::storage::MemoryStorage;
+    use std::sync::atomic::{AtomicU32, Ordering};
+
+    /// Fails the first `failures` calls to `get`/`increment` with `error`.
+    struct Flaky {
+        inner: MemoryStorage,
+        failures: u32,
+        calls: AtomicU32,
+        error: fn() -> RateLimitError,
+    }
+
+    impl Flaky {
+        fn new(failures: u32, error: fn() -> RateLimitError) -> Self {
+            Self {
+                inner: MemoryStorage::new(),
+                failures,
+                calls: AtomicU32::new(0),
+                error,
+            }
+        }
+
+        fn fail(&self) -> Result<()> {
+            if self.calls.fetch_add(1, Ordering::SeqCst) < self.failures {
+                Err((self.error)())
+            } else {
+                Ok(())
+            }
+        }
+    }
+
+    impl Storage for Flaky {
+        async fn get(&self, key: &str) -> Result<Option<StorageEntry>> {
+            self.fail()?;
+            self.inner.get(key).await
+        }
+
+        async fn set(&self, key: &str, entry: StorageEntry, ttl: Duration) -> Result<()> {
+            self.inner.set(key, entry, ttl).await
+        }
+
+        async fn delete(&self, key: &str) -> Result<()> {
+            self.inner.delete(key).await
+        }
+
+        async fn increment(
+            &self,
+            key: &str,
+            delta: u64,
+            window_start: u64,
+            ttl: Duration,
+        ) -> Result<u64> {
+            self.fail()?;
+            self.inner.increment(key, delta, window_start, ttl).await
+        }
+
+        async fn execute_atomic<F, T>(&self, key: &str, ttl: Duration, operation: F) -> Result<T>
+        where
+            F: FnOnce(Option<StorageEntry>) -> (StorageEntry, T) + Send,
+            T: Send,
+        {
+            self.fail()?;
+            self.inner.execute_atomic(key, ttl, operation).await
+        }
+
+        async fn compare_and_swap(
+            &self,
+            key: &str,
+            expected: Option<&StorageEntry>,
+            new: StorageEntry,
+            ttl: Duration,
+        ) -> Result<bool> {
+            self.inner.compare_and_swap(key, expected, new, ttl).await
+        }
+    }
+
+    fn timeout() -> RateLimitError {
+        ConnectionError::Timeout(Duration::from_millis(1)).into()
+    }
+
+    fn config() -> RetryConfig {
+        RetryConfig::new().with_initial_backoff(Duration::from_millis(1))
+    }
+
+    #[tokio::test]
+    async fn test_retries_transient_errors() {
+        let storage = RetryStorage::new(Flaky::new(2, timeout), config());
+
+        let count = storage.increment("key", 1, 0, Duration::from_secs(60)).await.unwrap();
+        assert_eq!(count, 1);
+        assert_eq!(storage.inner().calls.load(Ordering::SeqCst), 3);
+    }
+
+    #[tokio::test]
+    async fn test_gives_up_after_max_attempts() {
+        let storage = RetryStorage::new(Flaky::new(5, timeout), config().with_max_attempts(2));
+
+        let err = storage.get("key").await.unwrap_err();
+        assert!(matches!(err, RateLimitError::Connection(ConnectionError::Timeout(_))));
+        assert_eq!(storage.inner().calls.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn test_does_not_retry_permanent_errors() {
+        let storage = RetryStorage::new(
+            Flaky::new(5, || StorageError::Serialization("bad".into()).into()),
+            config(),
+        );
+
+        assert!(storage.get("key").await.is_err());
+        assert_eq!(storage.inner().calls.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn test_execute_atomic_runs_once() {
+        let storage = RetryStorage::new(Flaky::new(1, timeout), config());
+
+        let result = storage
+            .execute_atomic("key", Duration::from_secs(60), |_| (StorageEntry::new(1, 0), ()))
+            .await;
+        assert!(result.unwrap_err().is_retryable());
+        assert_eq!(storage.inner().calls.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn test_backoff() {
+        let config = RetryConfig::new()
+            .with_initial_backoff(Duration::from_millis(10))
+            .with_max_backoff(Duration::from_millis(50))
+            .with_jitter(false);
+        assert_eq!(config.backoff(0), Duration::from_millis(10));
+        assert_eq!(config.backoff(2), Duration::from_millis(40));
+        assert_eq!(config.backoff(3), Duration::from_millis(50));
+        assert_eq!(config.backoff(100), Duration::from_millis(50));
+
+        let config = config.with_jitter(true);
+        for retry in 0..10 {
+            let backoff = config.backoff(retry);
+            assert!(backoff >= Duration::from_millis(5) && backoff <= Duration::from_millis(50));
+        }
+    }
+}
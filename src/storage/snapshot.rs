@@ -0,0 +1,291 @@
+//! Snapshot files for `MemoryStorage`.
+//!
+//! A snapshot holds every live entry together with its absolute expiry time
+//! (wall-clock Unix milliseconds), so restoring after a restart resumes each
+//! key with whatever TTL it had left.
+//!
+//! # File Layout (version 1)
+//!
+//! ```text
+//! header:  "SKPRLSNP" [version: u8] [saved_at: u64 LE] [crc32: u32 LE]
+//! record:  [len: u32 LE] [crc32: u32 LE] [payload: len bytes]
+//! payload: [key len, key] [expires_at] [entry len, binary StorageEntry]
+//! ```
+//!
+//! Payload integers are varints and entries use the binary
+//! [`EntryCodec`](super::EntryCodec) layout. Records carry their own
+//! checksum: corrupted records are skipped and a truncated tail is ignored,
+//! so a damaged file still restores everything that can be trusted.
+
+use std::fs::{self, File};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use crate::error::{Result, StorageError};
+use crate::storage::codec::{put_varint, Reader};
+use crate::storage::{EntryCodec, StorageEntry};
+
+const MAGIC: &[u8; 8] = b"SKPRLSNP";
+const VERSION: u8 = 1;
+const HEADER_LEN: usize = MAGIC.len() + 1 + 8 + 4;
+
+/// Snapshot configuration for `MemoryStorage`.
+#[derive(Debug, Clone)]
+pub struct SnapshotConfig {
+    /// Snapshot file path.
+    pub path: PathBuf,
+    /// How often to write a snapshot in the background (default: never).
+    pub interval: Option<Duration>,
+    /// Whether to write a snapshot when the storage is dropped (default: true).
+    pub save_on_drop: bool,
+}
+
+impl SnapshotConfig {
+    /// Create a config that restores from and saves to `path`.
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self {
+            path: path.into(),
+            interval: None,
+            save_on_drop: true,
+        }
+    }
+
+    /// Also write a snapshot every `interval`.
+    pub fn with_interval(mut self, interval: Duration) -> Self {
+        self.interval = Some(interval);
+        self
+    }
+
+    /// Set whether to write a snapshot when the storage is dropped.
+    pub fn with_save_on_drop(mut self, save_on_drop: bool) -> Self {
+        self.save_on_drop = save_on_drop;
+        self
+    }
+}
+
+/// Outcome of restoring a snapshot.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct SnapshotStats {
+    /// Entries restored.
+    pub restored: usize,
+    /// Entries that had expired since the snapshot was taken.
+    pub expired: usize,
+    /// Records dropped because they were corrupted or truncated.
+    pub corrupted: usize,
+}
+
+/// A restored entry: key, entry and absolute expiry.
+pub(crate) type Record = (String, StorageEntry, u64);
+
+/// Incremental writer for a snapshot file image.
+pub(crate) struct SnapshotWriter {
+    buf: Vec<u8>,
+    payload: Vec<u8>,
+}
+
+impl SnapshotWriter {
+    pub(crate) fn new(saved_at: u64) -> Self {
+        let mut buf = Vec::with_capacity(HEADER_LEN);
+        buf.extend_from_slice(MAGIC);
+        buf.push(VERSION);
+        buf.extend_from_slice(&saved_at.to_le_bytes());
+        let crc = crc32fast::hash(&buf);
+        buf.extend_from_slice(&crc.to_le_bytes());
+
+        Self {
+            buf,
+            payload: Vec::new(),
+        }
+    }
+
+    pub(crate) fn push(&mut self, key: &str, entry: &StorageEntry, expires_at: u64) -> Result<()> {
+        let encoded = EntryCodec::Binary.encode(entry)?;
+
+        let payload = &mut self.payload;
+        payload.clear();
+        put_varint(payload, key.len() as u64);
+        payload.extend_from_slice(key.as_bytes());
+        put_varint(payload, expires_at);
+        put_varint(payload, encoded.len() as u64);
+        payload.extend_from_slice(&encoded);
+
+        self.buf.extend_from_slice(&(payload.len() as u32).to_le_bytes());
+        self.buf.extend_from_slice(&crc32fast::hash(payload).to_le_bytes());
+        self.buf.extend_from_slice(payload);
+        Ok(())
+    }
+
+    pub(crate) fn finish(self) -> Vec<u8> {
+        self.buf
+    }
+}
+
+/// Parse a snapshot file image, keeping entries that expire after `now`.
+///
+/// Fails only if the header is unusable.
+pub(crate) fn decode(bytes: &[u8], now: u64) -> Result<(Vec<Record>, SnapshotStats)> {
+    let header = bytes
+        .get(..HEADER_LEN)
+        .ok_or_else(|| invalid("file is shorter than the header"))?;
+    if &header[..MAGIC.len()] != MAGIC {
+        return Err(invalid("not a snapshot file"));
+    }
+    let (body, crc) = header.split_at(HEADER_LEN - 4);
+    if crc32fast::hash(body) != u32::from_le_bytes(crc.try_into().unwrap()) {
+        return Err(invalid("header checksum mismatch"));
+    }
+    if header[MAGIC.len()] != VERSION {
+        return Err(invalid(&format!("unsupported version {}", header[MAGIC.len()])));
+    }
+
+    let mut records = Vec::new();
+    let mut stats = SnapshotStats::default();
+    let mut rest = &bytes[HEADER_LEN..];
+
+    while !rest.is_empty() {
+        let Some((len, crc, payload)) = split_record(rest) else {
+            // Truncated tail, e.g. a crash mid-write without atomic rename
+            stats.corrupted += 1;
+            break;
+        };
+        rest = &rest[8 + len..];
+
+        if crc32fast::hash(payload) != crc {
+            stats.corrupted += 1;
+            continue;
+        }
+
+        match decode_payload(payload) {
+            Some((_, _, expires_at)) if expires_at <= now => stats.expired += 1,
+            Some(record) => {
+                stats.restored += 1;
+                records.push(record);
+            }
+            None => stats.corrupted += 1,
+        }
+    }
+
+    Ok((records, stats))
+}
+
+/// Split one record off the front of `bytes`.
+fn split_record(bytes: &[u8]) -> Option<(usize, u32, &[u8])> {
+    let len = u32::from_le_bytes(bytes.get(..4)?.try_into().ok()?) as usize;
+    let crc = u32::from_le_bytes(bytes.get(4..8)?.try_into().ok()?);
+    let payload = bytes.get(8..8 + len)?;
+    Some((len, crc, payload))
+}
+
+fn decode_payload(payload: &[u8]) -> Option<Record> {
+    let mut reader = Reader::new(payload);
+
+    let key_len = reader.length_prefix().ok()?;
+    let key = String::from_utf8(reader.take(key_len).ok()?.to_vec()).ok()?;
+    let expires_at = reader.varint().ok()?;
+    let entry_len = reader.length_prefix().ok()?;
+    let entry = EntryCodec::decode(reader.take(entry_len).ok()?).ok()?;
+
+    Some((key, entry, expires_at))
+}
+
+fn invalid(reason: &str) -> crate::error::RateLimitError {
+    StorageError::Serialization(format!("invalid snapshot: {}", reason)).into()
+}
+
+/// Write `bytes` to `path` atomically (temporary file, fsync, rename).
+pub(crate) fn write_atomic(path: &Path, bytes: &[u8]) -> Result<()> {
+    let mut tmp = path.as_os_str().to_owned();
+    tmp.push(".tmp");
+    let tmp = PathBuf::from(tmp);
+
+    let result = File::create(&tmp)
+        .and_then(|mut file| {
+            file.write_all(bytes)?;
+            file.sync_all()
+        })
+        .and_then(|()| fs::rename(&tmp, path));
+
+    result.map_err(|e| {
+        let _ = fs::remove_file(&tmp);
+        StorageError::operation_failed(
+            format!("failed to write snapshot {}: {}", path.display(), e),
+            true,
+        )
+        .into()
+    })
+}
+
+/// Read a snapshot file; a missing file yields `None`.
+pub(crate) fn read(path: &Path) -> Result<Option<Vec<u8>>> {
+    match fs::read(path) {
+        Ok(bytes) => Ok(Some(bytes)),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(StorageError::operation_failed(
+            format!("failed to read snapshot {}: {}", path.display(), e),
+            true,
+        )
+        .into()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample() -> Vec<u8> {
+        let a = StorageEntry::with_tat(5000);
+        let b = StorageEntry::with_timestamps(vec![1, 2, 3]);
+        let c = StorageEntry::new(7, 100);
+
+        let mut writer = SnapshotWriter::new(1000);
+        writer.push("a", &a, 10_000).unwrap();
+        writer.push("b", &b, 20_000).unwrap();
+        writer.push("c", &c, 500).unwrap();
+        writer.finish()
+    }
+
+    #[test]
+    fn test_roundtrip_skips_expired() {
+        let (records, stats) = decode(&sample(), 1000).unwrap();
+
+        assert_eq!(stats, SnapshotStats { restored: 2, expired: 1, corrupted: 0 });
+        assert_eq!(records[0], ("a".to_string(), StorageEntry::with_tat(5000), 10_000));
+        assert_eq!(records[1].0, "b");
+    }
+
+    #[test]
+    fn test_corrupted_record_is_skipped() {
+        let mut bytes = sample();
+        // Flip a byte inside the first record's payload
+        bytes[HEADER_LEN + 9] ^= 0xff;
+
+        let (records, stats) = decode(&bytes, 0).unwrap();
+        assert_eq!(stats.corrupted, 1);
+        assert_eq!(records.len(), 2);
+    }
+
+    #[test]
+    fn test_truncated_tail() {
+        let mut bytes = sample();
+        bytes.truncate(bytes.len() - 3);
+
+        let (records, stats) = decode(&bytes, 0).unwrap();
+        assert_eq!(stats.corrupted, 1);
+        assert_eq!(records.len(), 2);
+    }
+
+    #[test]
+    fn test_bad_header() {
+        assert!(decode(b"", 0).is_err());
+        assert!(decode(b"SKPRLSNP\x01", 0).is_err());
+
+        let mut bytes = sample();
+        bytes[MAGIC.len()] = 9;
+        assert!(decode(&bytes, 0).is_err());
+
+        let mut bytes = sample();
+        bytes[0] = b'X';
+        assert!(decode(&bytes, 0).is_err());
+    }
+}
@@ -0,0 +1,226 @@
+//! Per-operation deadline for storage calls.
+
+use std::future::Future;
+use std::time::Duration;
+
+use crate::error::{ConnectionError, Result};
+use crate::storage::{BucketUpdate, LogState, Storage, StorageEntry, TatUpdate, WindowUpdate};
+
+/// Storage wrapper that bounds how long each operation may take.
+///
+/// Operations that miss the deadline are abandoned and fail with
+/// `ConnectionError::Timeout`, which is retryable, so this composes with
+/// [`RetryStorage`](super::RetryStorage). An abandoned write may still have
+/// been applied by the backend.
+///
+/// # Example
+///
+/// ```ignore
+/// use skp_ratelimit::storage::TimeoutStorage;
+///
+/// let storage = TimeoutStorage::new(redis, Duration::from_millis(50));
+/// ```
+#[derive(Debug, Clone)]
+pub struct TimeoutStorage<S> {
+    inner: S,
+    timeout: Duration,
+}
+
+impl<S: Storage> TimeoutStorage<S> {
+    /// Wrap `inner`, failing operations that take longer than `timeout`.
+    pub fn new(inner: S, timeout: Duration) -> Self {
+        Self { inner, timeout }
+    }
+
+    /// Get the wrapped storage.
+    pub fn inner(&self) -> &S {
+        &self.inner
+    }
+
+    /// Get the per-operation timeout.
+    pub fn timeout(&self) -> Duration {
+        self.timeout
+    }
+
+    async fn run<T>(&self, operation: impl Future<Output = Result<T>>) -> Result<T> {
+        tokio::time::timeout(self.timeout, operation)
+            .await
+            .map_err(|_| ConnectionError::Timeout(self.timeout))?
+    }
+}
+
+impl<S: Storage> Storage for TimeoutStorage<S> {
+    async fn get(&self, key: &str) -> Result<Option<StorageEntry>> {
+        self.run(self.inner.get(key)).await
+    }
+
+    async fn set(&self, key: &str, entry: StorageEntry, ttl: Duration) -> Result<()> {
+        self.run(self.inner.set(key, entry, ttl)).await
+    }
+
+    async fn delete(&self, key: &str) -> Result<()> {
+        self.run(self.inner.delete(key)).await
+    }
+
+    async fn increment(
+        &self,
+        key: &str,
+        delta: u64,
+        window_start: u64,
+        ttl: Duration,
+    ) -> Result<u64> {
+        self.run(self.inner.increment(key, delta, window_start, ttl))
+            .await
+    }
+
+    async fn execute_atomic<F, T>(&self, key: &str, ttl: Duration, operation: F) -> Result<T>
+    where
+        F: FnOnce(Option<StorageEntry>) -> (StorageEntry, T) + Send,
+        T: Send,
+    {
+        self.run(self.inner.execute_atomic(key, ttl, operation)).await
+    }
+
+    async fn compare_and_swap(
+        &self,
+        key: &str,
+        expected: Option<&StorageEntry>,
+        new: StorageEntry,
+        ttl: Duration,
+    ) -> Result<bool> {
+        self.run(self.inner.compare_and_swap(key, expected, new, ttl))
+            .await
+    }
+
+    async fn get_or_insert(&self, key: &str, entry: StorageEntry, ttl: Duration) -> Result<StorageEntry> {
+        self.run(self.inner.get_or_insert(key, entry, ttl)).await
+    }
+
+    async fn update_tat(
+        &self,
+        key: &str,
+        now: u64,
+        period_ms: u64,
+        tolerance_ms: u64,
+        ttl: Duration,
+    ) -> Result<TatUpdate> {
+        self.run(self.inner.update_tat(key, now, period_ms, tolerance_ms, ttl))
+            .await
+    }
+
+    async fn take_tokens(
+        &self,
+        key: &str,
+        now: u64,
+        capacity: f64,
+        refill_rate: f64,
+        cost: f64,
+        ttl: Duration,
+    ) -> Result<BucketUpdate> {
+        self.run(
+            self.inner
+                .take_tokens(key, now, capacity, refill_rate, cost, ttl),
+        )
+        .await
+    }
+
+    async fn append_log(
+        &self,
+        key: &str,
+        now: u64,
+        window_ms: u64,
+        limit: u64,
+        ttl: Duration,
+    ) -> Result<LogState> {
+        self.run(self.inner.append_log(key, now, window_ms, limit, ttl))
+            .await
+    }
+
+    async fn increment_window(
+        &self,
+        key: &str,
+        now: u64,
+        window_ms: u64,
+        limit: u64,
+        ttl: Duration,
+    ) -> Result<WindowUpdate> {
+        self.run(self.inner.increment_window(key, now, window_ms, limit, ttl))
+            .await
+    }
+
+    async fn read_log(&self, key: &str, now: u64, window_ms: u64) -> Result<LogState> {
+        self.run(self.inner.read_log(key, now, window_ms)).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::RateLimitError;
+    use crate::storage::MemoryStorage;
+
+    /// Delays every `get` by `delay`.
+    struct Slow {
+        inner: MemoryStorage,
+        delay: Duration,
+    }
+
+    impl Storage for Slow {
+        async fn get(&self, key: &str) -> Result<Option<StorageEntry>> {
+            tokio::time::sleep(self.delay).await;
+            self.inner.get(key).await
+        }
+
+        async fn set(&self, key: &str, entry: StorageEntry, ttl: Duration) -> Result<()> {
+            self.inner.set(key, entry, ttl).await
+        }
+
+        async fn delete(&self, key: &str) -> Result<()> {
+            self.inner.delete(key).await
+        }
+
+        async fn increment(
+            &self,
+            key: &str,
+            delta: u64,
+            window_start: u64,
+            ttl: Duration,
+        ) -> Result<u64> {
+            self.inner.increment(key, delta, window_start, ttl).await
+        }
+
+        async fn execute_atomic<F, T>(&self, key: &str, ttl: Duration, operation: F) -> Result<T>
+        where
+            F: FnOnce(Option<StorageEntry>) -> (StorageEntry, T) + Send,
+            T: Send,
+        {
+            self.inner.execute_atomic(key, ttl, operation).await
+        }
+
+        async fn compare_and_swap(
+            &self,
+            key: &str,
+            expected: Option<&StorageEntry>,
+            new: StorageEntry,
+            ttl: Duration,
+        ) -> Result<bool> {
+            self.inner.compare_and_swap(key, expected, new, ttl).await
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_timeout() {
+        let slow = Slow {
+            inner: MemoryStorage::new(),
+            delay: Duration::from_secs(5),
+        };
+        let storage = TimeoutStorage::new(slow, Duration::from_millis(100));
+
+        let err = storage.get("key").await.unwrap_err();
+        assert!(matches!(err, RateLimitError::Connection(ConnectionError::Timeout(t)) if t == Duration::from_millis(100)));
+        assert!(err.is_retryable());
+
+        // Fast operations pass through
+        assert_eq!(storage.increment("key", 1, 0, Duration::from_secs(60)).await.unwrap(), 1);
+    }
+}
@@ -0,0 +1,411 @@
+//! Hierarchical timing wheel used to schedule `MemoryStorage` expirations.
+//!
+//! Deadlines are expressed in ticks. Each level has 64 slots; level `n`
+//! slots span `64^n` ticks. An item is placed at the highest level where its
+//! deadline differs from the current tick, and cascades down as time
+//! advances, so advancing costs O(1) per occupied slot rather than per tick
+//! or per scheduled item.
+//!
+//! Items live in a slab and slots hold slab indices, so an item can be
+//! cancelled in O(1) through the [`TimerId`] returned when scheduling it.
+
+use std::collections::VecDeque;
+
+const SLOT_BITS: u32 = 6;
+const SLOTS: usize = 1 << SLOT_BITS;
+const LEVELS: usize = 7;
+
+/// Furthest a deadline may lie in the future, in ticks.
+const HORIZON: u64 = 1 << 40;
+
+/// Handle to a scheduled item.
+///
+/// Stale once the item fires or is cancelled, even if its slab slot is
+/// reused.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct TimerId {
+    index: u32,
+    generation: u32,
+}
+
+/// A hierarchical timing wheel.
+#[derive(Debug)]
+pub(crate) struct TimerWheel<T> {
+    current: u64,
+    levels: Vec<Level>,
+    /// Nodes whose deadline has passed, in firing order.
+    due: VecDeque<u32>,
+    /// Sequence number of the front of `due`.
+    due_head: u64,
+    nodes: Vec<Node<T>>,
+    /// Indices of free nodes.
+    free: Vec<u32>,
+    len: usize,
+}
+
+#[derive(Debug)]
+struct Level {
+    /// Bit `i` is set when `slots[i]` is non-empty.
+    occupied: u64,
+    slots: Vec<Vec<u32>>,
+}
+
+impl Level {
+    fn new() -> Self {
+        Self {
+            occupied: 0,
+            slots: (0..SLOTS).map(|_| Vec::new()).collect(),
+        }
+    }
+}
+
+/// Where a node is queued.
+#[derive(Debug, Clone, Copy)]
+enum Place {
+    Slot { level: usize, slot: usize },
+    Due,
+}
+
+#[derive(Debug)]
+struct Node<T> {
+    generation: u32,
+    deadline: u64,
+    place: Place,
+    /// Index in its slot, or sequence number in the due queue.
+    position: u64,
+    /// `None` while the node is free.
+    item: Option<T>,
+}
+
+impl<T> TimerWheel<T> {
+    /// Approximate memory used per scheduled item, beyond what `T` owns.
+    pub(crate) const NODE_SIZE: usize = std::mem::size_of::<Node<T>>() + std::mem::size_of::<u32>();
+
+    /// Create a wheel positioned at tick `now`.
+    pub(crate) fn new(now: u64) -> Self {
+        Self {
+            current: now,
+            levels: (0..LEVELS).map(|_| Level::new()).collect(),
+            due: VecDeque::new(),
+            due_head: 0,
+            nodes: Vec::new(),
+            free: Vec::new(),
+            len: 0,
+        }
+    }
+
+    /// Number of scheduled items, due or not.
+    #[cfg(test)]
+    pub(crate) fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Pick up to `limit` scheduled items pseudo-randomly from `seed`, or
+    /// all of them if there are no more than `limit`.
+    pub(crate) fn sample(&self, seed: u64, limit: usize) -> Vec<&T> {
+        if self.len <= limit {
+            return self.nodes.iter().filter_map(|node| node.item.as_ref()).collect();
+        }
+
+        let mut state = seed;
+        let mut sample = Vec::with_capacity(limit);
+        // Picks landing on free nodes are skipped, so allow a few misses
+        for _ in 0..limit * 4 {
+            let index = splitmix64(&mut state) % self.nodes.len() as u64;
+            if let Some(item) = &self.nodes[index as usize].item {
+                sample.push(item);
+                if sample.len() == limit {
+                    break;
+                }
+            }
+        }
+        sample
+    }
+
+    /// Schedule `item` to fire at tick `deadline`.
+    pub(crate) fn insert(&mut self, deadline: u64, item: T) -> TimerId {
+        let index = match self.free.pop() {
+            Some(index) => {
+                let node = &mut self.nodes[index as usize];
+                node.deadline = deadline;
+                node.item = Some(item);
+                index
+            }
+            None => {
+                self.nodes.push(Node {
+                    generation: 0,
+                    deadline,
+                    place: Place::Due,
+                    position: 0,
+                    item: Some(item),
+                });
+                (self.nodes.len() - 1) as u32
+            }
+        };
+
+        self.len += 1;
+        self.place(index);
+        TimerId {
+            index,
+            generation: self.nodes[index as usize].generation,
+        }
+    }
+
+    /// Remove a scheduled item, returning it unless it already fired or
+    /// was cancelled.
+    pub(crate) fn cancel(&mut self, id: TimerId) -> Option<T> {
+        let node = self.nodes.get(id.index as usize)?;
+        if node.generation != id.generation || node.item.is_none() {
+            return None;
+        }
+
+        self.unlink(id.index);
+        Some(self.release(id.index))
+    }
+
+    /// Advance to tick `now`, moving every item with a deadline at or
+    /// before `now` to the due queue.
+    pub(crate) fn advance(&mut self, now: u64) {
+        while self.current < now {
+            let Some((level, slot, tick)) = self.next_event() else {
+                break;
+            };
+            if tick > now {
+                break;
+            }
+
+            self.current = tick;
+            let level = &mut self.levels[level];
+            level.occupied &= !(1 << slot);
+            for index in std::mem::take(&mut level.slots[slot]) {
+                self.place(index);
+            }
+        }
+
+        self.current = self.current.max(now);
+    }
+
+    /// Pop the next due item.
+    pub(crate) fn pop_due(&mut self) -> Option<T> {
+        let index = self.due.pop_front()?;
+        self.due_head += 1;
+        Some(self.release(index))
+    }
+
+    /// Remove all items.
+    pub(crate) fn clear(&mut self) {
+        for level in &mut self.levels {
+            level.occupied = 0;
+            level.slots.iter_mut().for_each(Vec::clear);
+        }
+        self.due.clear();
+
+        for index in 0..self.nodes.len() {
+            if self.nodes[index].item.is_some() {
+                self.release(index as u32);
+            }
+        }
+    }
+
+    /// Queue node `index` by its deadline.
+    fn place(&mut self, index: u32) {
+        let node = &mut self.nodes[index as usize];
+        if node.deadline <= self.current {
+            node.place = Place::Due;
+            node.position = self.due_head + self.due.len() as u64;
+            self.due.push_back(index);
+            return;
+        }
+
+        let deadline = node.deadline.min(self.current + HORIZON);
+        let level = ((63 - (self.current ^ deadline).leading_zeros()) / SLOT_BITS) as usize;
+        let level = level.min(LEVELS - 1);
+        let slot = ((deadline >> (level as u32 * SLOT_BITS)) as usize) & (SLOTS - 1);
+
+        let slots = &mut self.levels[level].slots[slot];
+        node.deadline = deadline;
+        node.place = Place::Slot { level, slot };
+        node.position = slots.len() as u64;
+        slots.push(index);
+        self.levels[level].occupied |= 1 << slot;
+    }
+
+    /// Take node `index` out of its slot or the due queue.
+    fn unlink(&mut self, index: u32) {
+        let node = &self.nodes[index as usize];
+        match node.place {
+            Place::Slot { level, slot } => {
+                let position = node.position as usize;
+                let level = &mut self.levels[level];
+                let slots = &mut level.slots[slot];
+                slots.swap_remove(position);
+                if let Some(&moved) = slots.get(position) {
+                    self.nodes[moved as usize].position = position as u64;
+                }
+                if slots.is_empty() {
+                    level.occupied &= !(1 << slot);
+                }
+            }
+            Place::Due => {
+                let position = (node.position - self.due_head) as usize;
+                self.due.swap_remove_back(position);
+                if let Some(&moved) = self.due.get(position) {
+                    self.nodes[moved as usize].position = self.due_head + position as u64;
+                }
+            }
+        }
+    }
+
+    /// Free node `index`, returning its item.
+    fn release(&mut self, index: u32) -> T {
+        let node = &mut self.nodes[index as usize];
+        node.generation = node.generation.wrapping_add(1);
+        self.free.push(index);
+        self.len -= 1;
+        node.item.take().expect("released timer node is occupied")
+    }
+
+    /// Find the earliest occupied slot after the current tick.
+    ///
+    /// Lower levels always fire before higher ones, so the first level with
+    /// an occupied slot past the current position holds the next event.
+    fn next_event(&self) -> Option<(usize, usize, u64)> {
+        for (index, level) in self.levels.iter().enumerate() {
+            let shift = index as u32 * SLOT_BITS;
+            let position = ((self.current >> shift) as usize) & (SLOTS - 1);
+            let ahead = level.occupied & u64::MAX.checked_shl(position as u32 + 1).unwrap_or(0);
+            if ahead == 0 {
+                continue;
+            }
+
+            let slot = ahead.trailing_zeros() as usize;
+            let base = self
+                .current
+                .checked_shr(shift + SLOT_BITS)
+                .map_or(0, |high| high << (shift + SLOT_BITS));
+            return Some((index, slot, base | ((slot as u64) << shift)));
+        }
+        None
+    }
+}
+
+/// Advance `state` and return the next pseudo-random number.
+fn splitmix64(state: &mut u64) -> u64 {
+    *state = state.wrapping_add(0x9E37_79B9_7F4A_7C15);
+    let mut z = *state;
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    z ^ (z >> 31)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn drain(wheel: &mut TimerWheel<u64>) -> Vec<u64> {
+        std::iter::from_fn(|| wheel.pop_due()).collect()
+    }
+
+    #[test]
+    fn test_fires_in_order_at_deadline() {
+        let mut wheel = TimerWheel::new(1000);
+        for deadline in [1005, 1001, 1000 + 64 * 64 + 3, 1070, 995] {
+            wheel.insert(deadline, deadline);
+        }
+        assert_eq!(drain(&mut wheel), vec![995]);
+
+        wheel.advance(1004);
+        assert_eq!(drain(&mut wheel), vec![1001]);
+
+        wheel.advance(1070);
+        assert_eq!(drain(&mut wheel), vec![1005, 1070]);
+
+        wheel.advance(1000 + 64 * 64 + 2);
+        assert!(drain(&mut wheel).is_empty());
+
+        wheel.advance(u64::from(u32::MAX));
+        assert_eq!(drain(&mut wheel), vec![1000 + 64 * 64 + 3]);
+        assert!(wheel.pop_due().is_none());
+    }
+
+    #[test]
+    fn test_matches_naive_schedule() {
+        let mut wheel = TimerWheel::new(0);
+        let deadlines: Vec<u64> = (0..2000u64).map(|i| (i * 7919) % 300_000).collect();
+        for &deadline in &deadlines {
+            wheel.insert(deadline, deadline);
+        }
+
+        let mut fired = Vec::new();
+        for now in (0..=300_000).step_by(977) {
+            wheel.advance(now);
+            for deadline in drain(&mut wheel) {
+                assert!(deadline <= now);
+                assert!(deadline + 977 > now);
+                fired.push(deadline);
+            }
+        }
+        wheel.advance(300_000);
+        fired.extend(drain(&mut wheel));
+
+        let mut expected = deadlines;
+        expected.sort_unstable();
+        fired.sort_unstable();
+        assert_eq!(fired, expected);
+    }
+
+    #[test]
+    fn test_clear() {
+        let mut wheel = TimerWheel::new(0);
+        wheel.insert(10, 1);
+        wheel.insert(0, 2);
+        wheel.clear();
+        wheel.advance(100);
+        assert!(wheel.pop_due().is_none());
+        assert_eq!(wheel.len(), 0);
+    }
+
+    #[test]
+    fn test_cancel() {
+        let mut wheel = TimerWheel::new(0);
+        let ids: Vec<TimerId> = (0..10u64).map(|i| wheel.insert(i * 100, i)).collect();
+        let due = wheel.insert(0, 99);
+        let _ = wheel.insert(0, 98);
+
+        for &i in &[1, 4, 5, 9] {
+            assert_eq!(wheel.cancel(ids[i]), Some(i as u64));
+        }
+        assert_eq!(wheel.cancel(ids[4]), None, "already cancelled");
+        assert_eq!(wheel.cancel(due), Some(99));
+        assert_eq!(wheel.len(), 7);
+
+        // Fired items can't be cancelled, even once their node is reused
+        wheel.advance(1000);
+        assert_eq!(drain(&mut wheel), vec![0, 98, 2, 3, 6, 7, 8]);
+        let reused = wheel.insert(2000, 7);
+        assert_eq!(wheel.cancel(ids[7]), None);
+        assert_eq!(wheel.cancel(reused), Some(7));
+        assert_eq!(wheel.len(), 0);
+    }
+
+    #[test]
+    fn test_sample() {
+        let mut wheel = TimerWheel::new(0);
+        let ids: Vec<TimerId> = (0..100u64).map(|i| wheel.insert(i, i)).collect();
+        for &id in &ids[..95] {
+            wheel.cancel(id);
+        }
+
+        // Small wheels return every item
+        let mut all: Vec<u64> = wheel.sample(1, 8).into_iter().copied().collect();
+        all.sort_unstable();
+        assert_eq!(all, vec![95, 96, 97, 98, 99]);
+
+        for i in 0..100u64 {
+            wheel.insert(1000 + i, 1000 + i);
+        }
+        let sample = wheel.sample(7, 8);
+        assert_eq!(sample.len(), 8);
+        assert_ne!(sample, wheel.sample(8, 8));
+    }
+}
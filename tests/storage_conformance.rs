@@ -0,0 +1,57 @@
+//! Storage conformance checks against every in-tree backend.
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use skp_ratelimit::storage::conformance;
+#[cfg(feature = "memory")]
+use skp_ratelimit::storage::{GcConfig, MemoryStorage};
+
+#[cfg(feature = "memory")]
+#[tokio::test(flavor = "multi_thread")]
+async fn test_memory_storage_conforms() {
+    conformance::run_all(Arc::new(MemoryStorage::new())).await;
+}
+
+#[cfg(feature = "memory")]
+#[tokio::test(flavor = "multi_thread")]
+async fn test_bounded_memory_storage_conforms() {
+    let config = GcConfig::on_requests(16)
+        .with_max_entries(10_000)
+        .with_max_age(Duration::ZERO);
+    conformance::run_all(Arc::new(MemoryStorage::with_gc(config))).await;
+}
+
+#[cfg(feature = "memory")]
+#[tokio::test(flavor = "multi_thread")]
+async fn test_boxed_storage_conforms() {
+    conformance::run_all(Arc::new(Box::new(MemoryStorage::new()))).await;
+}
+
+#[cfg(feature = "redb")]
+#[tokio::test(flavor = "multi_thread")]
+async fn test_redb_storage_conforms() {
+    use skp_ratelimit::storage::{RedbConfig, RedbStorage};
+
+    let path = std::env::temp_dir().join(format!("skp-ratelimit-conformance-{}.redb", std::process::id()));
+    let _ = std::fs::remove_file(&path);
+
+    let config = RedbConfig::new(&path).with_durable_commits(false);
+    conformance::run_all(Arc::new(RedbStorage::new(config).unwrap())).await;
+
+    let _ = std::fs::remove_file(&path);
+}
+
+/// Requires a Redis server at `REDIS_URL` (default `redis://localhost:6379`).
+#[cfg(feature = "redis")]
+#[tokio::test(flavor = "multi_thread")]
+#[ignore]
+async fn test_redis_storage_conforms() {
+    use skp_ratelimit::storage::{EntryCodec, RedisConfig, RedisStorage};
+
+    let url = std::env::var("REDIS_URL").unwrap_or_else(|_| "redis://localhost:6379".into());
+    for codec in [EntryCodec::Json, EntryCodec::Binary] {
+        let config = RedisConfig::new(url.clone()).with_codec(codec);
+        conformance::run_all(Arc::new(RedisStorage::new(config).await.unwrap())).await;
+    }
+}